@@ -1,4 +1,4 @@
-from .api import evaluate, wrap, unwrap, get_config, create_webview, display_html, debugger_message, start_debugging
+from .api import evaluate, wrap, unwrap, get_config, create_webview, display_html, debugger_message, start_debugging, register_frame_filter
 from .value import Value
 
 def __lldb_init_module(debugger, internal_dict):  # pyright: ignore
@@ -1,7 +1,7 @@
-from lldb import SBValue
+from lldb import SBValue, SBFrame
 import warnings
 import __main__
-from typing import Any, Optional, Union, Dict
+from typing import Any, Callable, Optional, Union, Dict
 
 from . import interface
 from .value import Value
@@ -75,6 +75,18 @@ def create_webview(html: Optional[str] = None, title: Optional[str] = None, view
     return webview
 
 
+def register_frame_filter(filter: Callable[[SBFrame, str], Optional[str]]):
+    '''Register a callback that may rewrite function names displayed in the call stack view.
+        filter: A callable receiving lldb.SBFrame and the current frame name.  It should return the new name,
+                or None to leave the name unchanged.  Filters are applied in the order of registration.
+    '''
+    debugger = interface.current_debugger()
+    inst_dict = interface.get_instance_dict(debugger)
+    inst_dict.setdefault('frame_filters', []).append(filter)
+    # Let the adapter know that frame names need to be passed through the filters.
+    interface.fire_event(debugger.GetID(), dict(type='FrameFilterRegistered'))
+
+
 def debugger_message(output: str, category: str = 'console'):
     debugger_id = interface.current_debugger().GetID()
     interface.fire_event(debugger_id, dict(type='DebuggerMessage', output=output, category=category))
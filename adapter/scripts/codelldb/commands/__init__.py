@@ -1,7 +1,9 @@
 from .debug_info import DebugInfoCommand
 from .nofail import NoFailCommand
+from .parallel_stacks import ParallelStacksCommand
 
 def register(debugger):
     debugger.HandleCommand('script import codelldb')
     debugger.HandleCommand('command script add -c codelldb.commands.DebugInfoCommand debug_info')
     debugger.HandleCommand('command script add -c codelldb.commands.NoFailCommand nofail')
+    debugger.HandleCommand('command script add -c codelldb.commands.ParallelStacksCommand parallel_stacks')
@@ -0,0 +1,56 @@
+import lldb
+import argparse
+import shlex
+
+
+class ParallelStacksCommand:
+    '''Prints call stacks of all threads merged into a tree.'''
+
+    def __init__(self, debugger, internal_dict):
+        self.parser = argparse.ArgumentParser('parallel_stacks')
+        self.parser.add_argument('--depth', type=int, default=256,
+                                 help='Maximum number of frames to unwind per thread.')
+
+    def __call__(self, debugger, command, exe_ctx, result):
+        try:
+            args = self.parser.parse_args(shlex.split(command))
+        except SystemExit:
+            self.parser.print_help(result)
+            result.flush()
+            return
+        process = exe_ctx.process
+        if not process.IsValid():
+            result.SetError('No process.')
+            return
+
+        # Mirrors the tree built by the adapter's _parallelStacks request, so that thread ids and frame names
+        # match the ones displayed in the Parallel Stacks view.
+        # Each node is a pair of (thread ids, {(function, pc): callee node}).
+        roots = {}
+        for thread in process.threads:
+            tid = thread.GetThreadID()
+            keys = []
+            # Avoid GetNumFrames(), which unwinds the entire stack.
+            for i in range(args.depth):
+                frame = thread.GetFrameAtIndex(i)
+                if not frame.IsValid():
+                    break
+                name = frame.GetFunctionName() or '{:X}'.format(frame.GetPCAddress().GetFileAddress())
+                keys.append((name, frame.GetPC()))
+            else:
+                if thread.GetFrameAtIndex(args.depth).IsValid():
+                    keys.append(('...', 0))
+            node = (None, roots)
+            for key in reversed(keys):
+                node = node[1].setdefault(key, ([], {}))
+                node[0].append(tid)
+
+        def dump(children, indent):
+            for (name, pc), (tids, grandchildren) in children.items():
+                threads = ', '.join(str(tid) for tid in tids)
+                result.write('{}{} [{} thread{}: {}]\n'.format(
+                    '  ' * indent, name, len(tids), '' if len(tids) == 1 else 's', threads))
+                dump(grandchildren, indent + 1)
+
+        dump(roots, 0)
+        result.flush()
@@ -4,6 +4,7 @@ import os
 from typing import Optional
 import lldb
 import logging
+import threading
 import ctypes
 import json
 from ctypes import (CFUNCTYPE, POINTER, py_object, sizeof, byref, memmove, cast,
@@ -129,7 +130,8 @@ def initialize(init_callback_addr, callback_context, send_message_addr, log_leve
         handle_message,
         compile_code,
         evaluate_as_sbvalue,
-        evaluate_as_bool
+        evaluate_as_bool,
+        filter_frame_name
     ]
     ptr_arr = (c_void_p * len(pointers))(*[cast(p, c_void_p) for p in pointers])
     init_callback = CFUNCTYPE(None, c_void_p, POINTER(c_void_p), c_size_t)(init_callback_addr)
@@ -244,6 +246,26 @@ def handle_message(body_ptr, body_len):
     return True
 
 
+# Receives the new frame name.
+NameCallback = CFUNCTYPE(None, c_void_p, c_char_p, c_size_t)
+
+
+@CFUNCTYPE(c_bool, RustSBExecutionContext, NameCallback, c_void_p)
+def filter_frame_name(exec_context, name_callback, callback_context):
+    '''Apply registered frame filters to the name of the frame, passing the new name to name_callback if it was changed'''
+    try:
+        exec_context = into_swig_wrapper(exec_context, RustSBExecutionContext)
+        with evaluation_lock:
+            name = apply_frame_filters(exec_context.GetFrame())
+        if name is not None:
+            name = str_to_bytes(name)
+            name_callback(callback_context, name, len(name))
+        return True
+    except Exception as err:
+        log.exception('filter_frame_name failed')
+        return False
+
+
 @CFUNCTYPE(None, py_object)
 def drop_pyobject(obj):
     decref(obj)
@@ -331,45 +353,50 @@ def bytes_to_str(b):
 
 current_exec_context: Optional[lldb.SBExecutionContext] = None
 
+# Evaluation sets process-wide state (`current_exec_context`, the `lldb.frame`/`lldb.thread`/... convenience variables
+# and `sys.stdout`), so concurrent debug sessions, which share this interpreter, must take turns.  The lock is re-entrant,
+# because evaluation may call back into Python on the same thread (e.g. via a synthetic children provider).
+evaluation_lock = threading.RLock()
+
 
 def evaluate_in_context(code, exec_context, eval_context):
     global current_exec_context
-    current_exec_context = exec_context
-    debugger = exec_context.GetTarget().GetDebugger()
-    prev_stdout = sys.stdout
-    sess_stdout = session_stdouts.get(debugger.GetID())
-    if sess_stdout:
-        sys.stdout = sess_stdout
-    try:
-        if eval_context == 2:  # EvalContext::SimpleExpression
-            frame = exec_context.GetFrame()
-            eval_globals = {}
-            # Helpers used by the expression evaluator
-            eval_globals['__eval'] = lambda expr: nat_eval(frame, expr)
-            eval_globals['Value'] = Value
-            return eval(code, eval_globals, {})
-        else:
-            lldb.frame = exec_context.GetFrame()
-            lldb.thread = exec_context.GetThread()
-            lldb.process = exec_context.GetProcess()
-            lldb.target = exec_context.GetTarget()
-            lldb.debugger = debugger
-            if eval_context == 1:  # EvalContext::PythonExpression
+    with evaluation_lock:
+        # Saved for restoring on exit, in case this is a nested evaluation.
+        prev_exec_context = current_exec_context
+        prev_globals = (lldb.frame, lldb.process, lldb.thread, lldb.target, lldb.debugger)
+        current_exec_context = exec_context
+        debugger = exec_context.GetTarget().GetDebugger()
+        prev_stdout = sys.stdout
+        sess_stdout = session_stdouts.get(debugger.GetID())
+        if sess_stdout:
+            sys.stdout = sess_stdout
+        try:
+            if eval_context == 2:  # EvalContext::SimpleExpression
                 frame = exec_context.GetFrame()
-                eval_globals = get_instance_dict(debugger)
+                eval_globals = {}
+                # Helpers used by the expression evaluator
                 eval_globals['__eval'] = lambda expr: nat_eval(frame, expr)
-                return eval(code, eval_globals)
-            else:  # EvalContext::Statement
-                eval_globals = get_instance_dict(debugger)
-                return eval(code, eval_globals)
-    finally:
-        sys.stdout = prev_stdout
-        lldb.frame = None
-        lldb.process = None
-        lldb.thread = None
-        lldb.target = None
-        lldb.debugger = None
-        current_exec_context = None
+                eval_globals['Value'] = Value
+                return eval(code, eval_globals, {})
+            else:
+                lldb.frame = exec_context.GetFrame()
+                lldb.thread = exec_context.GetThread()
+                lldb.process = exec_context.GetProcess()
+                lldb.target = exec_context.GetTarget()
+                lldb.debugger = debugger
+                if eval_context == 1:  # EvalContext::PythonExpression
+                    frame = exec_context.GetFrame()
+                    eval_globals = get_instance_dict(debugger)
+                    eval_globals['__eval'] = lambda expr: nat_eval(frame, expr)
+                    return eval(code, eval_globals)
+                else:  # EvalContext::Statement
+                    eval_globals = get_instance_dict(debugger)
+                    return eval(code, eval_globals)
+        finally:
+            sys.stdout = prev_stdout
+            lldb.frame, lldb.process, lldb.thread, lldb.target, lldb.debugger = prev_globals
+            current_exec_context = prev_exec_context
 
 
 def current_debugger() -> lldb.SBDebugger:
@@ -419,5 +446,19 @@ def nat_eval(sbframe, expr):
     return Value(val)
 
 
+def apply_frame_filters(frame: lldb.SBFrame) -> Optional[str]:
+    '''Apply registered frame filters to the frame name, returns None if there was no change.'''
+    filters = get_instance_dict(frame.GetThread().GetProcess().GetTarget().GetDebugger()).get('frame_filters')
+    if not filters:
+        return None
+    name = frame.GetFunctionName() or ''
+    new_name = name
+    for filter in filters:
+        result = filter(frame, new_name)
+        if result is not None:
+            new_name = result
+    return new_name if new_name != name else None
+
+
 def get_instance_dict(debugger: lldb.SBDebugger) -> dict:
     return getattr(__main__, debugger.GetInstanceName() + '_dict')
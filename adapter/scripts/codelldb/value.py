@@ -80,6 +80,9 @@ class Value(object):
 
     def __getattr__(self, name):
         child_sbvalue = self.__sbvalue.GetChildMemberWithName(name)
+        if not child_sbvalue.IsValid() and name.isdigit():
+            # Rust tuple fields are named `__0`, `__1`, etc in the debug info.
+            child_sbvalue = self.__sbvalue.GetChildMemberWithName('__' + name)
         if child_sbvalue and child_sbvalue.IsValid():
             return Value(child_sbvalue)
         raise AttributeError("Attribute '%s' is not defined" % name)
@@ -65,6 +65,7 @@ export class AdapterSettingsManager extends DisposableSubscriber {
             if (event.affectsConfiguration('lldb.displayFormat') ||
                 event.affectsConfiguration('lldb.showDisassembly') ||
                 event.affectsConfiguration('lldb.dereferencePointers') ||
+                event.affectsConfiguration('lldb.disassembly') ||
                 event.affectsConfiguration('lldb.suppressMissingSourceFiles') ||
                 event.affectsConfiguration('lldb.evaluationTimeout') ||
                 event.affectsConfiguration('lldb.consoleMode')) {
@@ -88,6 +89,14 @@ export class AdapterSettingsManager extends DisposableSubscriber {
             scriptConfig: config.get('script'),
             evaluateForHovers: config.get('evaluateForHovers'),
             commandCompletions: config.get('commandCompletions'),
+            disassembly: {
+                flavor: config.get('disassembly.flavor'),
+                showBytes: config.get('disassembly.showBytes'),
+                maxBytes: config.get('disassembly.maxBytes'),
+                uppercaseMnemonics: config.get('disassembly.uppercaseMnemonics'),
+                demangle: config.get('disassembly.demangle'),
+                interleaveSource: config.get('disassembly.interleaveSource'),
+            },
         };
         return settings;
     }
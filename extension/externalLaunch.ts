@@ -1,9 +1,9 @@
-import { LaunchEnvironment, LaunchRequestArguments, LaunchResponse } from 'codelldb';
+import { DebuggeeExit, LaunchEnvironment, LaunchRequestArguments, LaunchResponse } from 'codelldb';
 import * as crypto from 'crypto';
 import * as net from 'net';
 import * as querystring from 'querystring';
 import stringArgv from 'string-argv';
-import { debug, DebugConfiguration, EventEmitter, tasks, Uri, UriHandler, window, workspace } from "vscode";
+import { debug, DebugConfiguration, Disposable, EventEmitter, tasks, Uri, UriHandler, window, workspace } from "vscode";
 import YAML from 'yaml';
 import { output } from './logging';
 import { Dict } from "./novsc/commonTypes";
@@ -73,13 +73,16 @@ export class UriLaunchServer implements UriHandler {
     }
 }
 
+// `write` may be used to send interim messages before the final response.
+type RequestProcessor = (request: string, write: (chunk: string) => void) => string | Promise<string>;
+
 export class RpcServer {
     inner: net.Server;
-    processRequest: (request: string) => string | Promise<string>;
+    processRequest: RequestProcessor;
     errorEmitter = new EventEmitter<Error>();
     readonly onError = this.errorEmitter.event;
 
-    constructor(processRequest: (request: string) => string | Promise<string>) {
+    constructor(processRequest: RequestProcessor) {
         this.processRequest = processRequest;
         this.inner = net.createServer({ allowHalfOpen: true });
         this.inner.on('error', err => this.errorEmitter.fire(err));
@@ -87,7 +90,7 @@ export class RpcServer {
             let request = '';
             socket.on('data', chunk => request += chunk);
             socket.on('end', () => {
-                let response = this.processRequest(request);
+                let response = this.processRequest(request, chunk => socket.write(chunk));
                 if (response instanceof Promise) {
                     response.then(value => socket.end(value));
                 } else {
@@ -112,11 +115,13 @@ export class RpcLaunchServer extends RpcServer {
     token?: string;
 
     constructor(options: { token?: string }) {
-        super(request => this.onRequest(request).then(response => JSON.stringify(response)));
+        super((request, write) => this.onRequest(request, write).then(response => JSON.stringify(response)));
         this.token = options.token;
     }
 
-    async onRequest(rawRequest: string): Promise<LaunchResponse> {
+    // If the launcher has asked to wait for the debuggee, LaunchResponse is sent as soon as the debug session starts,
+    // and the final response is DebuggeeExit.
+    async onRequest(rawRequest: string, write: (chunk: string) => void): Promise<LaunchResponse | DebuggeeExit> {
         let request = YAML.parse(rawRequest);
 
         let debugConfig: DebugConfiguration & LaunchRequestArguments = {
@@ -126,6 +131,7 @@ export class RpcLaunchServer extends RpcServer {
             env: {},
             waitEndOfSession: false // Whether to wait for the end of the debug session before responding
         };
+        let waitDebuggee = false;
 
         if (request.type == 'LaunchEnvironment') {
             let launchEnv = request as LaunchEnvironment;
@@ -137,6 +143,10 @@ export class RpcLaunchServer extends RpcServer {
             Object.assign(debugConfig, launchConfig);
             debugConfig.env = Object.assign(debugConfig.env as any, launchEnv.env, launchConfig.env);
             debugConfig.relativePathBase = launchEnv.cwd;
+            waitDebuggee = launchEnv.wait ?? false;
+            // Waiting for the debuggee requires tracking the debug session, regardless of the launch config.
+            if (waitDebuggee)
+                debugConfig.waitEndOfSession = true;
         } else { // Naked DebugConfiguration
             Object.assign(debugConfig, request);
         }
@@ -148,25 +158,44 @@ export class RpcLaunchServer extends RpcServer {
             delete debugConfig.token;
         }
 
+        let debuggeeExit: DebuggeeExit = { status: 'unknown' };
+        let exitSub: Disposable | undefined;
         try {
             let endSessionAsync = undefined;
             if (debugConfig.waitEndOfSession) {
-                endSessionAsync = waitEndOfDebugSession(debugConfig);
+                let onStart: (() => void) | undefined;
+                if (waitDebuggee) {
+                    onStart = () => write(JSON.stringify({ success: true }));
+                }
+                endSessionAsync = waitEndOfDebugSession(debugConfig, 10, onStart);
+            }
+            if (waitDebuggee) {
+                exitSub = debug.onDidReceiveDebugSessionCustomEvent(e => {
+                    if (e.event == '_debuggeeExited' &&
+                        e.session.configuration._codelldbSessionId == debugConfig._codelldbSessionId) {
+                        debuggeeExit = e.body;
+                    }
+                });
             }
             if (!await debug.startDebugging(undefined, debugConfig))
                 throw Error('Could not start debugging');
             if (!await endSessionAsync)
                 throw Error('Debug session did not start within the allotted time');
-            return { success: true };
+            return waitDebuggee ? debuggeeExit : { success: true };
         } catch (err: any) {
             return { success: false, message: err.toString() };
+        } finally {
+            exitSub?.dispose();
         }
     };
 }
 
 // Return a future that will be resolved upon termination of the debug session (result=true).
 // The future will also be resolved after `timeout` seconds (result=false).
-export function waitEndOfDebugSession(debugConfig: DebugConfiguration, timeout: number = 10): Promise<boolean> {
+// `onStart` is invoked when the debug session starts.
+export function waitEndOfDebugSession(
+    debugConfig: DebugConfiguration, timeout: number = 10, onStart?: () => void
+): Promise<boolean> {
     return new Promise<boolean>(resolve => {
         let sessionId = crypto.randomBytes(16).toString('base64');
         debugConfig._codelldbSessionId = sessionId;
@@ -176,6 +205,7 @@ export function waitEndOfDebugSession(debugConfig: DebugConfiguration, timeout:
             if (session.configuration._codelldbSessionId == sessionId) {
                 startSub.dispose();
                 clearTimeout(failedLaunchCleanup); // Disarm the cleanup timer.
+                onStart?.();
                 let endSub = debug.onDidTerminateDebugSession(session => {
                     if (session.configuration._codelldbSessionId == sessionId) {
                         endSub.dispose();
@@ -177,6 +177,8 @@ class Extension implements DebugAdapterDescriptorFactory {
                 if (typeof (address) == 'object') {
                     let ainfo = address as AddressInfo;
                     address = `${ainfo.address}:${ainfo.port}`;
+                } else {
+                    address = `unix:${address}`;
                 }
                 await async.fs.writeFile(this.rpcFile, address);
 
@@ -7,22 +7,24 @@ use serde_derive::*;
 pub use codelldb_types::*;
 
 pub use crate::dap::{
-    Breakpoint, BreakpointEventBody, CancelArguments, Capabilities, CapabilitiesEventBody, CompletionItem,
-    CompletionsArguments, CompletionsResponseBody, ContinueArguments, ContinueResponseBody, ContinuedEventBody,
-    DataBreakpoint, DataBreakpointAccessType, DataBreakpointInfoArguments, DataBreakpointInfoResponseBody,
-    DisassembleArguments, DisassembleResponseBody, DisassembledInstruction, DisassembledInstructionPresentationHint,
-    DisconnectArguments, EvaluateArguments, EvaluateResponseBody, ExceptionBreakMode, ExceptionBreakpointsFilter,
-    ExceptionInfoArguments, ExceptionInfoResponseBody, ExitedEventBody, GotoArguments, GotoTarget,
-    GotoTargetsArguments, GotoTargetsResponseBody, InitializeRequestArguments, InstructionBreakpoint, InvalidatedAreas,
-    InvalidatedEventBody, Module, ModuleEventBody, ModuleEventBodyReason, ModuleId, ModulesArguments,
-    ModulesResponseBody, NextArguments, OutputEventBody, PauseArguments, ReadMemoryArguments, ReadMemoryResponseBody,
-    RestartFrameArguments, ReverseContinueArguments, RunInTerminalRequestArguments, RunInTerminalRequestArgumentsKind,
+    Breakpoint, BreakpointEventBody, BreakpointLocation, BreakpointLocationsArguments, BreakpointLocationsResponseBody,
+    CancelArguments, Capabilities, CapabilitiesEventBody, CompletionItem, CompletionsArguments,
+    CompletionsResponseBody, ContinueArguments, ContinueResponseBody, ContinuedEventBody, DataBreakpoint,
+    DataBreakpointAccessType, DataBreakpointInfoArguments, DataBreakpointInfoResponseBody, DisassembleArguments,
+    DisassembleResponseBody, DisassembledInstruction, DisassembledInstructionPresentationHint, DisconnectArguments,
+    EvaluateArguments, EvaluateResponseBody, ExceptionBreakMode, ExceptionBreakpointsFilter, ExceptionInfoArguments,
+    ExceptionInfoResponseBody, ExitedEventBody, GotoArguments, GotoTarget, GotoTargetsArguments,
+    GotoTargetsResponseBody, InitializeRequestArguments, InstructionBreakpoint, InvalidatedAreas, InvalidatedEventBody,
+    MemoryEventBody, Module, ModuleEventBody, ModuleEventBodyReason, ModuleId, ModulesArguments, ModulesResponseBody,
+    NextArguments, OutputEventBody, PauseArguments, ProgressEndEventBody, ProgressStartEventBody,
+    ProgressUpdateEventBody, ReadMemoryArguments, ReadMemoryResponseBody, RestartFrameArguments,
+    ReverseContinueArguments, RunInTerminalRequestArguments, RunInTerminalRequestArgumentsKind,
     RunInTerminalResponseBody, Scope, ScopesArguments, ScopesResponseBody, SetBreakpointsArguments,
     SetBreakpointsResponseBody, SetDataBreakpointsArguments, SetDataBreakpointsResponseBody,
     SetExceptionBreakpointsArguments, SetExceptionBreakpointsResponseBody, SetFunctionBreakpointsArguments,
     SetInstructionBreakpointsArguments, SetInstructionBreakpointsResponseBody, SetVariableArguments,
-    SetVariableResponseBody, Source, SourceArguments, SourceBreakpoint, SourceResponseBody, StackFrame,
-    StackFrameModuleId, StackFramePresentationHint, StackTraceArguments, StackTraceResponseBody,
+    SetVariableResponseBody, Source, SourceArguments, SourceBreakpoint, SourcePresentationHint, SourceResponseBody,
+    StackFrame, StackFrameModuleId, StackFramePresentationHint, StackTraceArguments, StackTraceResponseBody,
     StartDebuggingRequestArguments, StartDebuggingRequestArgumentsRequest, StepBackArguments, StepInArguments,
     StepInTarget, StepInTargetsArguments, StepInTargetsResponseBody, StepOutArguments, SteppingGranularity,
     StoppedEventBody, TerminateArguments, TerminatedEventBody, Thread, ThreadEventBody, ThreadsResponseBody, Variable,
@@ -79,6 +81,7 @@ pub enum RequestArguments {
     attach(Either<AttachRequestArguments, serde_json::Value>),
     restart(Either<RestartRequestArguments, serde_json::Value>),
     setBreakpoints(SetBreakpointsArguments),
+    breakpointLocations(BreakpointLocationsArguments),
     setInstructionBreakpoints(SetInstructionBreakpointsArguments),
     setFunctionBreakpoints(SetFunctionBreakpointsArguments),
     setExceptionBreakpoints(SetExceptionBreakpointsArguments),
@@ -121,6 +124,17 @@ pub enum RequestArguments {
     _excludeCaller(ExcludeCallerRequest),
     _setExcludedCallers(SetExcludedCallersRequest),
     _pythonMessage(serde_json::Value),
+    _findMemory(FindMemoryRequest),
+    _snapshotMemory(SnapshotMemoryRequest),
+    _diffMemory(DiffMemoryRequest),
+    _run(Option<NoArguments>),
+    _asyncStack(AsyncStackRequest),
+    _asyncTasks(Option<NoArguments>),
+    _parallelStacks(Option<ParallelStacksRequest>),
+    _lockAnalysis(Option<NoArguments>),
+    _freezeThread(FreezeThreadRequest),
+    _thawThread(FreezeThreadRequest),
+    _threadInfo(Option<NoArguments>),
     #[serde(other)]
     unknown,
 }
@@ -134,6 +148,7 @@ pub enum ResponseBody {
     attach,
     restart,
     setBreakpoints(SetBreakpointsResponseBody),
+    breakpointLocations(BreakpointLocationsResponseBody),
     setInstructionBreakpoints(SetInstructionBreakpointsResponseBody),
     setFunctionBreakpoints(SetBreakpointsResponseBody),
     setExceptionBreakpoints(SetExceptionBreakpointsResponseBody),
@@ -176,6 +191,17 @@ pub enum ResponseBody {
     _excludeCaller(ExcludeCallerResponse),
     _setExcludedCallers,
     _pythonMessage,
+    _findMemory(FindMemoryResponse),
+    _snapshotMemory(SnapshotMemoryResponse),
+    _diffMemory(DiffMemoryResponse),
+    _run,
+    _asyncStack(AsyncStackResponse),
+    _asyncTasks(AsyncTasksResponse),
+    _parallelStacks(ParallelStacksResponse),
+    _lockAnalysis(LockAnalysisResponse),
+    _freezeThread,
+    _thawThread,
+    _threadInfo(ThreadInfoResponse),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -191,9 +217,14 @@ pub enum EventBody {
     terminated(TerminatedEventBody),
     thread(ThreadEventBody),
     invalidated(InvalidatedEventBody),
+    memory(MemoryEventBody),
     stopped(StoppedEventBody),
+    progressStart(ProgressStartEventBody),
+    progressUpdate(ProgressUpdateEventBody),
+    progressEnd(ProgressEndEventBody),
     // Custom
     _pythonMessage(serde_json::Value),
+    _debuggeeExited(DebuggeeExit),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -2,12 +2,11 @@ use std::env;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::net;
-use std::str::FromStr;
 use std::time::Duration;
 
 use clap::Parser;
 use codelldb_types::TerminalId;
-use codelldb_types::{JsonMap, LaunchEnvironment, LaunchResponse};
+use codelldb_types::{DebuggeeExit, JsonMap, LaunchEnvironment, LaunchResponse};
 use log::{debug, trace};
 use socket2::{SockRef, TcpKeepalive};
 
@@ -21,6 +20,10 @@ struct Args {
     config: Option<String>,
     #[arg(long)]
     clear_screen: bool,
+    #[arg(long)]
+    auth_token: Option<String>,
+    #[arg(long)]
+    wait: bool,
     #[arg(trailing_var_arg = true)]
     cmd: Vec<String>,
 }
@@ -67,24 +70,25 @@ fn main() -> Result<(), Error> {
         env: env,
         terminal_id: terminal_id,
         config: config,
+        auth_token: args.auth_token,
+        wait: if args.wait { Some(true) } else { None },
     };
     trace!("Request: {:?}", request);
 
-    let address = net::SocketAddr::from_str(&address)?;
-    let mut stream = net::TcpStream::connect(address)?;
-    debug!("Connected to {:?}", stream.peer_addr().unwrap());
-
-    // Windows may abrutly terminate an idle TCP connection after approximately 2 minutes.
-    SockRef::from(&stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(Duration::from_secs(30)))?;
+    let mut stream = connect(&address)?;
 
     serde_json::to_writer(&mut stream, &request)?;
     stream.flush()?;
-    stream.shutdown(net::Shutdown::Write)?;
+    stream.shutdown_write()?;
 
     if args.clear_screen {
         let _ = clearscreen::ClearScreen::default().clear();
     }
 
+    if args.wait {
+        return wait_for_debuggee(stream);
+    }
+
     let mut response = String::new();
     stream.read_to_string(&mut response)?;
     debug!("Response: {}", response);
@@ -104,6 +108,80 @@ fn main() -> Result<(), Error> {
     }
 }
 
+// Reads LaunchResponse, followed by DebuggeeExit, and exits with the debuggee's status.
+fn wait_for_debuggee(stream: Box<dyn Stream>) -> Result<(), Error> {
+    let mut messages = serde_json::Deserializer::from_reader(stream).into_iter::<serde_json::Value>();
+
+    let response: LaunchResponse = match messages.next() {
+        Some(message) => serde_json::from_value(message?)?,
+        None => return Err("Connection closed before the launch response.".into()),
+    };
+    debug!("Response: {:?}", response);
+    if !response.success {
+        purge_stdin();
+        return Err(response.message.unwrap_or("Failed".into()).into());
+    }
+
+    let debuggee_exit: DebuggeeExit = match messages.next() {
+        Some(message) => serde_json::from_value(message?)?,
+        None => DebuggeeExit::Unknown,
+    };
+    debug!("Debuggee exit: {:?}", debuggee_exit);
+    purge_stdin();
+
+    match exit_status(&debuggee_exit) {
+        Some(status) => std::process::exit(status),
+        None => Err("The debug session has ended before the debuggee exited.".into()),
+    }
+}
+
+// Converts debuggee exit information into a status code, following the shell convention for signals.
+fn exit_status(debuggee_exit: &DebuggeeExit) -> Option<i32> {
+    match debuggee_exit {
+        DebuggeeExit::Exited { exit_code } => Some(*exit_code),
+        DebuggeeExit::Signaled { signal } => Some(128 + signal),
+        DebuggeeExit::Unknown => None,
+    }
+}
+
+trait Stream: Read + Write {
+    fn shutdown_write(&self) -> std::io::Result<()>;
+}
+
+impl Stream for net::TcpStream {
+    fn shutdown_write(&self) -> std::io::Result<()> {
+        self.shutdown(net::Shutdown::Write)
+    }
+}
+
+#[cfg(unix)]
+impl Stream for std::os::unix::net::UnixStream {
+    fn shutdown_write(&self) -> std::io::Result<()> {
+        self.shutdown(net::Shutdown::Write)
+    }
+}
+
+// The address is either `host:port`, or `unix:<path>` of a Unix domain socket.
+fn connect(address: &str) -> Result<Box<dyn Stream>, Error> {
+    if let Some(path) = address.strip_prefix("unix:") {
+        #[cfg(unix)]
+        {
+            let stream = std::os::unix::net::UnixStream::connect(path)?;
+            debug!("Connected to {}", path);
+            return Ok(Box::new(stream));
+        }
+        #[cfg(not(unix))]
+        return Err(format!("Unix domain sockets are not supported on this platform: {}", path).into());
+    }
+
+    let stream = net::TcpStream::connect(address)?;
+    debug!("Connected to {:?}", stream.peer_addr().unwrap());
+
+    // Windows may abrutly terminate an idle TCP connection after approximately 2 minutes.
+    SockRef::from(&stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(Duration::from_secs(30)))?;
+    Ok(Box::new(stream))
+}
+
 #[cfg(unix)]
 fn purge_stdin() {
     use std::os::unix::io::AsRawFd;
@@ -172,4 +250,17 @@ fn test_args() {
     assert_eq!(args.connect.as_deref(), Some("127.0.0.1:12345"));
     assert_eq!(args.clear_screen, false);
     assert_eq!(args.cmd, ["--config", "-arg", "val"]);
+
+    let args = Args::parse_from(["<launch>", "--wait", "command", "--wait"]);
+    assert_eq!(args.wait, true);
+    assert_eq!(args.cmd, ["command", "--wait"]);
+}
+
+#[test]
+fn test_exit_status() {
+    assert_eq!(exit_status(&DebuggeeExit::Exited { exit_code: 0 }), Some(0));
+    assert_eq!(exit_status(&DebuggeeExit::Exited { exit_code: 3 }), Some(3));
+    assert_eq!(exit_status(&DebuggeeExit::Signaled { signal: 9 }), Some(137));
+    assert_eq!(exit_status(&DebuggeeExit::Signaled { signal: 11 }), Some(139));
+    assert_eq!(exit_status(&DebuggeeExit::Unknown), None);
 }
@@ -130,7 +130,7 @@ pub struct ExcludedCaller {
     pub symbol: String,
 }
 
-#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
 #[schemars(deny_unknown_fields)]
 pub struct Source {
     pub path: String,
@@ -164,6 +164,314 @@ pub struct SymbolsResponse {
     pub symbols: Vec<Symbol>,
 }
 
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct FindMemoryRequest {
+    pub pattern: MemoryPattern,
+    /// Memory reference of the start of the search range.
+    pub start: Option<String>,
+    /// Memory reference of the end of the search range (exclusive).
+    pub end: Option<String>,
+    /// Only search memory regions of this kind.
+    pub region_kind: Option<MemoryRegionKind>,
+    /// Only search memory occupied by sections of this module (file name or full path).
+    pub module: Option<String>,
+    pub max_results: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum MemoryPattern {
+    /// Hex bytes, optionally separated by spaces; `??` matches any byte.  For example, "48 8B ?? 10".
+    Bytes { bytes: String },
+    String {
+        value: String,
+        encoding: Option<StringEncoding>,
+    },
+    Integer {
+        /// Decimal or hex (0x-prefixed) number.
+        value: String,
+        /// Size in bytes: 1, 2, 4 or 8.
+        size: u32,
+        /// Defaults to the target's byte order.
+        big_endian: Option<bool>,
+    },
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum StringEncoding {
+    Utf8,
+    Utf16,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum MemoryRegionKind {
+    Writable,
+    ReadOnly,
+    Executable,
+    Heap,
+    Stack,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct FindMemoryResponse {
+    pub matches: Vec<MemoryMatch>,
+    /// True if the search was stopped upon reaching `maxResults`.
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct MemoryMatch {
+    /// Memory reference usable with the `readMemory` request.
+    pub memory_reference: String,
+    /// Description of the containing memory region, e.g. "[heap] rw-".
+    pub region: String,
+    /// `symbol+offset` of the match, if any.
+    pub symbol: Option<String>,
+    pub module: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct SnapshotMemoryRequest {
+    /// Memory reference of the start of the range to capture.
+    pub memory_reference: Option<String>,
+    pub offset: Option<i64>,
+    /// Number of bytes to capture; required when `memoryReference` is specified.
+    pub count: Option<u64>,
+    /// Alternatively, an expression whose value is to be captured.
+    /// Format specifiers may be used to capture arrays, e.g. `ptr,[100]`.
+    pub expression: Option<String>,
+    /// Stack frame in the context of which `expression` is evaluated.
+    pub frame_id: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct SnapshotMemoryResponse {
+    /// Only a limited number of the most recently used snapshots is retained.
+    pub snapshot_id: u32,
+    pub memory_reference: String,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct DiffMemoryRequest {
+    pub snapshot_id: u32,
+    /// Snapshot to compare with; if omitted, the snapshot is compared with the current memory contents.
+    pub other_snapshot_id: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct DiffMemoryResponse {
+    pub changes: Vec<MemoryChange>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct MemoryChange {
+    /// Memory reference of the start of the changed range.
+    pub memory_reference: String,
+    pub count: u64,
+    /// Base64-encoded bytes in the first snapshot.
+    pub old_data: String,
+    /// Base64-encoded bytes in the second snapshot or in the current memory.
+    pub new_data: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct AsyncStackRequest {
+    pub thread_id: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct AsyncStackResponse {
+    /// Logical call stack, starting with the root future of the task.
+    pub frames: Vec<AsyncFrame>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct AsyncTasksResponse {
+    pub tasks: Vec<AsyncTask>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct AsyncTask {
+    /// Task id assigned by the executor.
+    pub id: Option<u64>,
+    /// Name of the task's root future.
+    pub name: String,
+    /// Chain of futures awaited by the task, starting with the root future.
+    pub frames: Vec<AsyncFrame>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct AsyncFrame {
+    /// Name of the async function or block, or the type name of a future that isn't one.
+    pub name: String,
+    /// State of an async function, e.g. "Suspend0" for the first await point, "Unresumed" or "Returned".
+    pub state: Option<String>,
+    pub source: Option<Source>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    /// Reference for retrieving fields of the future via the `variables` request.
+    /// For async functions, these are the variables live across the current await point.
+    pub variables_reference: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct ParallelStacksRequest {
+    /// Maximum number of frames to unwind per thread.  Defaults to 256.
+    pub max_depth: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct ParallelStacksResponse {
+    /// Call trees rooted at the outermost frames of thread stacks.
+    pub roots: Vec<StackNode>,
+    /// Threads whose stacks were deeper than `maxDepth`.  Their outermost frames are replaced with a "..." node.
+    pub truncated_thread_ids: Vec<i64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct StackNode {
+    /// Function name, or the address, if the function is unknown.
+    pub name: String,
+    /// Memory reference of the frame's program counter.
+    pub instruction_pointer_reference: String,
+    pub source: Option<Source>,
+    pub line: Option<u32>,
+    /// Threads whose stacks pass through this frame.
+    pub thread_ids: Vec<i64>,
+    /// Nodes of frames called from this one.
+    pub children: Vec<StackNode>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct LockAnalysisResponse {
+    /// Threads blocked in lock primitives.
+    pub waits: Vec<LockWait>,
+    /// Cycles in the wait-for graph.
+    pub deadlocks: Vec<Deadlock>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct LockWait {
+    pub thread_id: i64,
+    pub thread_name: Option<String>,
+    pub kind: LockKind,
+    /// The lock primitive function the thread is blocked in, e.g. `pthread_mutex_lock`.
+    pub function: String,
+    /// Memory reference of the lock object, if known.
+    pub lock_reference: Option<String>,
+    /// Thread holding the lock, if it could be determined.
+    pub owner_thread_id: Option<i64>,
+    /// The code that requested the lock.
+    pub caller: Option<CodeLocation>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum LockKind {
+    Mutex,
+    RwLock,
+    Condvar,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct CodeLocation {
+    pub function: String,
+    pub source: Option<Source>,
+    pub line: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct Deadlock {
+    /// Threads forming the cycle, each waiting for a lock held by the next one.
+    pub thread_ids: Vec<i64>,
+    /// Human-readable description of the cycle.
+    pub description: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct FreezeThreadRequest {
+    pub thread_id: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct ThreadInfoResponse {
+    pub threads: Vec<ThreadInfo>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct ThreadInfo {
+    pub id: i64,
+    /// LLDB's sequential thread index.
+    pub index: u32,
+    pub name: Option<String>,
+    /// E.g. `Breakpoint`, `Signal`, `None`.
+    pub stop_reason: String,
+    pub stop_description: Option<String>,
+    /// Location of the innermost stack frame.
+    pub location: Option<CodeLocation>,
+    /// Name of the dispatch queue the thread is servicing (macOS).
+    pub queue_name: Option<String>,
+    /// True if the thread has been frozen with `_freezeThread`.
+    pub frozen: bool,
+    /// Memory reference of the start of the memory region containing the thread's stack.
+    pub stack_start: Option<String>,
+    /// Memory reference of the end of the memory region containing the thread's stack.
+    pub stack_end: Option<String>,
+    /// Scheduler state of the thread, e.g. `sleeping` (Linux only).
+    pub scheduler_state: Option<String>,
+    /// CPU time consumed by the thread in user and kernel modes, in seconds (Linux only).
+    pub cpu_time: Option<f64>,
+}
+
 #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 #[schemars(deny_unknown_fields)]
@@ -180,6 +488,36 @@ pub struct AdapterSettings {
     pub script_config: Option<serde_json::Map<String, serde_json::Value>>,
     pub evaluate_for_hovers: Option<bool>,
     pub command_completions: Option<bool>,
+    pub disassembly: Option<DisassemblySettings>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct DisassemblySettings {
+    /// Assembly syntax flavor (x86 only).
+    pub flavor: Option<DisassemblyFlavor>,
+    /// Whether to show instruction bytes.
+    pub show_bytes: Option<bool>,
+    /// Maximum number of instruction bytes to show.
+    pub max_bytes: Option<u32>,
+    /// Display instruction mnemonics in upper case.
+    pub uppercase_mnemonics: Option<bool>,
+    /// Whether to demangle symbol names.
+    pub demangle: Option<bool>,
+    /// Interleave source lines with instructions in disassembly views.
+    pub interleave_source: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DisassemblyFlavor {
+    /// Use LLDB's `target.x86-disassembly-flavor` setting.
+    Default,
+    /// Intel syntax.
+    Intel,
+    /// AT&T syntax.
+    Att,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
@@ -217,10 +555,55 @@ pub struct CommonLaunchFields {
     pub relative_path_base: Option<String>,
     /// Specifies how source breakpoints should be set
     pub breakpoint_mode: Option<BreakpointMode>,
+    /// Natvis files (or directories containing them) with visualizers for native types.
+    pub natvis: Option<Vec<String>>,
+    /// Step only through user code.  Either a boolean, or an object specifying which source files are considered user code.
+    pub just_my_code: Option<Either<bool, JustMyCode>>,
+    /// Rules for de-emphasizing or collapsing stack frames in the call stack view.
+    pub frame_filters: Option<Vec<FrameFilter>>,
     #[serde(rename = "_adapterSettings")]
     #[schemars(skip)]
     pub adapter_settings: Option<AdapterSettings>,
 }
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct FrameFilter {
+    /// Regular expression matched against the function name.
+    pub function: Option<String>,
+    /// Regular expression matched against the path of the module containing the function.
+    pub module: Option<String>,
+    /// How matching frames are presented.  Defaults to "subtle".
+    pub presentation: Option<FramePresentation>,
+    /// Description of matching frames used in collapsed frame labels, e.g. "std" produces "[12 std frames]".
+    pub label: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FramePresentation {
+    /// Show frames as usual.
+    Normal,
+    /// De-emphasize frames.
+    Subtle,
+    /// Show frames as non-selectable labels.
+    Label,
+    /// Fold runs of consecutive matching frames into a single label.
+    Collapse,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+#[schemars(deny_unknown_fields)]
+pub struct JustMyCode {
+    /// Glob patterns of source files considered to be user code.  Relative patterns are resolved against `relativePathBase`.
+    /// Defaults to all files under `relativePathBase`.
+    pub include: Option<Vec<String>>,
+    /// Glob patterns of source files to exclude from user code, e.g. vendored dependencies or generated code.
+    pub exclude: Option<Vec<String>>,
+}
+
 #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 #[schemars(deny_unknown_fields)]
@@ -242,6 +625,8 @@ pub struct LaunchRequestArguments {
     pub stdio: Option<Either<String, Vec<Option<String>>>>,
     /// Automatically stop debuggee after launch
     pub stop_on_entry: Option<bool>,
+    /// Create the debug target, but do not start the debuggee until a `_run` request is received
+    pub stop_before_launch: Option<bool>,
     /// Terminal type to use
     pub terminal: Option<TerminalKind>,
     /// Terminal type to use. (This setting is a compatibility alias of 'terminal'.)
@@ -287,6 +672,10 @@ pub struct LaunchEnvironment {
     pub terminal_id: Option<TerminalId>,
     /// Debug configuration
     pub config: Option<String>,
+    /// One-time secret identifying the launcher to the debug adapter.
+    pub auth_token: Option<String>,
+    /// Keep the connection open until the end of the debug session, then send DebuggeeExit.
+    pub wait: Option<bool>,
 }
 
 /// Response to LaunchEnvironment request
@@ -298,6 +687,21 @@ pub struct LaunchResponse {
     pub message: Option<String>,
 }
 
+/// Exit status of the debuggee, sent after LaunchResponse, if LaunchEnvironment requested to wait.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum DebuggeeExit {
+    /// The debuggee has exited normally.
+    Exited {
+        #[serde(rename = "exitCode")]
+        exit_code: i32,
+    },
+    /// The debuggee has been terminated by a signal.
+    Signaled { signal: i32 },
+    /// The debug session has ended before the debuggee exited.
+    Unknown,
+}
+
 #[test]
 fn serialization() {
     let kind = serde_json::from_str::<TerminalKind>(r#""integrated""#).unwrap();
@@ -308,4 +712,10 @@ fn serialization() {
 
     let kind = serde_json::from_str::<TerminalKind>(r#"42"#).unwrap();
     assert!(matches!(kind, TerminalKind::TerminalId(TerminalId::PID(pid)) if pid == 42));
+
+    let exit = serde_json::from_str::<DebuggeeExit>(r#"{"status":"exited","exitCode":3}"#).unwrap();
+    assert_eq!(exit, DebuggeeExit::Exited { exit_code: 3 });
+
+    let exit = serde_json::from_str::<DebuggeeExit>(r#"{"status":"unknown"}"#).unwrap();
+    assert_eq!(exit, DebuggeeExit::Unknown);
 }
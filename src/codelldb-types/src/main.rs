@@ -17,6 +17,7 @@ pub enum TypeLibrary {
     _AttachRequestArguments(AttachRequestArguments),
     _LaunchEnvironment(LaunchEnvironment),
     _LaunchResponse(LaunchResponse),
+    _DebuggeeExit(DebuggeeExit),
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
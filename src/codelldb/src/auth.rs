@@ -0,0 +1,33 @@
+use crate::prelude::*;
+
+// Generates a random token for authenticating connections to the adapter's listeners.
+pub fn one_time_secret() -> Result<String, Error> {
+    let mut bytes = [0u8; 16];
+    if let Err(err) = getrandom::getrandom(&mut bytes) {
+        bail!(format!("Could not generate a secret: {}", err));
+    }
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// Compares tokens in time that does not depend on the position of the first mismatch.
+pub fn tokens_equal(expected: &str, actual: &str) -> bool {
+    let (expected, actual) = (expected.as_bytes(), actual.as_bytes());
+    if expected.len() != actual.len() {
+        return false;
+    }
+    expected.iter().zip(actual).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_tokens() {
+    let secret = one_time_secret().unwrap();
+    assert_eq!(secret.len(), 32);
+    assert_ne!(secret, one_time_secret().unwrap());
+
+    assert!(tokens_equal(&secret, &secret.clone()));
+    assert!(!tokens_equal(&secret, &secret[1..]));
+    assert!(!tokens_equal("secret", "secreT"));
+    assert!(!tokens_equal("secret", ""));
+}
@@ -0,0 +1,474 @@
+use crate::prelude::*;
+
+use crate::dap_session::in_memory_channel;
+use adapter_protocol::*;
+use futures::channel::mpsc;
+use futures::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MAX_STACK_FRAMES: u32 = 64;
+
+/// Batch script, which describes a debugging session to run without a DAP client.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct BatchScript {
+    /// Launch configuration, the same as would be used in launch.json.
+    launch: Value,
+    #[serde(default)]
+    breakpoints: Vec<BreakpointSpec>,
+    /// Actions to perform whenever the debuggee stops, unless overridden by the breakpoint that was hit.
+    #[serde(default = "default_actions")]
+    on_stop: Vec<Action>,
+    /// Where to write the results.  Defaults to stdout.
+    output: Option<PathBuf>,
+    /// Maximum duration of the session, in seconds.
+    timeout: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct BreakpointSpec {
+    file: Option<PathBuf>,
+    line: Option<u32>,
+    function: Option<String>,
+    condition: Option<String>,
+    hit_condition: Option<String>,
+    log_message: Option<String>,
+    actions: Option<Vec<Action>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+enum Action {
+    Evaluate(String),
+    /// Fails the script if the expression does not evaluate to true.
+    Assert(String),
+    StackTrace,
+    Locals,
+    // The following resume the debuggee, so they should be the last action.
+    Continue,
+    Next,
+    StepIn,
+    StepOut,
+}
+
+fn default_actions() -> Vec<Action> {
+    vec![Action::StackTrace, Action::Locals, Action::Continue]
+}
+
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct BatchOutput {
+    stops: Vec<StopRecord>,
+    exit_code: Option<i64>,
+    failures: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct StopRecord {
+    reason: String,
+    thread_id: i64,
+    /// Index of the breakpoint in the script.
+    breakpoint: Option<usize>,
+    results: Vec<Value>,
+}
+
+/// Runs a batch script.  Returns the debuggee's exit code.
+pub async fn run_batch(
+    script_path: &Path,
+    adapter_settings: &AdapterSettings,
+    python_interface: &Option<Arc<crate::python::PythonInterface>>,
+) -> Result<i32, Error> {
+    let script = match parse_script(&std::fs::read_to_string(script_path)?) {
+        Ok(script) => script,
+        Err(err) => bail!(format!("{}: {}", script_path.display(), err)),
+    };
+    let timeout = script.timeout.map(Duration::from_secs_f64).unwrap_or(Duration::MAX); // Validated by parse_script()
+
+    let (channel, sender, receiver) = in_memory_channel();
+    let mut client = BatchClient {
+        sender,
+        receiver,
+        seq: 0,
+        responses: HashMap::new(),
+        events: VecDeque::new(),
+    };
+    let session_fut = crate::run_debug_session(channel, adapter_settings, python_interface);
+    let client_fut = async {
+        let mut output = BatchOutput::default();
+        let result = match tokio::time::timeout(timeout, client.run_script(&script, &mut output)).await {
+            Ok(result) => result,
+            Err(_) => Err("The batch script has timed out.".into()),
+        };
+        if let Err(err) = result {
+            output.failures.push(err.to_string());
+        }
+        if let Ok(seq) = client.send("disconnect", json!({ "terminateDebuggee": true })) {
+            log_errors!(tokio::time::timeout(Duration::from_secs(5), client.response(seq)).await);
+        }
+        drop(client); // Ends the session.
+        output
+    };
+    let (_, output) = future::join(session_fut, client_fut).await;
+
+    let output_json = serde_json::to_string_pretty(&output)?;
+    match &script.output {
+        Some(path) => std::fs::write(path, output_json)?,
+        None => println!("{}", output_json),
+    }
+    if !output.failures.is_empty() {
+        bail!(format!("Batch script failed: {}", output.failures.join("; ")));
+    }
+    Ok(output.exit_code.unwrap_or(0) as i32)
+}
+
+fn parse_script(text: &str) -> Result<BatchScript, Error> {
+    let script: BatchScript = serde_json::from_str(text)?;
+    if let Some(timeout) = script.timeout {
+        if Duration::try_from_secs_f64(timeout).is_err() {
+            bail!(format!("Invalid timeout: {}", timeout));
+        }
+    }
+    Ok(script)
+}
+
+// A minimal DAP client.
+struct BatchClient {
+    sender: mpsc::UnboundedSender<ProtocolMessage>,
+    receiver: mpsc::UnboundedReceiver<ProtocolMessage>,
+    seq: u32,
+    // Responses that have been received, but not claimed yet, by request_seq.
+    responses: HashMap<u32, Value>,
+    events: VecDeque<Value>,
+}
+
+impl BatchClient {
+    async fn run_script(&mut self, script: &BatchScript, output: &mut BatchOutput) -> Result<(), Error> {
+        self.request(
+            "initialize",
+            json!({
+                "clientID": "codelldb-batch",
+                "adapterID": "lldb",
+                "pathFormat": "path",
+                "linesStartAt1": true,
+                "columnsStartAt1": true,
+                "supportsVariableType": true,
+            }),
+        )
+        .await?;
+
+        // The launch request completes only after configurationDone.
+        let launch_seq = self.send("launch", script.launch.clone())?;
+        loop {
+            if let Some(response) = self.responses.remove(&launch_seq) {
+                // The launch has failed before the adapter could get to configuration.
+                response_body(response)?;
+                bail!("Unexpected launch response.");
+            }
+            if let Some(pos) = self.events.iter().position(|e| e["event"] == "initialized") {
+                self.events.remove(pos);
+                break;
+            }
+            self.receive().await?;
+        }
+        let breakpoint_ids = self.set_breakpoints(&script.breakpoints).await?;
+        self.request("configurationDone", json!({})).await?;
+        self.response(launch_seq).await?;
+
+        loop {
+            let event = self.next_event().await?;
+            let body = &event["body"];
+            match event["event"].as_str() {
+                Some("stopped") => {
+                    let thread_id = body["threadId"].as_i64().unwrap_or(0);
+                    let breakpoint = body["hitBreakpointIds"]
+                        .as_array()
+                        .and_then(|ids| ids.iter().find_map(|id| breakpoint_ids.get(&id.as_i64()?).copied()));
+                    let actions = match breakpoint.and_then(|index| script.breakpoints[index].actions.as_ref()) {
+                        Some(actions) => actions,
+                        None => &script.on_stop,
+                    };
+                    let mut stop = StopRecord {
+                        reason: body["reason"].as_str().unwrap_or("").to_owned(),
+                        thread_id,
+                        breakpoint,
+                        results: Vec::new(),
+                    };
+                    let resumed = self.perform_actions(actions, thread_id, &mut stop, &mut output.failures).await?;
+                    output.stops.push(stop);
+                    if !resumed {
+                        self.request("continue", json!({ "threadId": thread_id })).await?;
+                    }
+                }
+                Some("output") => {
+                    if let Some(text) = body["output"].as_str() {
+                        eprint!("{}", text);
+                    }
+                }
+                Some("exited") => output.exit_code = body["exitCode"].as_i64(),
+                Some("terminated") => break,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Returns a mapping from the adapter's breakpoint ids to indices of the breakpoints in the script.
+    async fn set_breakpoints(&mut self, breakpoints: &[BreakpointSpec]) -> Result<HashMap<i64, usize>, Error> {
+        let mut by_file: BTreeMap<PathBuf, Vec<usize>> = BTreeMap::new();
+        let mut functions = Vec::new();
+        for (index, bp) in breakpoints.iter().enumerate() {
+            match (&bp.file, bp.line, &bp.function) {
+                (Some(file), Some(_), None) => by_file.entry(std::path::absolute(file)?).or_default().push(index),
+                (None, None, Some(_)) if bp.log_message.is_some() => bail!(format!(
+                    "Breakpoint #{}: logMessage is not supported on function breakpoints.",
+                    index
+                )),
+                (None, None, Some(_)) => functions.push(index),
+                _ => bail!(format!(
+                    "Breakpoint #{} must specify either file and line, or function.",
+                    index
+                )),
+            }
+        }
+
+        let mut breakpoint_ids = HashMap::new();
+        let mut record_ids = |body: Value, indices: &[usize]| {
+            if let Some(bps) = body["breakpoints"].as_array() {
+                for (bp, &index) in bps.iter().zip(indices) {
+                    if let Some(id) = bp["id"].as_i64() {
+                        breakpoint_ids.insert(id, index);
+                    }
+                }
+            }
+        };
+        for (file, indices) in by_file {
+            let source_bps: Vec<Value> = indices
+                .iter()
+                .map(|&index| {
+                    let bp = &breakpoints[index];
+                    json!({
+                        "line": bp.line,
+                        "condition": bp.condition,
+                        "hitCondition": bp.hit_condition,
+                        "logMessage": bp.log_message,
+                    })
+                })
+                .collect();
+            let args = json!({ "source": { "path": file }, "breakpoints": source_bps });
+            record_ids(self.request("setBreakpoints", args).await?, &indices);
+        }
+        if !functions.is_empty() {
+            let function_bps: Vec<Value> = functions
+                .iter()
+                .map(|&index| {
+                    let bp = &breakpoints[index];
+                    json!({
+                        "name": bp.function,
+                        "condition": bp.condition,
+                        "hitCondition": bp.hit_condition,
+                    })
+                })
+                .collect();
+            let args = json!({ "breakpoints": function_bps });
+            record_ids(self.request("setFunctionBreakpoints", args).await?, &functions);
+        }
+        Ok(breakpoint_ids)
+    }
+
+    // Returns true if one of the actions has resumed the debuggee.
+    async fn perform_actions(
+        &mut self,
+        actions: &[Action],
+        thread_id: i64,
+        stop: &mut StopRecord,
+        failures: &mut Vec<String>,
+    ) -> Result<bool, Error> {
+        let stack = self
+            .request(
+                "stackTrace",
+                json!({ "threadId": thread_id, "levels": MAX_STACK_FRAMES }),
+            )
+            .await?;
+        let frames = stack["stackFrames"].as_array().cloned().unwrap_or_default();
+        let frame_id = frames.get(0).map(|f| f["id"].clone()).unwrap_or(Value::Null);
+
+        for action in actions {
+            match action {
+                Action::Evaluate(expression) => {
+                    let args = json!({ "expression": expression, "frameId": frame_id, "context": "watch" });
+                    let result = match self.request("evaluate", args).await {
+                        Ok(body) => json!({ "evaluate": expression, "result": body["result"], "type": body["type"] }),
+                        Err(err) => json!({ "evaluate": expression, "error": err.to_string() }),
+                    };
+                    stop.results.push(result);
+                }
+                Action::Assert(expression) => {
+                    let args = json!({ "expression": expression, "frameId": frame_id, "context": "watch" });
+                    let passed = match self.request("evaluate", args).await {
+                        Ok(body) => is_truthy(body["result"].as_str().unwrap_or("")),
+                        Err(_) => false,
+                    };
+                    if !passed {
+                        failures.push(format!("Assertion failed: {}", expression));
+                    }
+                    stop.results.push(json!({ "assert": expression, "passed": passed }));
+                }
+                Action::StackTrace => {
+                    let frames: Vec<Value> = frames
+                        .iter()
+                        .map(|f| json!({ "name": f["name"], "source": f["source"]["path"], "line": f["line"] }))
+                        .collect();
+                    stop.results.push(json!({ "stackTrace": frames }));
+                }
+                Action::Locals => {
+                    let scopes = self.request("scopes", json!({ "frameId": frame_id })).await?;
+                    let locals_ref = scopes["scopes"]
+                        .as_array()
+                        .and_then(|scopes| scopes.iter().find(|s| s["presentationHint"] == "locals"))
+                        .map(|s| s["variablesReference"].clone());
+                    let mut locals = Vec::new();
+                    if let Some(locals_ref) = locals_ref {
+                        let vars = self.request("variables", json!({ "variablesReference": locals_ref })).await?;
+                        for var in vars["variables"].as_array().into_iter().flatten() {
+                            locals.push(json!({ "name": var["name"], "value": var["value"], "type": var["type"] }));
+                        }
+                    }
+                    stop.results.push(json!({ "locals": locals }));
+                }
+                Action::Continue | Action::Next | Action::StepIn | Action::StepOut => {
+                    let command = match action {
+                        Action::Continue => "continue",
+                        Action::Next => "next",
+                        Action::StepIn => "stepIn",
+                        _ => "stepOut",
+                    };
+                    self.request(command, json!({ "threadId": thread_id })).await?;
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn send(&mut self, command: &str, arguments: Value) -> Result<u32, Error> {
+        self.seq += 1;
+        let message = json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments });
+        let message: ProtocolMessage = serde_json::from_value(message)?;
+        if self.sender.unbounded_send(message).is_err() {
+            bail!("The debug session has ended.");
+        }
+        Ok(self.seq)
+    }
+
+    async fn request(&mut self, command: &str, arguments: Value) -> Result<Value, Error> {
+        let seq = self.send(command, arguments)?;
+        self.response(seq).await
+    }
+
+    async fn response(&mut self, seq: u32) -> Result<Value, Error> {
+        loop {
+            if let Some(response) = self.responses.remove(&seq) {
+                return response_body(response);
+            }
+            self.receive().await?;
+        }
+    }
+
+    async fn next_event(&mut self) -> Result<Value, Error> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            self.receive().await?;
+        }
+    }
+
+    // Receives the next message from the adapter and stashes it.
+    async fn receive(&mut self) -> Result<(), Error> {
+        let message = match self.receiver.next().await {
+            Some(message) => serde_json::to_value(&message)?,
+            None => bail!("The debug session has ended unexpectedly."),
+        };
+        match message["type"].as_str() {
+            Some("response") => {
+                let request_seq = message["request_seq"].as_u64().unwrap_or(0) as u32;
+                self.responses.insert(request_seq, message);
+            }
+            Some("event") => self.events.push_back(message),
+            _ => {} // Reverse requests are not supported.
+        }
+        Ok(())
+    }
+}
+
+fn response_body(mut response: Value) -> Result<Value, Error> {
+    if response["success"] == true {
+        Ok(response["body"].take())
+    } else {
+        bail!(response["message"].as_str().unwrap_or("Request failed.").to_owned())
+    }
+}
+
+fn is_truthy(value: &str) -> bool {
+    match value.trim() {
+        "" | "false" | "0" | "False" | "None" => false,
+        _ => true,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_parse_script() {
+    let script = parse_script(
+        r#"{
+            "launch": { "program": "debuggee", "args": ["vars"] },
+            "breakpoints": [
+                { "file": "main.cpp", "line": 10, "condition": "i > 5" },
+                { "function": "foo", "actions": [{ "evaluate": "x" }, { "assert": "x == 1" }, "stepOut"] }
+            ]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(script.breakpoints.len(), 2);
+    assert_eq!(script.breakpoints[0].condition.as_deref(), Some("i > 5"));
+    assert!(matches!(
+        script.breakpoints[1].actions.as_deref(),
+        Some([Action::Evaluate(_), Action::Assert(_), Action::StepOut])
+    ));
+    assert!(matches!(
+        script.on_stop[..],
+        [Action::StackTrace, Action::Locals, Action::Continue]
+    ));
+
+    assert!(parse_script(r#"{ "launch": {}, "onStop": ["jump"] }"#).is_err());
+
+    let script = parse_script(
+        r#"{
+            "launch": { "program": "debuggee" },
+            "breakpoints": [{ "file": "main.cpp", "line": 10, "logMessage": "i = {i}" }],
+            "timeout": 30
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(script.breakpoints[0].log_message.as_deref(), Some("i = {i}"));
+    assert_eq!(script.timeout, Some(30.0));
+
+    assert!(parse_script(r#"{ "launch": {}, "timeout": -1 }"#).is_err());
+    assert!(parse_script(r#"{ "launch": {}, "timeout": 1e300 }"#).is_err());
+}
+
+#[test]
+fn test_is_truthy() {
+    assert!(is_truthy("true"));
+    assert!(is_truthy("42"));
+    assert!(!is_truthy("false"));
+    assert!(!is_truthy("0"));
+}
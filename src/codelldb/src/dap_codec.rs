@@ -1,5 +1,7 @@
 use crate::prelude::*;
 
+use crate::auth::tokens_equal;
+use crate::recording::SessionRecorder;
 use adapter_protocol::ProtocolMessage;
 use bytes::BytesMut;
 use serde_json;
@@ -11,6 +13,9 @@ use tokio_util::codec;
 pub struct DAPCodec {
     state: State,
     content_len: usize,
+    // The token the client must present before any messages are accepted; reset upon successful authentication.
+    auth_token: Option<String>,
+    recorder: Option<SessionRecorder>,
 }
 
 enum State {
@@ -23,8 +28,22 @@ impl DAPCodec {
         DAPCodec {
             state: State::ReadingHeaders,
             content_len: 0,
+            auth_token: None,
+            recorder: None,
         }
     }
+
+    /// Record all decoded and encoded messages.
+    pub fn record_to(mut self, recorder: SessionRecorder) -> DAPCodec {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Require the client to send `Auth-Token: <auth_token>` header with its first message.
+    pub fn require_auth_token(mut self, auth_token: String) -> DAPCodec {
+        self.auth_token = Some(auth_token);
+        self
+    }
 }
 
 pub type DecoderResult = Result<ProtocolMessage, DecoderError>;
@@ -57,12 +76,27 @@ impl codec::Decoder for DAPCodec {
                     Some(pos) => {
                         let line = buffer.split_to(pos + 2);
                         if line.len() == 2 {
+                            if self.auth_token.is_some() {
+                                error!("Rejecting client: no Auth-Token header");
+                                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Not authenticated"));
+                            }
                             self.state = State::ReadingBody;
                         } else if let Ok(line) = str::from_utf8(&line) {
                             if let Some(rest) = has_prefix(line, "Content-Length:") {
                                 if let Ok(content_len) = rest.trim().parse::<usize>() {
                                     self.content_len = content_len;
                                 }
+                            } else if let Some(rest) = has_prefix(line, "Auth-Token:") {
+                                if let Some(auth_token) = &self.auth_token {
+                                    if !tokens_equal(auth_token, rest.trim()) {
+                                        error!("Rejecting client: invalid Auth-Token");
+                                        return Err(io::Error::new(
+                                            io::ErrorKind::PermissionDenied,
+                                            "Invalid auth token",
+                                        ));
+                                    }
+                                    self.auth_token = None;
+                                }
                             } else if let Some(_) = has_prefix(line, "Origin:") {
                                 // Guard against malicious Javascript requests originaling from a local browser.
                                 return Err(io::Error::new(
@@ -82,6 +116,9 @@ impl codec::Decoder for DAPCodec {
                         self.content_len = 0;
 
                         debug!("--> {}", str::from_utf8(&message_bytes).unwrap());
+                        if let Some(recorder) = &self.recorder {
+                            recorder.record_inbound(&message_bytes);
+                        }
                         match serde_json::from_slice(&message_bytes) {
                             Ok(message) => return Ok(Some(Ok(message))),
                             Err(err) => {
@@ -108,6 +145,9 @@ impl codec::Encoder<ProtocolMessage> for DAPCodec {
     fn encode(&mut self, message: ProtocolMessage, buffer: &mut BytesMut) -> Result<(), Self::Error> {
         let message_bytes = serde_json::to_vec(&message).unwrap();
         debug!("<-- {}", str::from_utf8(&message_bytes).unwrap());
+        if let Some(recorder) = &self.recorder {
+            recorder.record_outbound(&message_bytes);
+        }
 
         buffer.reserve(32 + message_bytes.len());
         write!(buffer, "Content-Length: {}\r\n\r\n", message_bytes.len()).unwrap();
@@ -116,3 +156,33 @@ impl codec::Encoder<ProtocolMessage> for DAPCodec {
         Ok(())
     }
 }
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_auth_token() {
+    use codec::Decoder;
+
+    let message = r#"{"seq":1,"type":"request","command":"initialize","arguments":{"adapterID":"lldb"}}"#;
+    let frame =
+        |headers: &str| BytesMut::from(format!("{headers}Content-Length: {}\r\n\r\n{message}", message.len()).as_str());
+
+    let mut codec = DAPCodec::new().require_auth_token("secret".into());
+    let mut buffer = frame("Auth-Token: secret\r\n");
+    assert!(matches!(codec.decode(&mut buffer), Ok(Some(Ok(_)))));
+    // Subsequent messages don't need to repeat the token.
+    let mut buffer = frame("");
+    assert!(matches!(codec.decode(&mut buffer), Ok(Some(Ok(_)))));
+
+    let mut codec = DAPCodec::new().require_auth_token("secret".into());
+    let mut buffer = frame("Auth-Token: guess\r\n");
+    assert!(codec.decode(&mut buffer).is_err());
+
+    let mut codec = DAPCodec::new().require_auth_token("secret".into());
+    let mut buffer = frame("");
+    assert!(codec.decode(&mut buffer).is_err());
+
+    let mut codec = DAPCodec::new();
+    let mut buffer = frame("");
+    assert!(matches!(codec.decode(&mut buffer), Ok(Some(Ok(_)))));
+}
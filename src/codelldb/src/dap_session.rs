@@ -3,11 +3,13 @@ use crate::prelude::*;
 use crate::dap_codec::{DecoderError, DecoderResult};
 
 use adapter_protocol::*;
+use futures::channel::mpsc as mpsc_unbounded;
 use futures::prelude::*;
 use std::collections::{hash_map::Entry, HashMap};
 use std::io;
 use std::pin::Pin;
 use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
 use tokio::sync::{broadcast, mpsc, oneshot};
 
 pub trait DAPChannel:
@@ -196,3 +198,55 @@ impl DAPSession {
         Ok(())
     }
 }
+
+/// Creates an in-process DAP channel, for driving a debug session programmatically.
+/// Returns the adapter's end of the channel, along with the client's sender and receiver.
+pub fn in_memory_channel() -> (
+    Box<dyn DAPChannel>,
+    mpsc_unbounded::UnboundedSender<ProtocolMessage>,
+    mpsc_unbounded::UnboundedReceiver<ProtocolMessage>,
+) {
+    let (client_sender, adapter_receiver) = mpsc_unbounded::unbounded();
+    let (adapter_sender, client_receiver) = mpsc_unbounded::unbounded();
+    let channel = MemoryChannel {
+        incoming: adapter_receiver.map(|message| Ok(Ok(message))),
+        outgoing: adapter_sender.sink_map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe)),
+    };
+    (Box::new(channel), client_sender, client_receiver)
+}
+
+struct MemoryChannel<I, O> {
+    incoming: I,
+    outgoing: O,
+}
+
+impl<I, O> Stream for MemoryChannel<I, O>
+where
+    I: Stream<Item = Result<DecoderResult, io::Error>> + Unpin,
+    O: Unpin,
+{
+    type Item = Result<DecoderResult, io::Error>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.incoming).poll_next(cx)
+    }
+}
+
+impl<I, O> Sink<ProtocolMessage> for MemoryChannel<I, O>
+where
+    I: Unpin,
+    O: Sink<ProtocolMessage, Error = io::Error> + Unpin,
+{
+    type Error = io::Error;
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.outgoing).poll_ready(cx)
+    }
+    fn start_send(mut self: Pin<&mut Self>, item: ProtocolMessage) -> Result<(), Self::Error> {
+        Pin::new(&mut self.outgoing).start_send(item)
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.outgoing).poll_flush(cx)
+    }
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.outgoing).poll_close(cx)
+    }
+}
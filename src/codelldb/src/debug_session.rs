@@ -1,8 +1,17 @@
+mod async_stack;
 mod breakpoints;
 mod debugger_terminal;
 mod disassemble;
+mod frame_filters;
+mod frozen_threads;
 mod launch;
+mod lock_analysis;
+mod memory;
+mod natvis;
+mod parallel_stacks;
 mod step_in;
+mod thread_info;
+mod user_code;
 mod variables;
 
 use crate::debug_event_listener::DebugEventListener;
@@ -15,6 +24,7 @@ use crate::expressions;
 use crate::fsutil::normalize_path;
 use crate::handles::HandleTree;
 use crate::must_initialize::{Initialized, MustInitialize, NotInitialized};
+use crate::natvis::Natvis;
 use crate::platform::{get_fs_path_case, pipe};
 use crate::python::PythonEvent;
 use crate::python::{PythonInterface, PythonSession};
@@ -22,12 +32,13 @@ use crate::shared::Shared;
 use crate::terminal::Terminal;
 use breakpoints::Breakpoints;
 use debugger_terminal::DebuggerTerminal;
+use frame_filters::DisplayFrame;
 use variables::Container;
 
 use std;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::ffi::CStr;
 use std::fmt::Write;
@@ -66,6 +77,8 @@ pub struct DebugSession {
     var_refs: HandleTree<Container>,
     disasm_ranges: disassembly::DisassembledRanges,
     source_map_cache: RefCell<HashMap<PathBuf, Option<Rc<PathBuf>>>>,
+    natvis: Natvis,
+    natvis_summary_depth: Cell<u32>,
     relative_path_base: MustInitialize<PathBuf>,
     pre_terminate_commands: Option<Vec<String>>,
     exit_commands: Option<Vec<String>>,
@@ -90,7 +103,13 @@ pub struct DebugSession {
     graceful_shutdown: Option<Either<String, Vec<String>>>,
     summary_timeout: time::Duration,
     max_summary_length: usize,
-    max_instr_bytes: usize,
+    memory_snapshots: memory::MemorySnapshots,
+    memory_views: Vec<std::ops::Range<Address>>,
+    pending_launch: Option<LaunchRequestArguments>,
+    just_my_code: Option<user_code::UserCodeFilter>,
+    frame_filters: Vec<frame_filters::FrameFilterRule>,
+    python_frame_filters: bool, // Whether any frame filters have been registered by Python scripts
+    frozen_threads: HashSet<ThreadID>,
 }
 
 // AsyncResponse is used to "smuggle" futures out of request handlers
@@ -158,8 +177,10 @@ impl DebugSession {
 
             breakpoints: RefCell::new(Breakpoints::new()),
             var_refs: HandleTree::new(),
-            disasm_ranges: disassembly::DisassembledRanges::new(&target),
+            disasm_ranges: disassembly::DisassembledRanges::new(&target, Default::default()),
             source_map_cache: RefCell::new(HashMap::new()),
+            natvis: Natvis::new(),
+            natvis_summary_depth: Cell::new(0),
             relative_path_base: NotInitialized,
             pre_terminate_commands: None,
             exit_commands: None,
@@ -184,7 +205,13 @@ impl DebugSession {
             graceful_shutdown: None,
             summary_timeout: time::Duration::from_millis(10),
             max_summary_length: 32,
-            max_instr_bytes: 9,
+            memory_snapshots: Default::default(),
+            memory_views: Vec::new(),
+            pending_launch: None,
+            just_my_code: None,
+            frame_filters: Vec::new(),
+            python_frame_filters: false,
+            frozen_threads: HashSet::new(),
         };
 
         let con_reader = tokio::fs::File::from_std(con_reader);
@@ -199,6 +226,7 @@ impl DebugSession {
         let con_writer = debug_session.console_pipe.try_clone().unwrap();
         log_errors!(debug_session.debugger.set_output_file(SBFile::from(con_writer, false)));
 
+        let debugger = debug_session.debugger.clone();
         let shared_session = Shared::new(debug_session);
         shared_session.try_map(|s| s.self_ref = Initialized(shared_session.clone())).unwrap();
 
@@ -237,7 +265,7 @@ impl DebugSession {
                 })
                 .await;
 
-            SBBreakpoint::clear_all_callbacks(); // Callbacks hold references to the session.
+            SBBreakpoint::clear_all_callbacks(&debugger); // Callbacks hold references to the session.
 
             // There shouldn't be any other references at this point.
             if shared_session.ref_count() > 1 {
@@ -318,7 +346,10 @@ impl DebugSession {
                             // Requests that may be canceled.
                             RequestArguments::scopes(_)
                             | RequestArguments::variables(_)
-                            | RequestArguments::evaluate(_) => cancellable_requests.push(sender.clone()),
+                            | RequestArguments::evaluate(_)
+                            | RequestArguments::_findMemory(_)
+                            | RequestArguments::_parallelStacks(_)
+                            | RequestArguments::_lockAnalysis(_) => cancellable_requests.push(sender.clone()),
                             // Requests that will cancel the above.
                             RequestArguments::continue_(_)
                             | RequestArguments::pause(_)
@@ -429,6 +460,9 @@ impl DebugSession {
                         RequestArguments::setBreakpoints(args) =>
                             self.handle_set_breakpoints(args)
                                 .map(|r| ResponseBody::setBreakpoints(r)),
+                        RequestArguments::breakpointLocations(args) =>
+                            self.handle_breakpoint_locations(args)
+                                .map(|r| ResponseBody::breakpointLocations(r)),
                         RequestArguments::setInstructionBreakpoints(args) =>
                             self.handle_set_instruction_breakpoints(args)
                                 .map(|r| ResponseBody::setInstructionBreakpoints(r)),
@@ -527,6 +561,38 @@ impl DebugSession {
                         RequestArguments::_pythonMessage(args) =>
                             self.handle_python_message(args)
                                 .map(|_| ResponseBody::_pythonMessage),
+                        RequestArguments::_findMemory(args) =>
+                            self.handle_find_memory(args)
+                                .map(|r| ResponseBody::_findMemory(r)),
+                        RequestArguments::_snapshotMemory(args) =>
+                            self.handle_snapshot_memory(args)
+                                .map(|r| ResponseBody::_snapshotMemory(r)),
+                        RequestArguments::_diffMemory(args) =>
+                            self.handle_diff_memory(args)
+                                .map(|r| ResponseBody::_diffMemory(r)),
+                        RequestArguments::_run(_) =>
+                            self.handle_run(),
+                        RequestArguments::_asyncStack(args) =>
+                            self.handle_async_stack(args)
+                                .map(|r| ResponseBody::_asyncStack(r)),
+                        RequestArguments::_asyncTasks(_) =>
+                            self.handle_async_tasks()
+                                .map(|r| ResponseBody::_asyncTasks(r)),
+                        RequestArguments::_parallelStacks(args) =>
+                            self.handle_parallel_stacks(args)
+                                .map(|r| ResponseBody::_parallelStacks(r)),
+                        RequestArguments::_lockAnalysis(_) =>
+                            self.handle_lock_analysis()
+                                .map(|r| ResponseBody::_lockAnalysis(r)),
+                        RequestArguments::_freezeThread(args) =>
+                            self.handle_freeze_thread(args)
+                                .map(|_| ResponseBody::_freezeThread),
+                        RequestArguments::_thawThread(args) =>
+                            self.handle_thaw_thread(args)
+                                .map(|_| ResponseBody::_thawThread),
+                        RequestArguments::_threadInfo(_) =>
+                            self.handle_thread_info()
+                                .map(|r| ResponseBody::_threadInfo(r)),
                         _=> bail!("Not implemented.")
                     }
                 }
@@ -596,6 +662,7 @@ impl DebugSession {
         Capabilities {
             exception_breakpoint_filters: self.get_exception_filters_for(&self.source_languages),
             support_terminate_debuggee: Some(true),
+            supports_breakpoint_locations_request: Some(true),
             supports_cancel_request: Some(true),
             supports_clipboard_context: Some(true),
             supports_completions_request: Some(self.command_completions),
@@ -681,6 +748,9 @@ impl DebugSession {
             if let Some(name) = thread.name() {
                 log_errors!(write!(descr, " \"{}\"", name));
             }
+            if self.frozen_threads.contains(&thread.thread_id()) {
+                descr.push_str(" [frozen]");
+            }
             response.threads.push(Thread {
                 id: thread.thread_id() as i64,
                 name: descr,
@@ -704,14 +774,26 @@ impl DebugSession {
         let start_frame = args.start_frame.unwrap_or(0);
         let levels = args.levels.unwrap_or(std::i64::MAX);
 
+        let layout = self.frame_layout(&thread);
         let mut stack_frames = vec![];
         for i in start_frame..(start_frame + levels) {
-            let frame = thread.frame_at_index(i as u32);
+            let frame_index = match &layout {
+                None => i as u32,
+                Some(layout) => match layout.get(i as usize) {
+                    Some(DisplayFrame::Frame(index)) => *index,
+                    Some(DisplayFrame::Collapsed { first, count, rule }) => {
+                        stack_frames.push(self.make_collapsed_frame(&thread, *first, *count, *rule));
+                        continue;
+                    }
+                    None => break,
+                },
+            };
+            let frame = thread.frame_at_index(frame_index);
             if !frame.is_valid() {
                 break;
             }
 
-            let key = format!("[{},{}]", thread.index_id(), i);
+            let key = format!("[{},{}]", thread.index_id(), frame_index);
             let handle = self.var_refs.create(None, &key, Container::StackFrame(frame.clone()));
 
             let mut stack_frame: StackFrame = Default::default();
@@ -723,6 +805,14 @@ impl DebugSession {
             } else {
                 format!("{:X}", pc_address.file_address())
             };
+            // Calling into Python for every frame is expensive, so we only do it if any filters have been registered.
+            if let Some(python) = self.python.as_ref().filter(|_| self.python_frame_filters) {
+                match python.filter_frame_name(&frame) {
+                    Ok(Some(name)) => stack_frame.name = name,
+                    Ok(None) => {}
+                    Err(err) => error!("filter_frame_name: {}", err),
+                }
+            }
 
             let module = frame.module();
             if module.is_valid() {
@@ -740,11 +830,20 @@ impl DebugSession {
                             path: Some(local_path.to_string_lossy().into_owned()),
                             ..Default::default()
                         });
+                        // De-emphasize frames that "Just My Code" stepping would skip.
+                        if let Some(filter) = &self.just_my_code {
+                            if !filter.is_user_path(&local_path) {
+                                stack_frame.presentation_hint = Some(StackFramePresentationHint::Subtle);
+                                if let Some(source) = &mut stack_frame.source {
+                                    source.presentation_hint = Some(SourcePresentationHint::Deemphasize);
+                                }
+                            }
+                        }
                     }
                 }
             } else {
                 let pc_addr = frame.pc();
-                if let Ok(dasm) = self.disasm_ranges.from_address(pc_addr) {
+                if let Ok(dasm) = self.disasm_ranges.from_address(pc_addr, &|fs| self.map_filespec_to_local(fs)) {
                     stack_frame.line = dasm.line_num_by_address(pc_addr) as i64;
                     stack_frame.source = Some(Source {
                         name: Some(dasm.source_name().to_owned()),
@@ -756,16 +855,25 @@ impl DebugSession {
                 stack_frame.column = 0;
                 // Mark disassembly frames as "subtle" to reduce visual clutter,
                 // unless we are in "Always Show Disassembly" mode, or this is the first frame.
-                if i > 0 && self.show_disassembly != ShowDisassembly::Always {
+                if frame_index > 0 && self.show_disassembly != ShowDisassembly::Always {
                     stack_frame.presentation_hint = Some(StackFramePresentationHint::Subtle);
                 }
             }
+            if frame_index > 0 {
+                match self.frame_presentation(&frame) {
+                    FramePresentation::Subtle => {
+                        stack_frame.presentation_hint = Some(StackFramePresentationHint::Subtle)
+                    }
+                    FramePresentation::Label => stack_frame.presentation_hint = Some(StackFramePresentationHint::Label),
+                    FramePresentation::Normal | FramePresentation::Collapse => {}
+                }
+            }
             stack_frames.push(stack_frame);
         }
 
         Ok(StackTraceResponseBody {
             stack_frames: stack_frames,
-            total_frames: None,
+            total_frames: layout.map(|layout| layout.len() as i64),
         })
     }
 
@@ -799,6 +907,7 @@ impl DebugSession {
     }
 
     fn handle_continue(&mut self, _args: ContinueArguments) -> Result<ContinueResponseBody, Error> {
+        self.ensure_not_all_frozen()?;
         self.before_resume();
         let process = self.target.process();
         match process.resume() {
@@ -821,6 +930,7 @@ impl DebugSession {
 
     fn handle_next(&mut self, args: NextArguments) -> Result<(), Error> {
         let thread = self.thread_by_id(args.thread_id)?;
+        self.ensure_not_frozen(&thread)?;
 
         self.before_resume();
 
@@ -842,9 +952,14 @@ impl DebugSession {
     }
 
     fn handle_step_out(&mut self, args: StepOutArguments) -> Result<(), Error> {
-        self.before_resume();
         let thread = self.thread_by_id(args.thread_id)?;
-        thread.step_out()?;
+        self.ensure_not_frozen(&thread)?;
+        self.before_resume();
+        if self.just_my_code.is_some() {
+            self.step_out_to_user_code(&thread)?;
+        } else {
+            thread.step_out()?;
+        }
         Ok(())
     }
 
@@ -885,9 +1000,12 @@ impl DebugSession {
 
     fn handle_source(&mut self, args: SourceArguments) -> Result<SourceResponseBody, Error> {
         let handle = args.source_reference;
-        let dasm = self.disasm_ranges.find_by_handle(handle).ok_or(str_error("Invalid source reference"))?;
+        let dasm = self
+            .disasm_ranges
+            .find_by_handle(handle, &|fs| self.map_filespec_to_local(fs))
+            .ok_or(str_error("Invalid source reference"))?;
         Ok(SourceResponseBody {
-            content: dasm.get_source_text(self.max_instr_bytes),
+            content: dasm.get_source_text(),
             mime_type: Some("text/x-lldb.disassembly".to_owned()),
         })
     }
@@ -992,7 +1110,10 @@ impl DebugSession {
                 match goto_args.source.source_reference {
                     // Disassembly
                     Some(source_ref) => {
-                        let dasm = self.disasm_ranges.find_by_handle(source_ref).ok_or("source_ref")?;
+                        let dasm = self
+                            .disasm_ranges
+                            .find_by_handle(source_ref, &|fs| self.map_filespec_to_local(fs))
+                            .ok_or("source_ref")?;
                         let addr = dasm.address_by_line_num(goto_args.line as u32);
                         let frame = thread.frame_at_index(0).check().ok_or("frame 0")?;
                         if frame.set_pc(addr) {
@@ -1199,19 +1320,27 @@ impl DebugSession {
         let count = args.count as usize;
         let address = (mem_ref + offset) as lldb::Address;
         let process = self.target.process();
-        if let Ok(region_info) = process.memory_region_info(address) {
-            if region_info.is_readable() {
-                let to_read = cmp::min(count, (region_info.region_end() - address) as usize);
-                let mut buffer = Vec::new();
-                buffer.resize(to_read, 0);
-                if let Ok(bytes_read) = process.read_memory(address, buffer.as_mut_slice()) {
-                    buffer.resize(bytes_read, 0);
-                    return Ok(ReadMemoryResponseBody {
-                        address: format!("0x{:X}", address),
-                        unreadable_bytes: Some((count - bytes_read) as i64),
-                        data: Some(BASE64_STANDARD.encode(&buffer)),
-                    });
+        let to_read = if process.is_valid() {
+            match process.memory_region_info(address) {
+                Ok(region_info) if region_info.is_readable() => {
+                    cmp::min(count, (region_info.region_end() - address) as usize)
                 }
+                _ => 0,
+            }
+        } else {
+            count // Static target, reading from module sections
+        };
+        if to_read > 0 {
+            self.track_memory_view(address..address + to_read as u64);
+            let mut buffer = Vec::new();
+            buffer.resize(to_read, 0);
+            if let Ok(bytes_read) = self.read_target_memory(address, buffer.as_mut_slice()) {
+                buffer.resize(bytes_read, 0);
+                return Ok(ReadMemoryResponseBody {
+                    address: format!("0x{:X}", address),
+                    unreadable_bytes: Some((count - bytes_read) as i64),
+                    data: Some(BASE64_STANDARD.encode(&buffer)),
+                });
             }
         }
         Ok(ReadMemoryResponseBody {
@@ -1233,6 +1362,7 @@ impl DebugSession {
                 let to_write = cmp::min(data.len(), (region_info.region_end() - address) as usize);
                 if allow_partial || to_write == data.len() {
                     if let Ok(bytes_written) = process.write_memory(address, &data) {
+                        self.notify_memory_views();
                         return Ok(WriteMemoryResponseBody {
                             bytes_written: Some(bytes_written as i64),
                             ..Default::default()
@@ -1327,17 +1457,26 @@ impl DebugSession {
             PythonEvent::DebuggerMessage { output, category } => {
                 self.console_message_impl(Some(&category), output);
             }
+            PythonEvent::FrameFilterRegistered => {
+                self.python_frame_filters = true;
+            }
         }
     }
 
     fn handle_adapter_settings(&mut self, args: AdapterSettings) -> Result<(), Error> {
         let old_console_mode = self.console_mode;
+        let old_disasm_options = self.disasm_ranges.options().clone();
         self.update_adapter_settings_and_caps(&args);
         if self.console_mode != old_console_mode {
             self.print_console_mode();
         }
         if !self.target.process().state().is_running() {
             self.refresh_client_display(None);
+        } else if *self.disasm_ranges.options() != old_disasm_options {
+            // Cached disassembly is now stale, so open disassembly views need to be re-rendered.
+            if self.client_caps.supports_invalidated_event.unwrap_or(false) {
+                self.send_event(EventBody::invalidated(InvalidatedEventBody::default()));
+            }
         }
         Ok(())
     }
@@ -1362,6 +1501,24 @@ impl DebugSession {
         self.show_disassembly = settings.show_disassembly.unwrap_or(self.show_disassembly);
         self.deref_pointers = settings.dereference_pointers.unwrap_or(self.deref_pointers);
         self.suppress_missing_files = settings.suppress_missing_source_files.unwrap_or(self.suppress_missing_files);
+        if let Some(ref disassembly) = settings.disassembly {
+            let mut options = self.disasm_ranges.options().clone();
+            if let Some(flavor) = disassembly.flavor {
+                options.flavor = match flavor {
+                    DisassemblyFlavor::Default => None,
+                    DisassemblyFlavor::Intel => Some("intel".into()),
+                    DisassemblyFlavor::Att => Some("att".into()),
+                };
+            }
+            options.show_bytes = disassembly.show_bytes.unwrap_or(options.show_bytes);
+            options.max_bytes = disassembly.max_bytes.map_or(options.max_bytes, |n| n as usize);
+            options.uppercase_mnemonics = disassembly.uppercase_mnemonics.unwrap_or(options.uppercase_mnemonics);
+            options.demangle = disassembly.demangle.unwrap_or(options.demangle);
+            options.interleave_source = disassembly.interleave_source.unwrap_or(options.interleave_source);
+            if options != *self.disasm_ranges.options() {
+                self.disasm_ranges.set_options(options);
+            }
+        }
 
         if let Some(timeout) = settings.evaluation_timeout {
             self.evaluation_timeout = time::Duration::from_millis((timeout * 1000.0) as u64);
@@ -1425,6 +1582,7 @@ impl DebugSession {
     }
 
     fn before_resume(&mut self) {
+        self.apply_frozen_threads();
         self.var_refs.reset();
         self.selected_frame_changed = false;
         self.last_goto_request = None;
@@ -1552,6 +1710,7 @@ impl DebugSession {
             hit_breakpoint_ids: hit_breakpoint,
             ..Default::default()
         }));
+        self.notify_memory_views();
     }
 
     fn notify_process_terminated(&mut self) {
@@ -1562,6 +1721,20 @@ impl DebugSession {
                 let exit_code = process.exit_status() as i64;
                 self.console_message(format!("Process exited with code {}.", exit_code));
                 self.send_event(EventBody::exited(ExitedEventBody { exit_code }));
+                // When the debuggee is terminated by a signal, LLDB reports the signal number as the exit status,
+                // which can only be told apart from a normal exit by the exit description.
+                let signal = process.exit_description().and_then(|description| {
+                    parse_exit_signal(description, exit_code as i32, |name| {
+                        process.unix_signals().signal_number_from_name(name)
+                    })
+                });
+                let debuggee_exit = match signal {
+                    Some(signal) => DebuggeeExit::Signaled { signal },
+                    None => DebuggeeExit::Exited {
+                        exit_code: exit_code as i32,
+                    },
+                };
+                self.send_event(EventBody::_debuggeeExited(debuggee_exit));
                 self.send_event(EventBody::terminated(TerminatedEventBody { restart: None }));
             }
             Detached => {
@@ -1741,3 +1914,30 @@ impl Drop for DebugSession {
 fn into_string_lossy(cstr: &CStr) -> String {
     cstr.to_string_lossy().into_owned()
 }
+
+// Extracts the number of the terminating signal from a process exit description, such as
+// "Terminated due to signal 9" or "killed by signal SIGKILL".  Returns None if the description does not mention a signal.
+fn parse_exit_signal(description: &str, exit_status: i32, signal_by_name: impl Fn(&str) -> Option<i32>) -> Option<i32> {
+    let pos = description.to_ascii_lowercase().find("signal")?;
+    let rest = description[pos + "signal".len()..].trim_start_matches(|c: char| c == ':' || c.is_whitespace());
+    let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+    let token = &rest[..len];
+    match token.parse::<i32>() {
+        Ok(signal) => Some(signal),
+        // Otherwise, the exit status is the signal number.
+        Err(_) => signal_by_name(token).or(Some(exit_status)),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_parse_exit_signal() {
+    let by_name = |name: &str| if name == "SIGKILL" { Some(9) } else { None };
+    assert_eq!(parse_exit_signal("Terminated due to signal 9", 9, by_name), Some(9));
+    assert_eq!(parse_exit_signal("killed by signal SIGKILL", 9, by_name), Some(9));
+    assert_eq!(parse_exit_signal("Signal: 11", 11, by_name), Some(11));
+    assert_eq!(parse_exit_signal("terminated by a signal", 6, by_name), Some(6));
+    assert_eq!(parse_exit_signal("exited with status 9", 9, by_name), None);
+    assert_eq!(parse_exit_signal("", 0, by_name), None);
+}
@@ -0,0 +1,444 @@
+use crate::prelude::*;
+
+use super::variables::Container;
+
+use std::collections::HashSet;
+
+use adapter_protocol::*;
+use lldb::*;
+
+// Limits guarding against cycles and runaway walks through corrupted data structures.
+const MAX_AWAIT_DEPTH: usize = 256;
+const MAX_TASKS: usize = 10000;
+const MAX_SEARCH_NODES: usize = 20000;
+
+impl super::DebugSession {
+    pub(super) fn handle_async_stack(&mut self, args: AsyncStackRequest) -> Result<AsyncStackResponse, Error> {
+        let thread = self.thread_by_id(args.thread_id)?;
+        // Async function bodies are compiled into functions named like `app::main::{async_fn#0}`.
+        // The outermost of these on the stack is polling the root future of the current task.
+        let Some(root_frame) = thread
+            .frames()
+            .filter(|f| f.function_name().map_or(false, |name| is_coroutine_body(name)))
+            .last()
+        else {
+            bail!(blame_user(str_error(
+                "There are no async functions on the call stack of this thread."
+            )));
+        };
+        let options = VariableOptions {
+            arguments: true,
+            locals: false,
+            statics: false,
+            in_scope_only: false,
+        };
+        let Some(future) = root_frame.variables(&options).iter().next() else {
+            bail!("Could not locate the future being polled.");
+        };
+        let frames = self.await_chain(&future, Some(&thread));
+        Ok(AsyncStackResponse { frames })
+    }
+
+    pub(super) fn handle_async_tasks(&mut self) -> Result<AsyncTasksResponse, Error> {
+        let process = self.target.process();
+        if !process.is_valid() {
+            bail!(blame_user(str_error("No process.")));
+        }
+        if process.state().is_running() {
+            bail!(blame_nobody(str_error("Not available while the process is running.")));
+        }
+        let Some(header_type) = self.target.find_first_type("tokio::runtime::task::core::Header") else {
+            bail!(blame_user(str_error(
+                "Listing of async tasks is only supported for the tokio runtime."
+            )));
+        };
+
+        let mut tasks = Vec::new();
+        let mut seen = HashSet::new();
+        for list in self.find_tokio_task_lists(&process) {
+            let mut header_addr = read_pointer(&list.child_member_with_name("head"));
+            while let Some(addr) = header_addr {
+                if tasks.len() >= MAX_TASKS || !seen.insert(addr) {
+                    break;
+                }
+                header_addr = self.tokio_task(addr, &header_type, &mut tasks);
+            }
+        }
+        Ok(AsyncTasksResponse { tasks })
+    }
+
+    // Walks the chain of futures awaited by `future`, converting each level into an async frame.
+    // When `thread` is given, async functions it is currently executing are located using its stack frames.
+    fn await_chain(&mut self, future: &SBValue, thread: Option<&SBThread>) -> Vec<AsyncFrame> {
+        let mut frames = Vec::new();
+        let mut next = resolve_future(future);
+        while let Some(future) = next.take() {
+            if frames.len() >= MAX_AWAIT_DEPTH {
+                break;
+            }
+            let type_name = future.type_name().unwrap_or_default().to_owned();
+            let key = format!("[async,{:X},{}]", future.load_address(), type_name);
+            match coroutine_name(&type_name) {
+                Some(name) => {
+                    let mut frame = AsyncFrame {
+                        name,
+                        ..Default::default()
+                    };
+                    self.locate_coroutine(&type_name, thread, &mut frame);
+                    if let Some(state) = active_variant(&future) {
+                        frame.state = state.type_name().map(|name| last_path_segment(name).to_owned());
+                        frame.variables_reference = self.var_refs.create(None, &key, Container::SBValue(state.clone()));
+                        next = state.child_member_with_name("__awaitee").and_then(|awaitee| resolve_future(&awaitee));
+                    }
+                    frames.push(frame);
+                }
+                None => {
+                    // A hand-written future, such as a timer or a channel receiver, ends the chain.
+                    frames.push(AsyncFrame {
+                        name: type_name,
+                        variables_reference: self.var_refs.create(None, &key, Container::SBValue(future.clone())),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        frames
+    }
+
+    // Finds the source location of an async function, given the type name of its coroutine.
+    fn locate_coroutine(&self, type_name: &str, thread: Option<&SBThread>, frame: &mut AsyncFrame) {
+        let body_name = strip_generic_args(&type_name.replace("_env#", "#")).to_owned();
+        let is_body = |name: Option<&str>| name.map_or(false, |name| strip_generic_args(name) == body_name);
+        // If the function is on the call stack, report the current location.
+        let line_entry = match thread.and_then(|thread| thread.frames().find(|f| is_body(f.function_name()))) {
+            Some(stack_frame) => stack_frame.line_entry(),
+            None => {
+                let functions = self.target.find_functions(&body_name, FunctionNameType::Full);
+                let function = functions.iter().map(|sc| sc.function()).find(|f| f.is_valid());
+                function.and_then(|f| f.start_address().line_entry())
+            }
+        };
+        if let Some(le) = line_entry {
+            if let Some(local_path) = self.map_filespec_to_local(&le.file_spec()) {
+                frame.source = Some(codelldb_types::Source {
+                    path: local_path.to_string_lossy().into_owned(),
+                });
+                frame.line = Some(le.line());
+                frame.column = Some(le.column());
+            }
+        }
+    }
+
+    // Locates tokio's `OwnedTasks` collections reachable from variables of the stopped threads (every worker
+    // thread, as well as the thread blocked in `Runtime::block_on`, holds a reference to the runtime),
+    // and returns the linked lists of tasks they contain.
+    fn find_tokio_task_lists(&self, process: &SBProcess) -> Vec<SBValue> {
+        let options = VariableOptions {
+            arguments: true,
+            locals: true,
+            statics: false,
+            in_scope_only: true,
+        };
+        let mut search = ValueSearch::new("tokio::runtime::task::list::OwnedTasks<");
+        for thread in process.threads() {
+            for frame in thread.frames() {
+                for var in frame.variables(&options).iter() {
+                    search.visit(&var);
+                }
+            }
+        }
+        let mut lists = ValueSearch::new("tokio::util::linked_list::LinkedList<");
+        for owned_tasks in search.found {
+            lists.visit(&owned_tasks);
+        }
+        lists.found
+    }
+
+    // Appends a tokio task with the given header to `tasks`, unless it has completed.
+    // Returns the address of the next task in the list.
+    fn tokio_task(
+        &mut self,
+        header_addr: Address,
+        header_type: &SBType,
+        tasks: &mut Vec<AsyncTask>,
+    ) -> Option<Address> {
+        let address = SBAddress::from_load_address(header_addr, &self.target);
+        let header = self.target.create_value_from_address("header", &address, header_type);
+        // Task memory is laid out as `Cell<F, S>`, where F is the type of the future.  We recover F and S from
+        // the name of the `poll` function in the task's vtable.
+        let poll = find_member(&header, "vtable")?.dereference().child_member_with_name("poll")?;
+        let poll_addr = SBAddress::from_load_address(poll.value_as_unsigned(0), &self.target);
+        let poll_fn = poll_addr.function()?;
+        let args = generic_args(poll_fn.name())?;
+        let [future_type, scheduler_type] = args.as_slice() else {
+            return None;
+        };
+        let cell_type = format!("tokio::runtime::task::core::Cell<{}, {}>", future_type, scheduler_type);
+        let cell_type = self.target.find_first_type(&cell_type)?;
+        let cell = self.target.create_value_from_address("task", &address, &cell_type).non_synthetic_value();
+
+        let core = find_member(&cell, "core")?;
+        let stage = find_member(&core, "stage").and_then(|stage| find_member(&stage, "stage"));
+        if let Some(running) = stage.and_then(|stage| active_variant(&stage)) {
+            if running.type_name().map_or(false, |name| last_path_segment(name) == "Running") {
+                if let Some(future) = running.child_at_index(0).into_result().ok() {
+                    let id = find_member(&core, "task_id").and_then(|id| id.data().read_u64(0).ok());
+                    let frames = self.await_chain(&future, None);
+                    let name = match frames.first() {
+                        Some(frame) => frame.name.clone(),
+                        None => future_type.to_string(),
+                    };
+                    tasks.push(AsyncTask { id, name, frames });
+                }
+            }
+        }
+
+        let owned = find_member(&cell, "trailer").and_then(|trailer| find_member(&trailer, "owned"));
+        read_pointer(&owned.and_then(|owned| find_member(&owned, "next")))
+    }
+}
+
+// Depth-first search for values whose type name starts with a given prefix.
+// To keep the search tractable, only values of tokio types are traversed.
+struct ValueSearch {
+    prefix: &'static str,
+    found: Vec<SBValue>,
+    visited: HashSet<(Address, String)>,
+    budget: usize,
+}
+
+impl ValueSearch {
+    fn new(prefix: &'static str) -> Self {
+        ValueSearch {
+            prefix,
+            found: Vec::new(),
+            visited: HashSet::new(),
+            budget: MAX_SEARCH_NODES,
+        }
+    }
+
+    fn visit(&mut self, value: &SBValue) {
+        if self.budget == 0 {
+            return;
+        }
+        self.budget -= 1;
+        let value = value.non_synthetic_value();
+        let Some(type_name) = value.type_name() else {
+            return;
+        };
+        if !type_name.contains("tokio::") {
+            return;
+        }
+        if !self.visited.insert((value.load_address(), type_name.to_owned())) {
+            return;
+        }
+        if type_name.starts_with(self.prefix) {
+            self.found.push(value.clone());
+            return;
+        }
+        // Boxed slices (e.g. the shards of a `ShardedList`) are represented as `{ data_ptr, length }`.
+        if let (Some(data_ptr), Some(length)) = (
+            value.child_member_with_name("data_ptr"),
+            value.child_member_with_name("length"),
+        ) {
+            let elem_type = data_ptr.type_().pointee_type();
+            let elem_size = elem_type.byte_size();
+            let base = data_ptr.value_as_unsigned(0);
+            let target = value.target();
+            for i in 0..length.value_as_unsigned(0).min(1024) {
+                let addr = SBAddress::from_load_address(base + i * elem_size, &target);
+                self.visit(&target.create_value_from_address("[]", &addr, &elem_type));
+            }
+            return;
+        }
+        for child in value.children() {
+            self.visit(&child);
+        }
+    }
+}
+
+// Strips pointers, references and wrappers like `Pin` and `Box` surrounding a future.
+fn resolve_future(value: &SBValue) -> Option<SBValue> {
+    const WRAPPERS: &[&str] = &[
+        "core::pin::Pin<",
+        "alloc::boxed::Box<",
+        "core::ptr::unique::Unique<",
+        "core::ptr::non_null::NonNull<",
+    ];
+    let mut value = value.non_synthetic_value();
+    for _ in 0..16 {
+        if !value.is_valid() {
+            return None;
+        }
+        let ty = value.type_();
+        if ty.is_pointer_type() || ty.is_reference_type() {
+            value = value.dereference().non_synthetic_value();
+        } else if WRAPPERS.iter().any(|prefix| ty.name().starts_with(prefix)) {
+            value = value.child_at_index(0).non_synthetic_value();
+        } else {
+            return Some(value);
+        }
+    }
+    None
+}
+
+// Returns the active variant of a Rust enum, which includes the state of async function coroutines.
+// LLDB represents enums as a `$variants$` union of `$variant$N` members, where N is the discriminant value
+// and the `$discr$` field holds the actual discriminant.  The variant lacking `$discr$` is the default one.
+fn active_variant(value: &SBValue) -> Option<SBValue> {
+    let variants = find_member(value, "$variants$")?;
+    let mut default = None;
+    for variant in variants.children() {
+        match variant.child_member_with_name("$discr$") {
+            Some(discr) => {
+                let name = format!("$variant${}", discr.value_as_unsigned(u64::MAX));
+                if variant.name() == Some(name.as_str()) {
+                    return variant.child_member_with_name("value");
+                }
+            }
+            None => default = variant.child_member_with_name("value"),
+        }
+    }
+    default
+}
+
+// Finds a member by name, looking through single-field wrappers such as `UnsafeCell`.
+fn find_member(value: &SBValue, name: &str) -> Option<SBValue> {
+    let mut value = value.non_synthetic_value();
+    for _ in 0..8 {
+        if let Some(member) = value.child_member_with_name(name) {
+            return Some(member.non_synthetic_value());
+        }
+        if value.num_children() != 1 {
+            break;
+        }
+        value = value.child_at_index(0).non_synthetic_value();
+    }
+    None
+}
+
+// Reads a pointer-sized value, e.g. `Option<NonNull<T>>`; null pointers map to None.
+fn read_pointer(value: &Option<SBValue>) -> Option<Address> {
+    let value = value.as_ref()?;
+    match value.data().read_address(0) {
+        Ok(0) | Err(_) => None,
+        Ok(addr) => Some(addr),
+    }
+}
+
+fn is_coroutine_body(function_name: &str) -> bool {
+    let name = strip_generic_args(function_name);
+    name.ends_with('}') && (name.contains("::{async_fn#") || name.contains("::{async_block#"))
+}
+
+// Converts the type name of an async function's coroutine into a human-readable name, for example
+// `app::fetch::{async_fn_env#0}` -> `app::fetch` and `app::main::{async_block_env#1}` -> `app::main::{async block}`.
+// Returns None for types that are not coroutines.
+fn coroutine_name(type_name: &str) -> Option<String> {
+    let name = strip_generic_args(type_name);
+    let (parent, last) = name.rsplit_once("::")?;
+    if last.starts_with("{async_fn_env#") {
+        Some(parent.to_owned())
+    } else if last.starts_with("{async_block_env#") || last.starts_with("{async_closure_env#") {
+        Some(format!("{}::{{async block}}", parent))
+    } else {
+        None
+    }
+}
+
+fn last_path_segment(name: &str) -> &str {
+    let name = strip_generic_args(name);
+    name.rsplit_once("::").map_or(name, |(_, last)| last)
+}
+
+// Strips generic arguments from the end of a name, e.g. `foo::bar<T>` -> `foo::bar`.
+fn strip_generic_args(name: &str) -> &str {
+    match generic_args_start(name) {
+        Some(pos) => &name[..pos],
+        None => name,
+    }
+}
+
+// Splits generic arguments at the end of a name, e.g. `poll<A<B, C>, D>` -> ["A<B, C>", "D"].
+fn generic_args(name: &str) -> Option<Vec<&str>> {
+    let start = generic_args_start(name)?;
+    let args = &name[start + 1..name.len() - 1];
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut arg_start = 0;
+    for (pos, ch) in args.char_indices() {
+        match ch {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                result.push(args[arg_start..pos].trim());
+                arg_start = pos + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(args[arg_start..].trim());
+    Some(result)
+}
+
+// Finds the '<' matching the '>' at the end of the name.
+fn generic_args_start(name: &str) -> Option<usize> {
+    if !name.ends_with('>') {
+        return None;
+    }
+    let mut depth = 0;
+    for (pos, ch) in name.char_indices().rev() {
+        match ch {
+            '>' => depth += 1,
+            '<' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_coroutine_names() {
+    assert_eq!(
+        coroutine_name("app::fetch::{async_fn_env#0}").as_deref(),
+        Some("app::fetch")
+    );
+    assert_eq!(
+        coroutine_name("app::Client::get::{async_fn_env#0}<u32>").as_deref(),
+        Some("app::Client::get")
+    );
+    assert_eq!(
+        coroutine_name("app::main::{async_block_env#1}").as_deref(),
+        Some("app::main::{async block}")
+    );
+    assert_eq!(coroutine_name("tokio::time::sleep::Sleep"), None);
+    assert_eq!(last_path_segment("app::fetch::{async_fn_env#0}::Suspend0"), "Suspend0");
+
+    assert!(is_coroutine_body("app::fetch::{async_fn#0}"));
+    assert!(is_coroutine_body("app::run::{async_block#0}<app::Config>"));
+    assert!(!is_coroutine_body("app::fetch"));
+}
+
+#[test]
+fn test_generic_args() {
+    assert_eq!(
+        generic_args("raw::poll<app::main::{async_block_env#0}, alloc::sync::Arc<current_thread::Handle, Global>>"),
+        Some(vec![
+            "app::main::{async_block_env#0}",
+            "alloc::sync::Arc<current_thread::Handle, Global>"
+        ])
+    );
+    assert_eq!(
+        generic_args("foo<(u8, u16), [u32; 2]>"),
+        Some(vec!["(u8, u16)", "[u32; 2]"])
+    );
+    assert_eq!(generic_args("foo"), None);
+    assert_eq!(strip_generic_args("<T as Trait>::foo<Vec<u8>>"), "<T as Trait>::foo");
+}
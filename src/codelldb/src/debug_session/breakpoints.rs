@@ -6,8 +6,9 @@ use crate::handles::Handle;
 use crate::prelude::*;
 use crate::python::{EvalContext, PyObject};
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::mem;
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::path::PathBuf;
 use std::rc::Rc;
@@ -61,6 +62,9 @@ const CPP_CATCH: &str = "cpp_catch";
 const RUST_PANIC: &str = "rust_panic";
 const SWIFT_THROW: &str = "swift_throw";
 
+// Pseudo-variable referring to the exception object or panic payload, accessible as `$exception`.
+pub(super) const EXCEPTION_VAR: &str = "exception";
+
 impl DebugSession {
     pub(super) fn handle_set_breakpoints(
         &mut self,
@@ -73,7 +77,7 @@ impl DebugSession {
         let dasm = args
             .source
             .source_reference
-            .and_then(|source_ref| self.disasm_ranges.find_by_handle(source_ref));
+            .and_then(|source_ref| self.disasm_ranges.find_by_handle(source_ref, &|fs| self.map_filespec_to_local(fs)));
 
         let breakpoints = match (dasm, args.source.adapter_data, args.source.path.as_ref()) {
             (Some(dasm), _, _) => self.set_dasm_breakpoints(dasm, &args.breakpoints),
@@ -87,6 +91,53 @@ impl DebugSession {
         Ok(SetBreakpointsResponseBody { breakpoints })
     }
 
+    pub(super) fn handle_breakpoint_locations(
+        &mut self,
+        args: BreakpointLocationsArguments,
+    ) -> Result<BreakpointLocationsResponseBody, Error> {
+        let mut locations = BTreeSet::new();
+        if let Some(path) = &args.source.path {
+            let file_path_norm = normalize_path(path);
+            let file_name = file_path_norm.file_name();
+            let range = location_range(args.line, args.column, args.end_line, args.end_column);
+            for module in self.target.modules() {
+                for cu in module.compile_units() {
+                    // Skip compile units that don't use the file at all, without looking at their line tables.
+                    if !cu.support_files().any(|f| Some(f.filename().as_os_str()) == file_name) {
+                        continue;
+                    }
+                    for le in cu.line_entries() {
+                        let position = (le.line(), le.column());
+                        if position.0 == 0 || !range.contains(&position) {
+                            continue;
+                        }
+                        // Compare file names first, since mapping paths is comparatively expensive.
+                        let file_spec = le.file_spec();
+                        if Some(file_spec.filename().as_os_str()) != file_name {
+                            continue;
+                        }
+                        if let Some(local_path) = self.map_filespec_to_local(&file_spec) {
+                            if *local_path == file_path_norm {
+                                locations.insert(position);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(BreakpointLocationsResponseBody {
+            breakpoints: locations
+                .into_iter()
+                .map(|(line, column)| BreakpointLocation {
+                    line: line as i64,
+                    column: if column > 0 { Some(column as i64) } else { None },
+                    end_line: None,
+                    end_column: None,
+                })
+                .collect(),
+        })
+    }
+
     fn set_source_breakpoints(
         &mut self,
         file_path: &Path,
@@ -461,6 +512,19 @@ impl DebugSession {
         }
     }
 
+    // If the thread is stopped on one of the exception breakpoints, returns the exception object or panic payload.
+    pub(super) fn exception_value(&self, thread: &SBThread) -> Option<SBValue> {
+        if thread.stop_reason() != StopReason::Breakpoint {
+            return None;
+        }
+        let bp_id = thread.stop_reason_data_at_index(0) as BreakpointID;
+        let breakpoints = self.breakpoints.borrow();
+        match &breakpoints.breakpoint_infos.get(&bp_id)?.kind {
+            BreakpointKind::Exception(exc_name) => get_exception_value(exc_name, thread),
+            _ => None,
+        }
+    }
+
     fn make_bp_info(
         &self,
         bp: SBBreakpoint,
@@ -541,7 +605,7 @@ impl DebugSession {
                 BreakpointKind::Disassembly => {
                     let address = bp_info.breakpoint.location_at_index(0).address();
                     let laddress = address.load_address(&self.target);
-                    if let Ok(dasm) = self.disasm_ranges.from_address(laddress) {
+                    if let Ok(dasm) = self.disasm_ranges.from_address(laddress, &|fs| self.map_filespec_to_local(fs)) {
                         let adapter_data = Some(serde_json::to_value(dasm.adapter_data()).unwrap());
                         Breakpoint {
                             id: Some(bp_info.id as i64),
@@ -595,9 +659,17 @@ impl DebugSession {
 
     // Propagate breakpoint options from BreakpointInfo into the associated SBBreakpoint.
     fn init_bp_actions(&self, bp_info: &BreakpointInfo) {
+        // Conditions that are evaluated via evaluate_expr_in_frame() when the breakpoint is hit:
+        // those referring to `$exception`, which can only be evaluated once the exception value is known,
+        // and simple expressions, when Python is not available or the expression uses Rust syntax.
+        let mut deferred_condition = None;
         // Determine type of the break condition expression.
         let py_condition: Option<(PyObject, EvalContext)> = if let Some(ref condition) = bp_info.condition {
             match expressions::prepare(condition, self.default_expr_type) {
+                Ok(pp_expr) if pp_expr.references_pseudo_var(EXCEPTION_VAR) => {
+                    deferred_condition = Some(pp_expr);
+                    None
+                }
                 Ok(pp_expr) => match &pp_expr {
                     // if native, use that directly,
                     PreparedExpression::Native(expr) => {
@@ -605,27 +677,14 @@ impl DebugSession {
                         None
                     }
                     // otherwise, we'll need to evaluate it ourselves in the breakpoint callback.
-                    PreparedExpression::Simple(expr) | //.
-                    PreparedExpression::Python(expr) => {
-                        if let Some(python) = &self.python {
-                            match python.compile_code(&expr, "<breakpoint condition>") {
-                                Ok(pycode) => {
-                                    let eval_context = if matches!(pp_expr, PreparedExpression::Simple(_)) {
-                                        EvalContext::SimpleExpression
-                                    } else {
-                                        EvalContext::PythonExpression
-                                    };
-                                    Some((pycode, eval_context))
-                                }
-                                Err(err) => {
-                                    self.console_error(format!("Could not parse breakpoint condition:\n{}", err));
-                                    None
-                                }
-                            }
-                        } else {
-                            None
-                        }
+                    PreparedExpression::Simple(expr) if self.python.is_none() || expr.has_rust_syntax() => {
+                        deferred_condition = Some(pp_expr);
+                        None
+                    }
+                    PreparedExpression::Simple(expr) => {
+                        self.compile_bp_condition(&expr.to_python(), EvalContext::SimpleExpression)
                     }
+                    PreparedExpression::Python(expr) => self.compile_bp_condition(expr, EvalContext::PythonExpression),
                 },
                 Err(err) => {
                     self.console_error(format!("Could not parse breakpoint condition:\n{}", err));
@@ -640,16 +699,31 @@ impl DebugSession {
         let rt = tokio::runtime::Handle::current();
         bp_info.breakpoint.set_callback(move |process, thread, location| {
             debug!("Callback for breakpoint location {:?}", location);
-            rt.block_on(shared_session.map(|s| s.on_breakpoint_hit(process, thread, location, &py_condition)))
+            rt.block_on(
+                shared_session
+                    .map(|s| s.on_breakpoint_hit(process, thread, location, &py_condition, &deferred_condition)),
+            )
         });
     }
 
+    fn compile_bp_condition(&self, expr: &str, eval_context: EvalContext) -> Option<(PyObject, EvalContext)> {
+        let python = self.python.as_ref()?;
+        match python.compile_code(expr, "<breakpoint condition>") {
+            Ok(pycode) => Some((pycode, eval_context)),
+            Err(err) => {
+                self.console_error(format!("Could not parse breakpoint condition:\n{}", err));
+                None
+            }
+        }
+    }
+
     fn on_breakpoint_hit(
         &self,
         _process: &SBProcess,
         thread: &SBThread,
         location: &SBBreakpointLocation,
         py_condition: &Option<(PyObject, EvalContext)>,
+        deferred_condition: &Option<PreparedExpression>,
     ) -> bool {
         let mut breakpoints = self.breakpoints.borrow_mut();
         let bp_info = breakpoints.breakpoint_infos.get_mut(&location.breakpoint().id()).unwrap();
@@ -695,6 +769,38 @@ impl DebugSession {
             }
         }
 
+        if let Some(condition) = deferred_condition {
+            let frame = thread.frame_at_index(0);
+            let bound_condition;
+            let condition = if condition.references_pseudo_var(EXCEPTION_VAR) {
+                bound_condition = match &bp_info.kind {
+                    BreakpointKind::Exception(exc_name) => get_exception_value(exc_name, thread)
+                        .and_then(|exception| bind_exception_var(condition, &exception)),
+                    _ => None,
+                };
+                match &bound_condition {
+                    Some(condition) => condition,
+                    None => {
+                        self.console_error("Could not evaluate breakpoint condition:\n$exception is not available.");
+                        return true;
+                    }
+                }
+            } else {
+                condition
+            };
+            match self.evaluate_expr_in_frame(condition, Some(&frame)) {
+                Ok(val) => {
+                    if val.value_as_unsigned(0) == 0 {
+                        return false;
+                    }
+                }
+                Err(err) => {
+                    self.console_error(format!("Could not evaluate breakpoint condition:\n{}", err));
+                    return true;
+                }
+            }
+        }
+
         // We maintain our own hit count for consistency between native and python conditions:
         // LLDB doesn't count breakpoint hits for which native condition evaluated to false,
         // however it does count ones where the callback was invoked, even if it had returned false.
@@ -716,9 +822,11 @@ impl DebugSession {
         }
 
         // If we are supposed to stop and there's a log message, evaluate and print the message, but don't stop.
-        if let Some(ref log_message) = bp_info.log_message {
+        if let Some(log_message) = bp_info.log_message.clone() {
+            // Release the borrow, since evaluating `$exception` needs to look up the breakpoint info.
+            drop(breakpoints);
             let frame = thread.frame_at_index(0);
-            let message = self.format_logpoint_message(log_message, &frame);
+            let message = self.format_logpoint_message(&log_message, &frame);
             self.console_message(message);
             return false;
         }
@@ -763,7 +871,7 @@ impl DebugSession {
             let (pp_expr, format_spec) = expressions::prepare_with_format(expr, self.default_expr_type).unwrap();
             let sbval = self.evaluate_expr_in_frame(&pp_expr, Some(frame))?;
             let sbval = self.apply_format_spec(sbval, &format_spec)?;
-            let str_val = self.get_var_summary(&sbval, false);
+            let str_val = self.get_formatted_summary(&sbval, &format_spec, false);
             Ok(str_val)
         })
     }
@@ -880,3 +988,86 @@ impl DebugSession {
         Ok(())
     }
 }
+
+// Locates the thrown object or the panic payload, given the exception breakpoint the thread has stopped on.
+fn get_exception_value(exc_name: &str, thread: &SBThread) -> Option<SBValue> {
+    let frame = thread.frame_at_index(0);
+    let target = thread.process().target();
+    match exc_name {
+        CPP_THROW => thread.current_exception().or_else(|| {
+            // __cxa_throw(void* thrown_object, std::type_info* tinfo, void (*dest)(void*))
+            let object = frame.find_register("arg1")?.value_as_unsigned(0);
+            let tinfo = frame.find_register("arg2")?.value_as_unsigned(0);
+            let symbol = SBAddress::from_load_address(tinfo, &target).symbol()?;
+            let ty = target.find_first_type(symbol.name().strip_prefix("typeinfo for ")?)?;
+            let addr = SBAddress::from_load_address(object, &target);
+            target.create_value_from_address(EXCEPTION_VAR, &addr, &ty).check()
+        }),
+        CPP_CATCH | SWIFT_THROW => thread.current_exception(),
+        RUST_PANIC => {
+            // rust_panic(payload: &mut dyn PanicPayload)
+            let args = frame.variables(&VariableOptions {
+                arguments: true,
+                locals: false,
+                statics: false,
+                in_scope_only: false,
+            });
+            let payload = args.iter().next();
+            // Without debug info for std, fall back to the raw payload pointer.
+            payload.or_else(|| {
+                let void_ptr = target.get_basic_type(BasicType::Void).pointer_type();
+                frame.find_register("arg1")?.cast(&void_ptr).check()
+            })
+        }
+        _ => None,
+    }
+}
+
+// Replaces references to `$exception` with a native expression that evaluates to the exception value.
+pub(super) fn bind_exception_var(expression: &PreparedExpression, exception: &SBValue) -> Option<PreparedExpression> {
+    let type_name = exception.type_name()?;
+    let native_expr = match exception.address() {
+        Some(addr) => {
+            let addr = addr.load_address(&exception.target());
+            format!("(*({}*){:#x})", type_name, addr)
+        }
+        None => format!("(({}){:#x})", type_name, exception.value_as_unsigned(0)),
+    };
+    Some(expression.replace_pseudo_var(EXCEPTION_VAR, &native_expr))
+}
+
+// Range of (line, column) positions requested by `breakpointLocations`.  Columns of 0 or u32::MAX stand for
+// the start and the end of a line respectively.
+fn location_range(
+    line: i64,
+    column: Option<i64>,
+    end_line: Option<i64>,
+    end_column: Option<i64>,
+) -> RangeInclusive<(u32, u32)> {
+    let start = (line as u32, column.map_or(0, |c| c as u32));
+    let end = (
+        end_line.unwrap_or(line) as u32,
+        end_column.map_or(u32::MAX, |c| c as u32),
+    );
+    start..=end
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_location_range() {
+    // A single line.
+    let range = location_range(10, None, None, None);
+    assert!(range.contains(&(10, 0)));
+    assert!(range.contains(&(10, 25)));
+    assert!(!range.contains(&(9, 100)));
+    assert!(!range.contains(&(11, 0)));
+
+    // Multiple lines, with columns.
+    let range = location_range(10, Some(5), Some(12), Some(8));
+    assert!(!range.contains(&(10, 4)));
+    assert!(range.contains(&(10, 5)));
+    assert!(range.contains(&(11, 1)));
+    assert!(range.contains(&(12, 8)));
+    assert!(!range.contains(&(12, 9)));
+}
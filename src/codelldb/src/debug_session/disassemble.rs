@@ -57,30 +57,30 @@ impl super::DebugSession {
             start_idx = 0;
         }
         let resolve_symbols = args.resolve_symbols.unwrap_or(true);
+        let options = self.disasm_ranges.options();
         let mut last_line_entry = None;
         for (arange, instr) in &instructions[start_idx as usize..end_idx as usize] {
             let dis_instsr = match instr {
                 Ok(sbinstr) => {
-                    let mnemonic = sbinstr.mnemonic(&self.target);
+                    let mnemonic = options.mnemonic(sbinstr.mnemonic(&self.target));
                     let operands = sbinstr.operands(&self.target);
-                    let comment = sbinstr.comment(&self.target);
+                    let annotation = match resolve_symbols {
+                        true => disassembly::annotate_instruction(&self.target, sbinstr, options),
+                        false => None,
+                    };
+                    let comment = disassembly::compose_comment(sbinstr.comment(&self.target), annotation.as_deref());
                     let comment_sep = if comment.is_empty() { "" } else { "  ; " };
                     let instruction_str = format!("{:<6} {}{}{}", mnemonic, operands, comment_sep, comment);
 
                     let instr_bytes = &buffer[(arange.start - start_addr) as usize..(arange.end - start_addr) as usize];
-                    let mut bytes_str = String::with_capacity(instr_bytes.len() * 3);
-                    for (i, b) in instr_bytes.iter().enumerate() {
-                        if i >= self.max_instr_bytes {
-                            bytes_str.push('>');
-                            break;
-                        }
-                        let _ = write!(bytes_str, "{:02X} ", b);
-                    }
-                    let bytes_str = format!("{bytes_str:<width$}", width = self.max_instr_bytes * 3 + 2);
+                    let bytes_str = match options.show_bytes {
+                        true => Some(options.format_bytes(instr_bytes)),
+                        false => None,
+                    };
 
                     let mut dis_instr = DisassembledInstruction {
                         address: format!("0x{:X}", arange.start),
-                        instruction_bytes: Some(bytes_str),
+                        instruction_bytes: bytes_str,
                         instruction: instruction_str,
                         ..Default::default()
                     };
@@ -106,7 +106,7 @@ impl super::DebugSession {
 
                     if resolve_symbols {
                         if let Some(symbol) = address.symbol() {
-                            dis_instr.symbol = Some(symbol.name().into());
+                            dis_instr.symbol = Some(options.symbol_name(&symbol).into());
                         }
                     }
                     dis_instr
@@ -148,20 +148,19 @@ impl super::DebugSession {
     // Read a memory range while handling unreadable regions.
     // If unreadable regions are encountered, the returned range will be a subset of the requested one.
     fn read_memory(&self, start_address: Address, count: u64) -> (Address, Vec<u8>) {
-        let process = self.target.process();
         let mut buffer = Vec::new();
         buffer.resize(count as usize, 0);
-        if let Ok(read) = process.read_memory(start_address, &mut buffer) {
+        if let Ok(read) = self.read_target_memory(start_address, &mut buffer) {
             buffer.truncate(read);
             return (start_address, buffer);
         }
         // Binary search for the boundary between unreadable and readable areas
         let first_readable = partition_point(start_address..start_address + count, |addr| {
             let mut b = [0u8; 1];
-            process.read_memory(addr, &mut b).is_err()
+            self.read_target_memory(addr, &mut b).is_err()
         });
         buffer.resize((start_address + count as u64 - first_readable) as usize, 0);
-        match process.read_memory(first_readable, &mut buffer) {
+        match self.read_target_memory(first_readable, &mut buffer) {
             Ok(read) => {
                 buffer.truncate(read);
                 (first_readable, buffer)
@@ -175,7 +174,7 @@ impl super::DebugSession {
     }
 
     fn disassemble_bytes(&self, base_addr: Address, buffer: &[u8]) -> Vec<(Range<Address>, Result<SBInstruction, u8>)> {
-        let flavor = self.target.disassembly_flavor();
+        let flavor = self.disasm_ranges.options().flavor(&self.target);
         let mut base_addr = base_addr;
         let mut buffer = buffer;
         let mut dis_instructions = Vec::new();
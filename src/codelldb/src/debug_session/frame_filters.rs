@@ -0,0 +1,186 @@
+use crate::prelude::*;
+
+use super::variables::Container;
+
+use std::path::Path;
+
+use adapter_protocol::*;
+use lldb::*;
+use regex_lite::Regex;
+
+pub(super) struct FrameFilterRule {
+    function: Option<Regex>,
+    module: Option<Regex>,
+    presentation: FramePresentation,
+    label: String,
+}
+
+impl FrameFilterRule {
+    fn new(filter: &FrameFilter) -> Result<FrameFilterRule, Error> {
+        let compile = |pattern: &Option<String>| -> Result<Option<Regex>, Error> {
+            match pattern {
+                None => Ok(None),
+                Some(pattern) => match Regex::new(pattern) {
+                    Ok(regex) => Ok(Some(regex)),
+                    Err(err) => bail!(blame_user(str_error(format!(
+                        "Invalid frame filter pattern \"{}\": {}",
+                        pattern, err
+                    )))),
+                },
+            }
+        };
+        Ok(FrameFilterRule {
+            function: compile(&filter.function)?,
+            module: compile(&filter.module)?,
+            presentation: filter.presentation.unwrap_or(FramePresentation::Subtle),
+            label: filter.label.clone().unwrap_or_else(|| "filtered".into()),
+        })
+    }
+
+    fn matches(&self, function: Option<&str>, module: Option<&Path>) -> bool {
+        let function_matches = match &self.function {
+            Some(regex) => function.map_or(false, |f| regex.is_match(f)),
+            None => true,
+        };
+        let module_matches = match &self.module {
+            Some(regex) => module.map_or(false, |m| regex.is_match(&m.to_string_lossy())),
+            None => true,
+        };
+        function_matches && module_matches
+    }
+}
+
+// An entry of the call stack view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum DisplayFrame {
+    Frame(u32),
+    // A run of `count` frames starting at `first`, folded by the filter rule with index `rule`.
+    Collapsed { first: u32, count: u32, rule: usize },
+}
+
+impl super::DebugSession {
+    pub(super) fn init_frame_filters(&mut self, filters: &[FrameFilter]) -> Result<(), Error> {
+        self.frame_filters = filters.iter().map(FrameFilterRule::new).collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    // Returns index of the first filter rule matching the frame.
+    fn match_frame_filter(&self, frame: &SBFrame) -> Option<usize> {
+        if self.frame_filters.is_empty() {
+            return None;
+        }
+        let module = frame.module();
+        let module_path = if module.is_valid() { Some(module.file_spec().path()) } else { None };
+        self.frame_filters
+            .iter()
+            .position(|rule| rule.matches(frame.function_name(), module_path.as_deref()))
+    }
+
+    pub(super) fn frame_presentation(&self, frame: &SBFrame) -> FramePresentation {
+        match self.match_frame_filter(frame) {
+            Some(rule) => self.frame_filters[rule].presentation,
+            None => FramePresentation::Normal,
+        }
+    }
+
+    // When there are collapsing rules, the call stack view no longer maps one-to-one onto the thread's frames,
+    // so we need to lay out the entire stack.
+    pub(super) fn frame_layout(&self, thread: &SBThread) -> Option<Vec<DisplayFrame>> {
+        let collapse = self
+            .frame_filters
+            .iter()
+            .map(|r| r.presentation == FramePresentation::Collapse)
+            .collect::<Vec<_>>();
+        if !collapse.contains(&true) {
+            return None;
+        }
+        let matches = thread.frames().map(|frame| self.match_frame_filter(&frame)).collect::<Vec<_>>();
+        Some(layout_frames(&matches, &collapse))
+    }
+
+    pub(super) fn make_collapsed_frame(
+        &mut self,
+        thread: &SBThread,
+        first: u32,
+        count: u32,
+        rule: usize,
+    ) -> StackFrame {
+        // Label frames can't be selected, but they still need a unique id.
+        let key = format!("[{},{}+{}]", thread.index_id(), first, count);
+        let handle = self.var_refs.create(None, &key, Container::StackFrame(thread.frame_at_index(first)));
+        StackFrame {
+            id: handle,
+            name: format!("[{} {} frames]", count, self.frame_filters[rule].label),
+            presentation_hint: Some(StackFramePresentationHint::Label),
+            ..Default::default()
+        }
+    }
+}
+
+// Fold runs of consecutive frames matched by the same collapsing rule.
+// `matches` contains indices of rules matching each frame.  The top frame is never folded.
+fn layout_frames(matches: &[Option<usize>], collapse: &[bool]) -> Vec<DisplayFrame> {
+    let mut result = Vec::new();
+    let mut index = 0;
+    while index < matches.len() {
+        let run_len = match matches[index] {
+            Some(rule) if index > 0 && collapse[rule] => {
+                matches[index..].iter().take_while(|m| **m == Some(rule)).count()
+            }
+            _ => 1,
+        };
+        if run_len > 1 {
+            result.push(DisplayFrame::Collapsed {
+                first: index as u32,
+                count: run_len as u32,
+                rule: matches[index].unwrap(),
+            });
+        } else {
+            result.push(DisplayFrame::Frame(index as u32));
+        }
+        index += run_len;
+    }
+    result
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_layout_frames() {
+    use DisplayFrame::*;
+    let collapse = [true, false];
+    assert_eq!(
+        layout_frames(
+            &[Some(0), Some(0), None, Some(0), Some(0), Some(0), Some(1), Some(1)],
+            &collapse
+        ),
+        [
+            Frame(0),
+            Frame(1),
+            Frame(2),
+            Collapsed {
+                first: 3,
+                count: 3,
+                rule: 0
+            },
+            Frame(6),
+            Frame(7)
+        ]
+    );
+    assert_eq!(
+        layout_frames(&[None, Some(0), None], &collapse),
+        [Frame(0), Frame(1), Frame(2)]
+    );
+    assert_eq!(
+        layout_frames(&[None, Some(0), Some(0)], &collapse),
+        [
+            Frame(0),
+            Collapsed {
+                first: 1,
+                count: 2,
+                rule: 0
+            }
+        ]
+    );
+    assert!(layout_frames(&[], &collapse).is_empty());
+}
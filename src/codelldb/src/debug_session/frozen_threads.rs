@@ -0,0 +1,82 @@
+use crate::prelude::*;
+
+use adapter_protocol::*;
+use lldb::*;
+
+impl super::DebugSession {
+    pub(super) fn handle_freeze_thread(&mut self, args: FreezeThreadRequest) -> Result<(), Error> {
+        self.ensure_process_stopped()?;
+        let thread = self.thread_by_id(args.thread_id)?;
+        thread.suspend()?;
+        self.frozen_threads.insert(thread.thread_id());
+        self.refresh_client_display(Some(thread.thread_id()));
+        Ok(())
+    }
+
+    pub(super) fn handle_thaw_thread(&mut self, args: FreezeThreadRequest) -> Result<(), Error> {
+        self.ensure_process_stopped()?;
+        let thread = self.thread_by_id(args.thread_id)?;
+        thread.resume()?;
+        self.frozen_threads.remove(&thread.thread_id());
+        self.refresh_client_display(Some(thread.thread_id()));
+        Ok(())
+    }
+
+    // Re-suspends frozen threads before the process is resumed, since some thread plans
+    // may override the suspended state.  Threads that have exited are forgotten.
+    pub(super) fn apply_frozen_threads(&mut self) {
+        if self.frozen_threads.is_empty() {
+            return;
+        }
+        let process = self.target.process();
+        self.frozen_threads.retain(|&tid| match process.thread_by_id(tid) {
+            Some(thread) => {
+                if !thread.is_suspended() {
+                    log_errors!(thread.suspend());
+                }
+                true
+            }
+            None => false,
+        });
+    }
+
+    // Refreshing the client's display of a running process would make it appear paused.
+    fn ensure_process_stopped(&self) -> Result<(), Error> {
+        if self.target.process().state().is_running() {
+            bail!(blame_user(str_error(
+                "Threads may only be frozen or thawed while the process is stopped."
+            )));
+        }
+        Ok(())
+    }
+
+    // Resuming a process with all threads suspended would never stop again.
+    pub(super) fn ensure_not_all_frozen(&self) -> Result<(), Error> {
+        if self.frozen_threads.is_empty() {
+            return Ok(());
+        }
+        let process = self.target.process();
+        if process.threads().all(|thread| self.frozen_threads.contains(&thread.thread_id())) {
+            bail!(blame_user(str_error(
+                "All threads are frozen. Thaw a thread before continuing."
+            )));
+        }
+        Ok(())
+    }
+
+    pub(super) fn ensure_not_frozen(&self, thread: &SBThread) -> Result<(), Error> {
+        if self.frozen_threads.contains(&thread.thread_id()) {
+            bail!(blame_user(str_error("This thread is frozen. Thaw it before stepping.")));
+        }
+        Ok(())
+    }
+
+    pub(super) fn thaw_all_threads(&mut self) {
+        let process = self.target.process();
+        for tid in self.frozen_threads.drain() {
+            if let Some(thread) = process.thread_by_id(tid) {
+                log_errors!(thread.resume());
+            }
+        }
+    }
+}
@@ -53,12 +53,53 @@ impl super::DebugSession {
         let self_ref = self.self_ref.clone();
         let fut = async move {
             log_errors!(config_done_recv.recv().await);
+            if args.stop_before_launch.unwrap_or(false) {
+                return self_ref.map(|s| s.defer_launch(args)).await;
+            }
             self_ref.map(|s| s.create_terminal(&args)).await.await;
             self_ref.map(|s| s.complete_launch(args)).await
         };
         Err(AsyncResponse(Box::new(fut)).into())
     }
 
+    // Complete the launch request without starting the debuggee.  Until `_run` is received, the main executable is
+    // mapped at its file addresses, so that its code and static data may be inspected without a process.
+    // Shared libraries are left unmapped, since their file addresses would overlap those of the executable.
+    fn defer_launch(&mut self, args: LaunchRequestArguments) -> Result<ResponseBody, Error> {
+        if let Some(module) = self.main_module() {
+            log_errors!(self.target.set_module_load_address(&module, 0));
+        }
+        self.pending_launch = Some(args);
+        self.console_message("The debuggee has not been launched yet; addresses shown are file addresses.");
+        Ok(ResponseBody::launch)
+    }
+
+    pub(super) fn handle_run(&mut self) -> Result<ResponseBody, Error> {
+        let args = match self.pending_launch.take() {
+            Some(args) => args,
+            None => bail!(blame_user(str_error("The debuggee has already been launched."))),
+        };
+        if let Some(module) = self.main_module() {
+            log_errors!(self.target.clear_module_load_address(&module));
+        }
+        let self_ref = self.self_ref.clone();
+        let fut = async move {
+            self_ref.map(|s| s.create_terminal(&args)).await.await;
+            self_ref.map(|s| s.complete_launch(args)).await.map(|_| ResponseBody::_run)
+        };
+        Err(AsyncResponse(Box::new(fut)).into())
+    }
+
+    // LLDB keeps the executable as the first module of a target.
+    fn main_module(&self) -> Option<SBModule> {
+        let module = self.target.module_at_index(0);
+        if module.is_valid() {
+            Some(module)
+        } else {
+            None
+        }
+    }
+
     fn complete_launch(&mut self, args: LaunchRequestArguments) -> Result<ResponseBody, Error> {
         let mut launch_info = self.target.launch_info();
 
@@ -404,6 +445,8 @@ impl super::DebugSession {
                 if terminate {
                     process.kill()?;
                 } else {
+                    // Don't leave the debuggee wedged.
+                    self.thaw_all_threads();
                     process.detach(false)?;
                 }
             }
@@ -433,7 +476,7 @@ impl super::DebugSession {
     fn set_target(&mut self, target: SBTarget) {
         self.debugger.listener().stop_listening_for_events(&self.target.broadcaster(), !0);
         self.target = target;
-        self.disasm_ranges = disassembly::DisassembledRanges::new(&self.target);
+        self.disasm_ranges = disassembly::DisassembledRanges::new(&self.target, self.disasm_ranges.options().clone());
         self.debugger.listener().start_listening_for_events(&self.target.broadcaster(), !0);
     }
 
@@ -539,7 +582,10 @@ impl super::DebugSession {
                 ),
                 Expressions::Native => (),
             }
-            self.default_expr_type = Expressions::Native;
+            // Simple expressions can still be evaluated natively.
+            if let Expressions::Python = self.default_expr_type {
+                self.default_expr_type = Expressions::Simple;
+            }
         }
 
         if let Some(source_map) = &args_common.source_map {
@@ -551,6 +597,18 @@ impl super::DebugSession {
             None => env::current_dir()?,
         });
 
+        if let Some(just_my_code) = &args_common.just_my_code {
+            self.init_just_my_code(just_my_code)?;
+        }
+
+        if let Some(frame_filters) = &args_common.frame_filters {
+            self.init_frame_filters(frame_filters)?;
+        }
+
+        if let Some(natvis) = &args_common.natvis {
+            self.load_natvis(natvis);
+        }
+
         if let Some(true) = &args_common.reverse_debugging {
             self.send_event(EventBody::capabilities(CapabilitiesEventBody {
                 capabilities: Capabilities {
@@ -0,0 +1,340 @@
+use crate::prelude::*;
+
+use std::collections::{HashMap, HashSet};
+
+use adapter_protocol::*;
+use lldb::*;
+
+// How deep into a thread's stack to look for lock primitives, and then for their callers.
+const MAX_SCAN_DEPTH: u32 = 24;
+
+// Frames of these are considered a part of the lock implementation, rather than the code requesting the lock.
+const LOCK_LIBRARY_PREFIXES: &[&str] = &[
+    "std::sync::",
+    "std::sys::",
+    "parking_lot::",
+    "parking_lot_core::",
+    "lock_api::",
+];
+
+impl super::DebugSession {
+    pub(super) fn handle_lock_analysis(&mut self) -> Result<LockAnalysisResponse, Error> {
+        let process = self.target.process();
+        if !process.is_valid() {
+            bail!(blame_user(str_error("No process.")));
+        }
+        if process.state().is_running() {
+            bail!(blame_nobody(str_error("Not available while the process is running.")));
+        }
+
+        let mut waits = Vec::new();
+        for thread in process.threads() {
+            if self.current_cancellation.is_cancelled() {
+                bail!("canceled");
+            }
+            if let Some(wait) = self.find_lock_wait(&thread) {
+                waits.push(wait);
+            }
+        }
+
+        let wait_for: HashMap<i64, i64> =
+            waits.iter().filter_map(|w| Some((w.thread_id, w.owner_thread_id?))).collect();
+        let deadlocks = find_cycles(&wait_for)
+            .into_iter()
+            .map(|cycle| Deadlock {
+                description: describe_deadlock(&cycle, &waits),
+                thread_ids: cycle,
+            })
+            .collect();
+        Ok(LockAnalysisResponse { waits, deadlocks })
+    }
+
+    // Checks whether the thread is blocked in a lock primitive.
+    fn find_lock_wait(&self, thread: &SBThread) -> Option<LockWait> {
+        // Find the run of lock primitive frames nearest to the top of the stack, e.g.
+        // `futex_wait` <- `__lll_lock_wait` <- `pthread_mutex_lock`, and use the outermost of them.
+        let mut found: Option<(LockKind, String)> = None;
+        let mut lock_addr = None;
+        let mut index = 0;
+        while index < MAX_SCAN_DEPTH {
+            let frame = thread.frame_at_index(index);
+            if !frame.is_valid() {
+                break;
+            }
+            let function = frame.function_name().unwrap_or_default();
+            match (classify_lock_function(function), &found) {
+                (Some(kind), None) => found = Some((kind, function.to_owned())),
+                (Some(kind), Some((found_kind, _))) if kind == *found_kind => found = Some((kind, function.to_owned())),
+                (_, Some(_)) => break,
+                (None, None) => {}
+            }
+            if found.is_some() && lock_addr.is_none() {
+                lock_addr = lock_argument(&frame);
+            }
+            index += 1;
+        }
+        let (kind, function) = found?;
+
+        let owner_thread_id = match lock_addr {
+            Some(addr) => self.lock_owner(kind, &function, addr),
+            None => None,
+        };
+        Some(LockWait {
+            thread_id: thread.thread_id() as i64,
+            thread_name: thread.name().map(Into::into),
+            kind,
+            function,
+            lock_reference: lock_addr.map(|addr| format!("0x{:X}", addr)),
+            owner_thread_id,
+            caller: self.lock_caller(thread, index),
+        })
+    }
+
+    // glibc records the TID of the owner in mutexes (`__data.__owner`) and in write-locked rwlocks
+    // (`__data.__cur_writer`).  Other lock implementations don't track ownership.
+    fn lock_owner(&self, kind: LockKind, function: &str, addr: Address) -> Option<i64> {
+        let base_name = function.split('@').next().unwrap_or(function).trim_start_matches('_');
+        if !self.target.triple().contains("linux")
+            || !(base_name.starts_with("pthread_") || base_name == "lll_lock_wait")
+        {
+            return None;
+        }
+        let offset = match kind {
+            LockKind::Mutex => 8,
+            LockKind::RwLock if self.target.address_byte_size() == 8 => 24,
+            _ => return None,
+        };
+        let mut buffer = [0; 4];
+        self.target.process().read_memory(addr + offset, &mut buffer).ok()?;
+        let tid = match self.target.byte_order() {
+            ByteOrder::Big => i32::from_be_bytes(buffer),
+            _ => i32::from_le_bytes(buffer),
+        };
+        // Robust mutexes may have FUTEX_OWNER_DIED and FUTEX_WAITERS bits set.
+        match tid & 0x3FFFFFFF {
+            0 => None,
+            tid => Some(tid as i64),
+        }
+    }
+
+    // Finds the code that requested the lock, preferring frames with source.
+    fn lock_caller(&self, thread: &SBThread, start: u32) -> Option<CodeLocation> {
+        let mut fallback = None;
+        for index in start..start + MAX_SCAN_DEPTH {
+            let frame = thread.frame_at_index(index);
+            if !frame.is_valid() {
+                break;
+            }
+            let location = self.frame_location(&frame);
+            if LOCK_LIBRARY_PREFIXES.iter().any(|prefix| location.function.starts_with(prefix)) {
+                continue;
+            }
+            if location.source.is_some() {
+                return Some(location);
+            }
+            if fallback.is_none() {
+                fallback = Some(location);
+            }
+        }
+        fallback
+    }
+}
+
+// Address of the lock object passed as the first argument of a lock primitive.
+// Requires debug info for the function, which is often available for Rust std and parking_lot, less so for libc.
+fn lock_argument(frame: &SBFrame) -> Option<Address> {
+    let options = VariableOptions {
+        arguments: true,
+        locals: false,
+        statics: false,
+        in_scope_only: false,
+    };
+    let args = frame.variables(&options);
+    let arg = args.iter().next()?;
+    let ty = arg.type_();
+    if !ty.is_pointer_type() && !ty.is_reference_type() {
+        return None;
+    }
+    match arg.value_as_unsigned(0) {
+        0 => None,
+        addr => Some(addr),
+    }
+}
+
+fn classify_lock_function(name: &str) -> Option<LockKind> {
+    // glibc exports versioned aliases with varying numbers of leading underscores, e.g. `___pthread_mutex_lock`.
+    let base_name = name.split('@').next().unwrap_or(name).trim_start_matches('_');
+    match base_name {
+        "pthread_mutex_lock"
+        | "pthread_mutex_lock_full"
+        | "pthread_mutex_timedlock"
+        | "pthread_mutex_clocklock"
+        | "pthread_mutex_clocklock_common"
+        | "lll_lock_wait"
+        | "lll_lock_wait_private"
+        | "pthread_mutex_firstfit_lock_slow"
+        | "psynch_mutexwait" => return Some(LockKind::Mutex),
+        "pthread_rwlock_rdlock"
+        | "pthread_rwlock_wrlock"
+        | "pthread_rwlock_timedrdlock"
+        | "pthread_rwlock_timedwrlock"
+        | "pthread_rwlock_clockrdlock"
+        | "pthread_rwlock_clockwrlock"
+        | "pthread_rwlock_rdlock_full"
+        | "pthread_rwlock_wrlock_full"
+        | "pthread_rwlock_rdlock_full64"
+        | "pthread_rwlock_wrlock_full64"
+        | "psynch_rw_rdlock"
+        | "psynch_rw_wrlock" => return Some(LockKind::RwLock),
+        "pthread_cond_wait"
+        | "pthread_cond_timedwait"
+        | "pthread_cond_clockwait"
+        | "pthread_cond_wait_common"
+        | "psynch_cvwait" => return Some(LockKind::Condvar),
+        _ => {}
+    }
+    // Slow paths of the futex-based locks in Rust std and of parking_lot.
+    const RUST_LOCKS: &[(&str, LockKind)] = &[
+        ("Mutex::lock_contended", LockKind::Mutex),
+        ("RwLock::read_contended", LockKind::RwLock),
+        ("RwLock::write_contended", LockKind::RwLock),
+        ("Condvar::wait_optional_timeout", LockKind::Condvar),
+        ("RawMutex::lock_slow", LockKind::Mutex),
+        ("RawRwLock::lock_exclusive_slow", LockKind::RwLock),
+        ("RawRwLock::lock_shared_slow", LockKind::RwLock),
+        ("RawRwLock::lock_upgradable_slow", LockKind::RwLock),
+        ("Condvar::wait_until_internal", LockKind::Condvar),
+    ];
+    if name.starts_with("std::sys::") || name.starts_with("parking_lot::") {
+        for (suffix, kind) in RUST_LOCKS {
+            if name.ends_with(suffix) {
+                return Some(*kind);
+            }
+        }
+    }
+    None
+}
+
+// Finds cycles in the wait-for graph.  Since a thread can wait for at most one lock, each node has at most
+// one outgoing edge: from the waiting thread to the lock owner.
+fn find_cycles(wait_for: &HashMap<i64, i64>) -> Vec<Vec<i64>> {
+    let mut cycles = Vec::new();
+    let mut visited = HashSet::new();
+    let mut starts = wait_for.keys().copied().collect::<Vec<_>>();
+    starts.sort();
+    for start in starts {
+        let mut path = Vec::new();
+        let mut node = start;
+        while !visited.contains(&node) {
+            if let Some(pos) = path.iter().position(|n| *n == node) {
+                cycles.push(path[pos..].to_vec());
+                break;
+            }
+            path.push(node);
+            match wait_for.get(&node) {
+                Some(owner) => node = *owner,
+                None => break,
+            }
+        }
+        visited.extend(path);
+    }
+    cycles
+}
+
+fn describe_deadlock(cycle: &[i64], waits: &[LockWait]) -> String {
+    let find_wait = |tid: i64| waits.iter().find(|w| w.thread_id == tid);
+    let thread_name = |tid: i64| match find_wait(tid).and_then(|w| w.thread_name.as_deref()) {
+        Some(name) => format!("thread {} \"{}\"", tid, name),
+        None => format!("thread {}", tid),
+    };
+    let mut parts = Vec::new();
+    for (i, tid) in cycle.iter().enumerate() {
+        let Some(wait) = find_wait(*tid) else {
+            continue;
+        };
+        let kind = match wait.kind {
+            LockKind::Mutex => "mutex",
+            LockKind::RwLock => "rwlock",
+            LockKind::Condvar => "condvar",
+        };
+        let lock = wait.lock_reference.as_deref().unwrap_or("?");
+        let owner = cycle[(i + 1) % cycle.len()];
+        let mut part = format!(
+            "{} waits for {} {} held by {}",
+            thread_name(*tid),
+            kind,
+            lock,
+            thread_name(owner)
+        );
+        if let Some(caller) = &wait.caller {
+            part.push_str(&format!(" (in {}", caller.function));
+            if let (Some(source), Some(line)) = (&caller.source, caller.line) {
+                part.push_str(&format!(" at {}:{}", source.path, line));
+            }
+            part.push(')');
+        }
+        parts.push(part);
+    }
+    parts.join("; ")
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_classify_lock_function() {
+    assert_eq!(classify_lock_function("___pthread_mutex_lock"), Some(LockKind::Mutex));
+    assert_eq!(
+        classify_lock_function("pthread_mutex_lock@@GLIBC_2.2.5"),
+        Some(LockKind::Mutex)
+    );
+    assert_eq!(
+        classify_lock_function("__pthread_rwlock_wrlock_full64"),
+        Some(LockKind::RwLock)
+    );
+    assert_eq!(
+        classify_lock_function("__pthread_cond_wait_common"),
+        Some(LockKind::Condvar)
+    );
+    assert_eq!(
+        classify_lock_function("std::sys::sync::mutex::futex::Mutex::lock_contended"),
+        Some(LockKind::Mutex)
+    );
+    assert_eq!(
+        classify_lock_function("parking_lot::raw_rwlock::RawRwLock::lock_shared_slow"),
+        Some(LockKind::RwLock)
+    );
+    assert_eq!(classify_lock_function("app::Mutex::lock_contended"), None);
+    assert_eq!(classify_lock_function("pthread_mutex_unlock"), None);
+}
+
+#[test]
+fn test_find_cycles() {
+    let wait_for = HashMap::from([(1, 2), (2, 3), (3, 1), (4, 1), (5, 6), (7, 7)]);
+    let mut cycles = find_cycles(&wait_for);
+    cycles.sort();
+    assert_eq!(cycles, [vec![1, 2, 3], vec![7]]);
+    assert!(find_cycles(&HashMap::from([(1, 2), (2, 3)])).is_empty());
+}
+
+#[test]
+fn test_describe_deadlock() {
+    let wait = |thread_id, owner, lock: &str| LockWait {
+        thread_id,
+        thread_name: Some(format!("worker-{}", thread_id)),
+        kind: LockKind::Mutex,
+        function: "pthread_mutex_lock".into(),
+        lock_reference: Some(lock.into()),
+        owner_thread_id: Some(owner),
+        caller: Some(CodeLocation {
+            function: "transfer".into(),
+            source: Some(codelldb_types::Source { path: "bank.c".into() }),
+            line: Some(42),
+        }),
+    };
+    let waits = [wait(10, 11, "0x1000"), wait(11, 10, "0x2000")];
+    assert_eq!(
+        describe_deadlock(&[10, 11], &waits),
+        "thread 10 \"worker-10\" waits for mutex 0x1000 held by thread 11 \"worker-11\" (in transfer at bank.c:42); \
+         thread 11 \"worker-11\" waits for mutex 0x2000 held by thread 10 \"worker-10\" (in transfer at bank.c:42)"
+    );
+}
@@ -0,0 +1,582 @@
+use crate::disassembly;
+use crate::expressions;
+use crate::prelude::*;
+
+use super::variables::Container;
+
+use std::cell::Cell;
+use std::cmp;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use adapter_protocol::*;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use lldb::*;
+
+// Size of the chunks in which memory is read while searching.
+const SEARCH_CHUNK_SIZE: usize = 1024 * 1024;
+const DEFAULT_MAX_RESULTS: u32 = 1000;
+// Size limit of a single memory snapshot.
+const MAX_SNAPSHOT_SIZE: u64 = 64 * 1024 * 1024;
+// Number of snapshots retained; beyond that, the least recently used ones are discarded.
+const MAX_SNAPSHOTS: usize = 32;
+// Number of memory views tracked for change notifications.
+const MAX_MEMORY_VIEWS: usize = 32;
+
+static NEXT_PROGRESS_ID: AtomicU32 = AtomicU32::new(1);
+
+pub(super) struct MemorySnapshot {
+    address: Address,
+    data: Vec<u8>,
+    last_used: Cell<u64>,
+}
+
+impl MemorySnapshot {
+    fn range(&self) -> Range<Address> {
+        self.address..self.address + self.data.len() as u64
+    }
+}
+
+#[derive(Default)]
+pub(super) struct MemorySnapshots {
+    snapshots: HashMap<u32, MemorySnapshot>,
+    last_id: u32,
+    clock: Cell<u64>,
+}
+
+impl MemorySnapshots {
+    fn add(&mut self, address: Address, data: Vec<u8>) -> u32 {
+        if self.snapshots.len() >= MAX_SNAPSHOTS {
+            let lru = self.snapshots.iter().min_by_key(|(_, s)| s.last_used.get()).map(|(id, _)| *id);
+            if let Some(lru) = lru {
+                self.snapshots.remove(&lru);
+            }
+        }
+        self.last_id += 1;
+        let snapshot = MemorySnapshot {
+            address,
+            data,
+            last_used: Cell::new(self.tick()),
+        };
+        self.snapshots.insert(self.last_id, snapshot);
+        self.last_id
+    }
+
+    fn get(&self, id: u32) -> Option<&MemorySnapshot> {
+        let snapshot = self.snapshots.get(&id)?;
+        snapshot.last_used.set(self.tick());
+        Some(snapshot)
+    }
+
+    fn overlaps(&self, range: &Range<Address>) -> bool {
+        self.snapshots.values().any(|s| intersect(&s.range(), range).is_some())
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.set(self.clock.get() + 1);
+        self.clock.get()
+    }
+}
+
+impl super::DebugSession {
+    pub(super) fn handle_find_memory(&mut self, args: FindMemoryRequest) -> Result<FindMemoryResponse, Error> {
+        let process = self.target.process();
+        if !process.is_valid() {
+            bail!(blame_user(str_error("The debuggee is not running.")));
+        }
+        let big_endian = self.target.byte_order() == ByteOrder::Big;
+        let pattern = compile_pattern(&args.pattern, big_endian).map_err(blame_user)?;
+        let max_results = args.max_results.unwrap_or(DEFAULT_MAX_RESULTS) as usize;
+
+        let start = match &args.start {
+            Some(start) => parse_int::parse::<u64>(start)?,
+            None => 0,
+        };
+        let end = match &args.end {
+            Some(end) => parse_int::parse::<u64>(end)?,
+            None => Address::MAX,
+        };
+        let mut search_ranges = vec![start..end];
+        if let Some(module_name) = &args.module {
+            let Some(module) = self.target.modules().find(|m| {
+                let file_spec = m.file_spec();
+                file_spec.path().to_string_lossy() == *module_name
+                    || file_spec.filename().to_string_lossy() == *module_name
+            }) else {
+                bail!(blame_user(str_error(format!("Module not found: {}", module_name))));
+            };
+            search_ranges = module
+                .sections()
+                .filter_map(|section| {
+                    let load_addr = section.load_address(&self.target);
+                    if load_addr == INVALID_ADDRESS {
+                        None
+                    } else {
+                        intersect(&(start..end), &(load_addr..load_addr + section.byte_size() as u64))
+                    }
+                })
+                .collect();
+        }
+
+        // Collect readable memory regions within the search ranges.
+        let mut regions = Vec::new();
+        for range in &search_ranges {
+            let mut addr = range.start;
+            while addr < range.end {
+                let region = process.memory_region_info(addr)?;
+                let region_end = region.region_end();
+                if region.is_readable() && region.is_mapped() && region_matches(&region, args.region_kind) {
+                    if let Some(scan_range) = intersect(range, &(region.region_base()..region_end)) {
+                        regions.push((scan_range, region.clone()));
+                    }
+                }
+                if region_end <= addr {
+                    break;
+                }
+                addr = region_end;
+            }
+        }
+        let total_bytes: u64 = regions.iter().map(|(range, _)| range.end - range.start).sum();
+
+        let progress_id = self.start_progress("Searching memory");
+        let mut scanned_bytes = 0;
+        let mut last_percentage = 0;
+        let mut matches = Vec::new();
+        let mut truncated = false;
+        let mut buffer = Vec::new();
+        'regions: for (range, region) in &regions {
+            // Consecutive chunks overlap by (pattern length - 1) bytes, so that matches across boundaries are found.
+            let mut chunk_start = range.start;
+            while chunk_start < range.end {
+                if self.current_cancellation.is_cancelled() {
+                    self.end_progress(progress_id, Some("Canceled"));
+                    bail!("canceled");
+                }
+                let chunk_end = cmp::min(range.end, chunk_start.saturating_add(SEARCH_CHUNK_SIZE as u64));
+                buffer.resize((chunk_end - chunk_start) as usize, 0);
+                let count = process.read_memory(chunk_start, &mut buffer).unwrap_or(0);
+                for offset in find_all(&buffer[..count], &pattern) {
+                    if matches.len() >= max_results {
+                        truncated = true;
+                        break 'regions;
+                    }
+                    matches.push(self.describe_match(chunk_start + offset as u64, region));
+                }
+                scanned_bytes += chunk_end - chunk_start;
+                if chunk_end >= range.end || count < pattern.len() {
+                    break;
+                }
+                chunk_start = chunk_end - (pattern.len() - 1) as u64;
+
+                let percentage = scanned_bytes * 100 / cmp::max(total_bytes, 1);
+                if percentage > last_percentage {
+                    last_percentage = percentage;
+                    self.update_progress(progress_id, percentage as f64);
+                }
+            }
+        }
+        self.end_progress(progress_id, None);
+
+        Ok(FindMemoryResponse { matches, truncated })
+    }
+
+    pub(super) fn handle_snapshot_memory(
+        &mut self,
+        args: SnapshotMemoryRequest,
+    ) -> Result<SnapshotMemoryResponse, Error> {
+        let (address, count) = if let Some(expression) = &args.expression {
+            let frame = match args.frame_id {
+                Some(frame_id) => match self.var_refs.get(frame_id) {
+                    Some(Container::StackFrame(frame)) => Some(frame.clone()),
+                    _ => bail!("Invalid frame id."),
+                },
+                None => None,
+            };
+            let (pp_expr, format_spec) =
+                expressions::prepare_with_format(expression, self.default_expr_type).map_err(blame_user)?;
+            let sbval = self.evaluate_expr_in_frame(&pp_expr, frame.as_ref()).map_err(blame_user)?;
+            let sbval = self.apply_format_spec(sbval, &format_spec).map_err(blame_user)?;
+            let Some(address) = sbval.address() else {
+                bail!(blame_user(str_error("The value does not reside in memory.")));
+            };
+            (address.load_address(&self.target), sbval.byte_size() as u64)
+        } else if let (Some(memory_reference), Some(count)) = (&args.memory_reference, args.count) {
+            let base = parse_int::parse::<i64>(memory_reference)?;
+            ((base + args.offset.unwrap_or(0)) as Address, count)
+        } else {
+            bail!(blame_user(str_error(
+                "Either an expression, or a memory reference and a count are required."
+            )));
+        };
+
+        if count > MAX_SNAPSHOT_SIZE {
+            bail!(blame_user(str_error(format!(
+                "Snapshots are limited to {} bytes.",
+                MAX_SNAPSHOT_SIZE
+            ))));
+        }
+        let data = self.read_snapshot_data(address, count)?;
+        let snapshot_id = self.memory_snapshots.add(address, data);
+        // Stop tracking views of the discarded snapshots.
+        self.memory_views.retain(|view| self.memory_snapshots.overlaps(view));
+        Ok(SnapshotMemoryResponse {
+            snapshot_id,
+            memory_reference: format!("0x{:X}", address),
+            count,
+        })
+    }
+
+    pub(super) fn handle_diff_memory(&mut self, args: DiffMemoryRequest) -> Result<DiffMemoryResponse, Error> {
+        let snapshot = self.get_snapshot(args.snapshot_id)?;
+        let (address, old_data, new_data) = match args.other_snapshot_id {
+            Some(other_id) => {
+                let other = self.get_snapshot(other_id)?;
+                let Some(overlap) = intersect(&snapshot.range(), &other.range()) else {
+                    bail!(blame_user(str_error("The snapshots do not overlap.")));
+                };
+                let len = (overlap.end - overlap.start) as usize;
+                let old_data = &snapshot.data[(overlap.start - snapshot.address) as usize..][..len];
+                let new_data = &other.data[(overlap.start - other.address) as usize..][..len];
+                (overlap.start, old_data.to_vec(), new_data.to_vec())
+            }
+            None => {
+                let current = self.read_snapshot_data(snapshot.address, snapshot.data.len() as u64)?;
+                (snapshot.address, snapshot.data.clone(), current)
+            }
+        };
+        let changes = diff_bytes(&old_data, &new_data)
+            .into_iter()
+            .map(|range| MemoryChange {
+                memory_reference: format!("0x{:X}", address + range.start as u64),
+                count: range.len() as u64,
+                old_data: BASE64_STANDARD.encode(&old_data[range.clone()]),
+                new_data: BASE64_STANDARD.encode(&new_data[range]),
+            })
+            .collect();
+        Ok(DiffMemoryResponse { changes })
+    }
+
+    fn get_snapshot(&self, id: u32) -> Result<&MemorySnapshot, Error> {
+        match self.memory_snapshots.get(id) {
+            Some(snapshot) => Ok(snapshot),
+            None => bail!(blame_user(str_error(format!(
+                "Invalid or discarded snapshot id: {}",
+                id
+            )))),
+        }
+    }
+
+    fn read_snapshot_data(&self, address: Address, count: u64) -> Result<Vec<u8>, Error> {
+        let mut data = vec![0; count as usize];
+        let bytes_read = self.read_target_memory(address, &mut data)?;
+        if bytes_read < data.len() {
+            bail!(blame_user(str_error(format!(
+                "Could not read memory at 0x{:X}.",
+                address + bytes_read as u64
+            ))));
+        }
+        Ok(data)
+    }
+
+    // Read debuggee memory.  When there is no process (e.g. before `_run`), read from sections of the target's modules.
+    pub(super) fn read_target_memory(&self, address: Address, buffer: &mut [u8]) -> Result<usize, Error> {
+        let process = self.target.process();
+        if process.is_valid() {
+            Ok(process.read_memory(address, buffer)?)
+        } else {
+            Ok(self.target.read_memory(&SBAddress::from_load_address(address, &self.target), buffer)?)
+        }
+    }
+
+    // Remember memory ranges displayed by the client that overlap snapshots, so that we can notify it when they change.
+    pub(super) fn track_memory_view(&mut self, range: Range<Address>) {
+        if self.memory_snapshots.overlaps(&range) && !self.memory_views.contains(&range) {
+            if self.memory_views.len() >= MAX_MEMORY_VIEWS {
+                self.memory_views.remove(0);
+            }
+            self.memory_views.push(range);
+        }
+    }
+
+    // Ask the client to refresh tracked memory views.
+    pub(super) fn notify_memory_views(&self) {
+        if self.client_caps.supports_memory_event.unwrap_or(false) {
+            for range in &self.memory_views {
+                self.send_event(EventBody::memory(MemoryEventBody {
+                    memory_reference: format!("0x{:X}", range.start),
+                    offset: 0,
+                    count: (range.end - range.start) as i64,
+                }));
+            }
+        }
+    }
+
+    fn describe_match(&self, addr: Address, region: &SBMemoryRegionInfo) -> MemoryMatch {
+        let sbaddr = SBAddress::from_load_address(addr, &self.target);
+        let symbol = disassembly::symbolize_address(&self.target, addr, self.disasm_ranges.options());
+        let module = sbaddr.module().map(|m| m.file_spec().filename().to_string_lossy().into_owned());
+        MemoryMatch {
+            memory_reference: format!("0x{:X}", addr),
+            region: describe_region(region),
+            symbol,
+            module,
+        }
+    }
+
+    // Returns None if the client does not support progress reporting.
+    pub(super) fn start_progress(&self, title: &str) -> Option<u32> {
+        if !self.client_caps.supports_progress_reporting.unwrap_or(false) {
+            return None;
+        }
+        let id = NEXT_PROGRESS_ID.fetch_add(1, Ordering::Relaxed);
+        self.send_event(EventBody::progressStart(ProgressStartEventBody {
+            progress_id: id.to_string(),
+            title: title.into(),
+            request_id: None,
+            cancellable: None,
+            message: None,
+            percentage: Some(0.0),
+        }));
+        Some(id)
+    }
+
+    pub(super) fn update_progress(&self, id: Option<u32>, percentage: f64) {
+        if let Some(id) = id {
+            self.send_event(EventBody::progressUpdate(ProgressUpdateEventBody {
+                progress_id: id.to_string(),
+                message: None,
+                percentage: Some(percentage),
+            }));
+        }
+    }
+
+    pub(super) fn end_progress(&self, id: Option<u32>, message: Option<&str>) {
+        if let Some(id) = id {
+            self.send_event(EventBody::progressEnd(ProgressEndEventBody {
+                progress_id: id.to_string(),
+                message: message.map(Into::into),
+            }));
+        }
+    }
+}
+
+fn intersect(a: &Range<Address>, b: &Range<Address>) -> Option<Range<Address>> {
+    let start = cmp::max(a.start, b.start);
+    let end = cmp::min(a.end, b.end);
+    if start < end {
+        Some(start..end)
+    } else {
+        None
+    }
+}
+
+// Find ranges of bytes that differ between the two buffers.
+fn diff_bytes(old: &[u8], new: &[u8]) -> Vec<Range<usize>> {
+    let mut changes: Vec<Range<usize>> = Vec::new();
+    for (i, (a, b)) in old.iter().zip(new.iter()).enumerate() {
+        if a != b {
+            match changes.last_mut() {
+                Some(last) if last.end == i => last.end = i + 1,
+                _ => changes.push(i..i + 1),
+            }
+        }
+    }
+    changes
+}
+
+fn region_matches(region: &SBMemoryRegionInfo, kind: Option<MemoryRegionKind>) -> bool {
+    match kind {
+        None => true,
+        Some(MemoryRegionKind::Writable) => region.is_writable(),
+        Some(MemoryRegionKind::ReadOnly) => !region.is_writable() && !region.is_executable(),
+        Some(MemoryRegionKind::Executable) => region.is_executable(),
+        Some(MemoryRegionKind::Heap) => region.name() == Some("[heap]"),
+        Some(MemoryRegionKind::Stack) => region.name().map_or(false, |name| name.starts_with("[stack")),
+    }
+}
+
+fn describe_region(region: &SBMemoryRegionInfo) -> String {
+    let perms = format!(
+        "{}{}{}",
+        if region.is_readable() { 'r' } else { '-' },
+        if region.is_writable() { 'w' } else { '-' },
+        if region.is_executable() { 'x' } else { '-' }
+    );
+    match region.name() {
+        Some(name) => format!("{} {}", name, perms),
+        None => perms,
+    }
+}
+
+// Convert the pattern to a sequence of bytes, where None matches any byte.
+fn compile_pattern(pattern: &MemoryPattern, big_endian: bool) -> Result<Vec<Option<u8>>, Error> {
+    let bytes = match pattern {
+        MemoryPattern::Bytes { bytes } => {
+            let digits: Vec<char> = bytes.chars().filter(|c| !c.is_whitespace()).collect();
+            if digits.len() % 2 != 0 {
+                bail!("Byte pattern must contain an even number of hex digits.");
+            }
+            let mut result = Vec::new();
+            for pair in digits.chunks(2) {
+                if pair == ['?', '?'] {
+                    result.push(None);
+                } else {
+                    let pair: String = pair.iter().collect();
+                    match u8::from_str_radix(&pair, 16) {
+                        Ok(byte) => result.push(Some(byte)),
+                        Err(_) => bail!(format!("Invalid byte in pattern: {}", pair)),
+                    }
+                }
+            }
+            result
+        }
+        MemoryPattern::String { value, encoding } => match encoding.unwrap_or(StringEncoding::Utf8) {
+            StringEncoding::Utf8 => value.bytes().map(Some).collect(),
+            StringEncoding::Utf16 => value
+                .encode_utf16()
+                .flat_map(|c| if big_endian { c.to_be_bytes() } else { c.to_le_bytes() })
+                .map(Some)
+                .collect(),
+        },
+        MemoryPattern::Integer {
+            value,
+            size,
+            big_endian: value_big_endian,
+        } => {
+            let size = *size as usize;
+            if ![1, 2, 4, 8].contains(&size) {
+                bail!("Integer size must be 1, 2, 4 or 8.");
+            }
+            let number = match value.strip_prefix('-') {
+                Some(abs) => -parse_int::parse::<i128>(abs)?,
+                None => parse_int::parse::<i128>(value)?,
+            };
+            let bits = size as u32 * 8;
+            if number < -(1i128 << (bits - 1)) || number >= (1i128 << bits) {
+                bail!(format!("{} does not fit in {} bytes.", value, size));
+            }
+            let le_bytes = (number as u64).to_le_bytes();
+            let mut bytes: Vec<Option<u8>> = le_bytes[..size].iter().copied().map(Some).collect();
+            if value_big_endian.unwrap_or(big_endian) {
+                bytes.reverse();
+            }
+            bytes
+        }
+    };
+    if bytes.is_empty() {
+        bail!("Search pattern is empty.");
+    }
+    Ok(bytes)
+}
+
+// Find offsets of all occurrences of the pattern in the buffer.
+fn find_all<'a>(buffer: &'a [u8], pattern: &'a [Option<u8>]) -> impl Iterator<Item = usize> + 'a {
+    let last = (buffer.len() + 1).saturating_sub(pattern.len());
+    (0..last).filter(move |&i| {
+        pattern.iter().zip(&buffer[i..]).all(|(p, b)| match p {
+            Some(p) => p == b,
+            None => true,
+        })
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_compile_pattern() {
+    let bytes = |s: &str| MemoryPattern::Bytes { bytes: s.into() };
+    assert_eq!(
+        compile_pattern(&bytes("48 8B ?? 10"), false).unwrap(),
+        [Some(0x48), Some(0x8B), None, Some(0x10)]
+    );
+    assert_eq!(
+        compile_pattern(&bytes("488b??"), false).unwrap(),
+        [Some(0x48), Some(0x8B), None]
+    );
+    assert!(compile_pattern(&bytes("48 8"), false).is_err());
+    assert!(compile_pattern(&bytes("zz"), false).is_err());
+    assert!(compile_pattern(&bytes(""), false).is_err());
+
+    let string = |s: &str, encoding| MemoryPattern::String {
+        value: s.into(),
+        encoding: Some(encoding),
+    };
+    assert_eq!(
+        compile_pattern(&string("Hi", StringEncoding::Utf8), false).unwrap(),
+        [Some(b'H'), Some(b'i')]
+    );
+    assert_eq!(
+        compile_pattern(&string("Hi", StringEncoding::Utf16), false).unwrap(),
+        [Some(b'H'), Some(0), Some(b'i'), Some(0)]
+    );
+    assert_eq!(
+        compile_pattern(&string("Hi", StringEncoding::Utf16), true).unwrap(),
+        [Some(0), Some(b'H'), Some(0), Some(b'i')]
+    );
+
+    let int = |value: &str, size, big_endian| MemoryPattern::Integer {
+        value: value.into(),
+        size,
+        big_endian,
+    };
+    assert_eq!(
+        compile_pattern(&int("0x1234", 4, None), false).unwrap(),
+        [Some(0x34), Some(0x12), Some(0), Some(0)]
+    );
+    assert_eq!(
+        compile_pattern(&int("0x1234", 2, Some(true)), false).unwrap(),
+        [Some(0x12), Some(0x34)]
+    );
+    assert_eq!(
+        compile_pattern(&int("-1", 2, None), false).unwrap(),
+        [Some(0xFF), Some(0xFF)]
+    );
+    assert_eq!(compile_pattern(&int("255", 1, None), false).unwrap(), [Some(0xFF)]);
+    assert!(compile_pattern(&int("256", 1, None), false).is_err());
+    assert!(compile_pattern(&int("-129", 1, None), false).is_err());
+    assert!(compile_pattern(&int("1", 3, None), false).is_err());
+}
+
+#[test]
+fn test_find_all() {
+    let buffer = b"abcabxabc";
+    let pattern = [Some(b'a'), Some(b'b'), None];
+    assert_eq!(find_all(buffer, &pattern).collect::<Vec<_>>(), [0, 3, 6]);
+    let pattern = [Some(b'b'), Some(b'c')];
+    assert_eq!(find_all(buffer, &pattern).collect::<Vec<_>>(), [1, 7]);
+    let pattern = [Some(b'x'); 10];
+    assert_eq!(find_all(buffer, &pattern).count(), 0);
+}
+
+#[test]
+fn test_intersect() {
+    assert_eq!(intersect(&(0..10), &(5..20)), Some(5..10));
+    assert_eq!(intersect(&(0..10), &(10..20)), None);
+    assert_eq!(intersect(&(0..Address::MAX), &(3..4)), Some(3..4));
+}
+
+#[test]
+fn test_diff_bytes() {
+    assert_eq!(diff_bytes(b"abcdef", b"abcdef"), []);
+    assert_eq!(diff_bytes(b"abcdef", b"aXYdeZ"), [1..3, 5..6]);
+    assert_eq!(diff_bytes(b"abc", b"XbY"), [0..1, 2..3]);
+}
+
+#[test]
+fn test_snapshot_eviction() {
+    let mut snapshots = MemorySnapshots::default();
+    let first = snapshots.add(0x1000, vec![0; 16]);
+    let second = snapshots.add(0x2000, vec![0; 16]);
+    for i in 2..MAX_SNAPSHOTS {
+        snapshots.add(0x1000 * (i as u64 + 1), vec![0; 16]);
+    }
+    // Using the first snapshot makes the second one the least recently used.
+    assert!(snapshots.get(first).is_some());
+    let newest = snapshots.add(0x100000, vec![0; 16]);
+    assert!(snapshots.get(first).is_some());
+    assert!(snapshots.get(second).is_none());
+    assert!(snapshots.get(newest).is_some());
+    assert!(!snapshots.overlaps(&(0x2000..0x2010)));
+    assert!(snapshots.overlaps(&(0x1008..0x1100)));
+}
@@ -0,0 +1,546 @@
+use crate::prelude::*;
+
+use crate::expressions::{self, FormatSpec};
+use crate::natvis::{self, CondExpr, DisplayPart, ExpandItemKind, TypeVisualizer};
+
+use std::collections::HashSet;
+use std::fs;
+use std::iter;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use lldb::*;
+
+// Upper bound on the number of items produced by a single collection expansion.
+const MAX_ITEMS: u64 = 10000;
+// Guards against runaway recursion when a DisplayString refers to values of the same type.
+const MAX_SUMMARY_DEPTH: u32 = 8;
+// Likewise, for ExpandedItem's that produce values matched by the same visualizer.
+const MAX_EXPAND_DEPTH: u32 = 8;
+
+pub(super) struct NatvisExpansion<'a> {
+    pub items: NatvisItems<'a>,
+    pub hide_raw_view: bool,
+}
+
+// Iterates over the items of a visualizer's Expand element, evaluating them on demand.
+pub(super) struct NatvisItems<'a> {
+    session: &'a super::DebugSession,
+    object: SBValue,
+    visualizer: Rc<TypeVisualizer>,
+    tparams: Rc<Vec<String>>,
+    next_item: usize,
+    current: Option<(usize, Box<dyn Iterator<Item = Result<SBValue, Error>> + 'a>)>, // (Item index, its values)
+    // Nesting level of ExpandedItem's.
+    depth: u32,
+}
+
+impl<'a> Iterator for NatvisItems<'a> {
+    type Item = SBValue;
+
+    fn next(&mut self) -> Option<SBValue> {
+        let expand = self.visualizer.expand.as_ref()?;
+        loop {
+            if self.session.current_cancellation.is_cancelled() {
+                return None;
+            }
+            if let Some((index, values)) = &mut self.current {
+                match values.next() {
+                    Some(Ok(value)) => return Some(value),
+                    Some(Err(err)) => {
+                        if !expand.items[*index].optional {
+                            debug!("Natvis expansion of {:?}: {}", self.object.type_name(), err);
+                        }
+                    }
+                    None => {}
+                }
+                self.current = None;
+            }
+
+            let index = self.next_item;
+            let item = expand.items.get(index)?;
+            self.next_item += 1;
+            if let Some(condition) = &item.condition {
+                match self.session.natvis_eval_condition(&self.object, condition, &self.tparams, None) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(err) => {
+                        if !item.optional {
+                            debug!("Natvis condition {}: {}", condition, err);
+                        }
+                        continue;
+                    }
+                }
+            }
+            match self.session.natvis_expand_item(&self.object, &item.kind, &self.tparams, self.depth) {
+                Ok(values) => self.current = Some((index, values)),
+                Err(err) => {
+                    if !item.optional {
+                        debug!("Natvis expansion of {:?}: {}", self.object.type_name(), err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl super::DebugSession {
+    // Load visualizers from the specified .natvis files and directories.
+    pub(super) fn load_natvis(&mut self, paths: &[String]) {
+        for path in paths {
+            let path = self.relative_path_base.join(path);
+            let files = if path.is_dir() {
+                let mut files: Vec<PathBuf> = match fs::read_dir(&path) {
+                    Ok(entries) => entries
+                        .filter_map(|e| e.ok().map(|e| e.path()))
+                        .filter(|p| p.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("natvis")))
+                        .collect(),
+                    Err(err) => {
+                        self.console_error(format!("Could not read {}: {}", path.display(), err));
+                        continue;
+                    }
+                };
+                files.sort();
+                files
+            } else {
+                vec![path]
+            };
+            for file in files {
+                match self.natvis.load_file(&file) {
+                    Ok(count) => info!("Loaded {} visualizers from {:?}", count, file),
+                    Err(err) => self.console_error(format!("Could not load {}: {}", file.display(), err)),
+                }
+            }
+        }
+    }
+
+    fn find_natvis(&self, var: &SBValue) -> Option<(Rc<TypeVisualizer>, Rc<Vec<String>>)> {
+        if self.natvis.is_empty() {
+            return None;
+        }
+        let var_type = var.type_();
+        if let Some(result) = self.natvis.find_visualizer(var_type.name()) {
+            return Some(result);
+        }
+        // Try again, with typedefs and qualifiers stripped.
+        let canonical_type = var_type.canonical_type().unqualified_type();
+        if canonical_type.name() != var_type.name() {
+            self.natvis.find_visualizer(canonical_type.name())
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn has_natvis_expansion(&self, var: &SBValue) -> bool {
+        match self.find_natvis(&deref_if_pointer(var)) {
+            Some((visualizer, _)) => visualizer.expand.is_some(),
+            None => false,
+        }
+    }
+
+    // Compute summary of a value using the matching DisplayString (if any), or from the Natvis-expanded children.
+    pub(super) fn natvis_summary(&self, var: &SBValue, unlimited: bool) -> Option<String> {
+        let (visualizer, tparams) = self.find_natvis(var)?;
+        let depth = self.natvis_summary_depth.get();
+        if depth >= MAX_SUMMARY_DEPTH {
+            return Some("{...}".into());
+        }
+        self.natvis_summary_depth.set(depth + 1);
+        let summary = self.format_display_string(var, &visualizer, &tparams, unlimited);
+        self.natvis_summary_depth.set(depth);
+
+        match summary {
+            Some(summary) => Some(summary),
+            None if visualizer.expand.is_some() => Some(self.get_container_summary(var, unlimited)),
+            None => None,
+        }
+    }
+
+    fn format_display_string(
+        &self,
+        var: &SBValue,
+        visualizer: &TypeVisualizer,
+        tparams: &[String],
+        unlimited: bool,
+    ) -> Option<String> {
+        'next: for display_string in &visualizer.display_strings {
+            if let Some(condition) = &display_string.condition {
+                match self.natvis_eval_condition(var, condition, tparams, None) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(_) if display_string.optional => continue,
+                    Err(err) => return Some(format!("<{}>", err)),
+                }
+            }
+            let mut summary = String::new();
+            for part in &display_string.parts {
+                match part {
+                    DisplayPart::Text(text) => summary.push_str(text),
+                    DisplayPart::Expr(expr) => match self.natvis_eval_with_format(var, expr, tparams, None) {
+                        Ok((value, format_spec)) => {
+                            summary.push_str(&self.get_formatted_summary(&value, &format_spec, unlimited))
+                        }
+                        Err(_) if display_string.optional => continue 'next,
+                        Err(_) => summary.push_str("???"),
+                    },
+                }
+            }
+            return Some(summary);
+        }
+        None
+    }
+
+    // Produce children of a value, as specified by the Expand element of the matching visualizer.
+    // Items are evaluated lazily, so that consumers that only need a few of them (e.g. summaries) can stop early.
+    pub(super) fn natvis_expand<'a>(&'a self, var: &SBValue) -> Option<NatvisExpansion<'a>> {
+        self.natvis_expand_at_depth(var, 0)
+    }
+
+    fn natvis_expand_at_depth<'a>(&'a self, var: &SBValue, depth: u32) -> Option<NatvisExpansion<'a>> {
+        let object = deref_if_pointer(var);
+        let (visualizer, tparams) = self.find_natvis(&object)?;
+        let hide_raw_view = visualizer.expand.as_ref()?.hide_raw_view;
+        Some(NatvisExpansion {
+            items: NatvisItems {
+                session: self,
+                object,
+                visualizer,
+                tparams,
+                next_item: 0,
+                current: None,
+                depth,
+            },
+            hide_raw_view,
+        })
+    }
+
+    fn natvis_expand_item<'a>(
+        &'a self,
+        object: &SBValue,
+        kind: &ExpandItemKind,
+        tparams: &Rc<Vec<String>>,
+        depth: u32,
+    ) -> Result<Box<dyn Iterator<Item = Result<SBValue, Error>> + 'a>, Error> {
+        let items: Box<dyn Iterator<Item = Result<SBValue, Error>> + 'a> = match kind {
+            ExpandItemKind::Item { name, expr } => {
+                let value = self.natvis_eval_formatted(object, expr, tparams, None)?;
+                Box::new(iter::once(Ok(rename_value(&value, name))))
+            }
+            ExpandItemKind::ArrayItems { size, value_pointer } => {
+                let size = self.natvis_eval_size(object, size, tparams)?;
+                let Some(value_pointer) = self.natvis_select(object, value_pointer, tparams, None)? else {
+                    return Ok(Box::new(iter::empty()));
+                };
+                let ptr = self.natvis_eval_formatted(object, &value_pointer.expr, tparams, None)?;
+                let ptr_type = ptr.type_();
+                let (base_addr, elem_type) = if ptr_type.is_pointer_type() {
+                    (ptr.value_as_unsigned(0), ptr_type.pointee_type())
+                } else if ptr_type.is_array_type() {
+                    (ptr.load_address(), ptr_type.array_element_type())
+                } else {
+                    bail!("ValuePointer must be a pointer or an array");
+                };
+                let target = object.target();
+                let elem_size = elem_type.byte_size();
+                Box::new((0..size).map(move |i| {
+                    let addr = SBAddress::from_load_address(base_addr + i * elem_size, &target);
+                    let elem = target.create_value_from_address(&format!("[{}]", i), &addr, &elem_type);
+                    if ptr.format() != Format::Default {
+                        elem.set_format(ptr.format());
+                    }
+                    Ok(elem)
+                }))
+            }
+            ExpandItemKind::IndexListItems { size, value_node } => {
+                let size = self.natvis_eval_size(object, size, tparams)?;
+                let (object, value_node, tparams) = (object.clone(), value_node.clone(), tparams.clone());
+                Box::new((0..size).filter_map(move |i| {
+                    match self.natvis_select(&object, &value_node, &tparams, Some(i)) {
+                        Ok(Some(value_node)) => Some(
+                            self.natvis_eval_formatted(&object, &value_node.expr, &tparams, Some(i))
+                                .map(|value| rename_value(&value, &format!("[{}]", i))),
+                        ),
+                        Ok(None) => None,
+                        Err(err) => Some(Err(err)),
+                    }
+                }))
+            }
+            ExpandItemKind::LinkedListItems {
+                size,
+                head_pointer,
+                next_pointer,
+                value_node,
+            } => {
+                let limit = match size {
+                    Some(size) => self.natvis_eval(object, size, tparams, None)?.value_as_unsigned(0).min(MAX_ITEMS),
+                    None => MAX_ITEMS,
+                };
+                let head = self.natvis_eval(object, head_pointer, tparams, None)?;
+                let head_addr = head.value_as_unsigned(0);
+                let (next_pointer, value_node, tparams) = (next_pointer.clone(), value_node.clone(), tparams.clone());
+                let mut visited = HashSet::new();
+                let mut next = Some(Ok(head));
+                let mut index = 0;
+                Box::new(iter::from_fn(move || {
+                    while index < limit {
+                        let node = match next.take()? {
+                            Ok(node) => node,
+                            Err(err) => return Some(Err(err)),
+                        };
+                        let node_addr = node.value_as_unsigned(0);
+                        if node_addr == 0 || !visited.insert(node_addr) {
+                            return None;
+                        }
+                        let value = match self.natvis_eval_cond_expr(&node, &value_node, &tparams, None) {
+                            Ok(true) => Some(
+                                self.natvis_eval_formatted(&node, &value_node.expr, &tparams, None)
+                                    .map(|value| rename_value(&value, &format!("[{}]", index))),
+                            ),
+                            Ok(false) => None,
+                            Err(err) => Some(Err(err)),
+                        };
+                        next = match self.natvis_eval(&node, &next_pointer, &tparams, None) {
+                            Ok(node) if node.value_as_unsigned(0) == head_addr => None,
+                            result => Some(result),
+                        };
+                        if value.is_some() {
+                            index += 1;
+                            return value;
+                        }
+                    }
+                    None
+                }))
+            }
+            ExpandItemKind::TreeItems {
+                size,
+                head_pointer,
+                left_pointer,
+                right_pointer,
+                value_node,
+            } => {
+                let limit = match size {
+                    Some(size) => self.natvis_eval(object, size, tparams, None)?.value_as_unsigned(0).min(MAX_ITEMS),
+                    None => MAX_ITEMS,
+                };
+                let head = self.natvis_eval(object, head_pointer, tparams, None)?;
+                let (left_pointer, right_pointer, value_node, tparams) = (
+                    left_pointer.clone(),
+                    right_pointer.clone(),
+                    value_node.clone(),
+                    tparams.clone(),
+                );
+                // Nodes, for which the ValueNode condition is false, are treated as null (e.g. sentinel nodes).
+                let is_null = move |node: &SBValue, value_node: &CondExpr, tparams: &[String]| -> Result<bool, Error> {
+                    Ok(node.value_as_unsigned(0) == 0
+                        || !self.natvis_eval_cond_expr(node, value_node, tparams, None)?)
+                };
+                // In-order traversal
+                let mut visited = HashSet::new();
+                let mut stack = Vec::new();
+                let mut next = Some(Ok(head));
+                let mut index = 0;
+                Box::new(iter::from_fn(move || {
+                    if index >= limit {
+                        return None;
+                    }
+                    let mut node = match next.take() {
+                        Some(Ok(node)) => Some(node),
+                        Some(Err(err)) => return Some(Err(err)),
+                        None => None,
+                    };
+                    while let Some(current) = node.take() {
+                        match is_null(&current, &value_node, &tparams) {
+                            Ok(false) if visited.insert(current.value_as_unsigned(0)) => {}
+                            Ok(_) => break,
+                            Err(err) => return Some(Err(err)),
+                        }
+                        match self.natvis_eval(&current, &left_pointer, &tparams, None) {
+                            Ok(left) => node = Some(left),
+                            Err(err) => return Some(Err(err)),
+                        }
+                        stack.push(current);
+                    }
+                    let top = stack.pop()?;
+                    let value = self
+                        .natvis_eval_formatted(&top, &value_node.expr, &tparams, None)
+                        .map(|value| rename_value(&value, &format!("[{}]", index)));
+                    index += 1;
+                    next = Some(self.natvis_eval(&top, &right_pointer, &tparams, None));
+                    Some(value)
+                }))
+            }
+            ExpandItemKind::ExpandedItem { expr } => {
+                let value = self.natvis_eval_formatted(object, expr, tparams, None)?;
+                let expansion = if depth + 1 < MAX_EXPAND_DEPTH {
+                    self.natvis_expand_at_depth(&value, depth + 1)
+                } else {
+                    debug!("Natvis expansion of {:?} is nested too deeply", object.type_name());
+                    None
+                };
+                match expansion {
+                    Some(expansion) => Box::new(expansion.items.map(Ok)),
+                    None => Box::new((0..value.num_children()).map(move |i| Ok(value.child_at_index(i)))),
+                }
+            }
+        };
+        Ok(items)
+    }
+
+    // Returns the first of the expressions whose condition evaluates to true.
+    fn natvis_select<'a>(
+        &self,
+        context: &SBValue,
+        exprs: &'a [CondExpr],
+        tparams: &[String],
+        index: Option<u64>,
+    ) -> Result<Option<&'a CondExpr>, Error> {
+        for cond_expr in exprs {
+            if self.natvis_eval_cond_expr(context, cond_expr, tparams, index)? {
+                return Ok(Some(cond_expr));
+            }
+        }
+        Ok(None)
+    }
+
+    fn natvis_eval_cond_expr(
+        &self,
+        context: &SBValue,
+        cond_expr: &CondExpr,
+        tparams: &[String],
+        index: Option<u64>,
+    ) -> Result<bool, Error> {
+        match &cond_expr.condition {
+            Some(condition) => self.natvis_eval_condition(context, condition, tparams, index),
+            None => Ok(true),
+        }
+    }
+
+    fn natvis_eval_size(&self, context: &SBValue, size: &[CondExpr], tparams: &[String]) -> Result<u64, Error> {
+        match self.natvis_select(context, size, tparams, None)? {
+            Some(size) => Ok(self.natvis_eval(context, &size.expr, tparams, None)?.value_as_unsigned(0).min(MAX_ITEMS)),
+            None => Ok(0),
+        }
+    }
+
+    fn natvis_eval_condition(
+        &self,
+        context: &SBValue,
+        condition: &str,
+        tparams: &[String],
+        index: Option<u64>,
+    ) -> Result<bool, Error> {
+        Ok(self.natvis_eval(context, condition, tparams, index)?.value_as_unsigned(0) != 0)
+    }
+
+    // Evaluates an expression, which may be followed by a format specifier.
+    fn natvis_eval_formatted(
+        &self,
+        context: &SBValue,
+        expr: &str,
+        tparams: &[String],
+        index: Option<u64>,
+    ) -> Result<SBValue, Error> {
+        Ok(self.natvis_eval_with_format(context, expr, tparams, index)?.0)
+    }
+
+    // Same as natvis_eval_formatted(), but also returns the format specifier, for use in summaries.
+    fn natvis_eval_with_format(
+        &self,
+        context: &SBValue,
+        expr: &str,
+        tparams: &[String],
+        index: Option<u64>,
+    ) -> Result<(SBValue, FormatSpec), Error> {
+        let (expr, format_spec) = self.natvis_format_spec(context, expr, tparams, index)?;
+        let value = self.natvis_eval(context, expr, tparams, index)?;
+        let value = self.apply_format_spec(value, &format_spec)?;
+        Ok((value, format_spec))
+    }
+
+    // Splits off the format specifier, which uses the same syntax as in watch expressions, except that
+    // Natvis also allows the array size to be an expression, e.g. `ptr,[size]na` or `ptr,5`.
+    fn natvis_format_spec<'e>(
+        &self,
+        context: &SBValue,
+        expr: &'e str,
+        tparams: &[String],
+        index: Option<u64>,
+    ) -> Result<(&'e str, FormatSpec), Error> {
+        let (base_expr, Some(spec)) = natvis::split_format_spec(expr) else {
+            return Ok((expr, FormatSpec::NONE));
+        };
+        if let Some(format_spec) = expressions::parse_format_spec(spec)? {
+            return Ok((base_expr, format_spec));
+        }
+        if let (Some(count), rest) = natvis::split_array_count(spec) {
+            if let Some(mut format_spec) = expressions::parse_format_spec(rest)? {
+                let count = self.natvis_eval(context, count, tparams, index)?.value_as_unsigned(0);
+                format_spec.array = Some(count.min(MAX_ITEMS) as u32);
+                return Ok((base_expr, format_spec));
+            }
+        }
+        Ok((expr, FormatSpec::NONE))
+    }
+
+    // Evaluates a Natvis expression in the context of the given value.
+    fn natvis_eval(
+        &self,
+        context: &SBValue,
+        expr: &str,
+        tparams: &[String],
+        index: Option<u64>,
+    ) -> Result<SBValue, Error> {
+        let expr = natvis::substitute_params(expr, tparams, index);
+        // Members of the context object are accessible directly.
+        let expr = natvis::strip_this(&expr);
+        let context = deref_if_pointer(context).non_synthetic_value();
+        if let Some(value) = member_path(&context, &expr) {
+            return Ok(value);
+        }
+        let value = context.evaluate_expression(&expr).into_result()?;
+        Ok(value)
+    }
+}
+
+fn deref_if_pointer(var: &SBValue) -> SBValue {
+    if var.type_().type_class().intersects(TypeClass::Pointer | TypeClass::Reference) {
+        var.dereference()
+    } else {
+        var.clone()
+    }
+}
+
+// Fast path for simple member access expressions, such as `foo.bar->baz`, which avoids invoking the expression evaluator.
+fn member_path(value: &SBValue, expr: &str) -> Option<SBValue> {
+    let mut value = value.clone();
+    let mut rest = expr.trim();
+    loop {
+        let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        if len == 0 || rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        value = value.child_member_with_name(&rest[..len])?;
+        rest = &rest[len..];
+        if rest.is_empty() {
+            return Some(value);
+        } else if let Some(tail) = rest.strip_prefix("->") {
+            value = value.dereference();
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix('.') {
+            rest = tail;
+        } else {
+            return None;
+        }
+    }
+}
+
+// Expansion items are named after the Natvis element rather than the expression that produced them.
+fn rename_value(value: &SBValue, name: &str) -> SBValue {
+    let target = value.target();
+    let renamed = match value.address() {
+        Some(addr) => target.create_value_from_address(name, &addr, &value.type_()),
+        None => target.create_value_from_data(name, &value.data(), &value.type_()),
+    };
+    renamed.set_format(value.format());
+    renamed
+}
@@ -0,0 +1,153 @@
+use crate::prelude::*;
+
+use adapter_protocol::*;
+use lldb::*;
+
+const DEFAULT_MAX_DEPTH: u32 = 256;
+
+impl super::DebugSession {
+    pub(super) fn handle_parallel_stacks(
+        &mut self,
+        args: Option<ParallelStacksRequest>,
+    ) -> Result<ParallelStacksResponse, Error> {
+        let max_depth = args.and_then(|a| a.max_depth).unwrap_or(DEFAULT_MAX_DEPTH).max(1);
+        let process = self.target.process();
+        if !process.is_valid() {
+            bail!(blame_user(str_error("No process.")));
+        }
+        if process.state().is_running() {
+            bail!(blame_nobody(str_error("Not available while the process is running.")));
+        }
+
+        let num_threads = process.num_threads();
+        let progress_id = self.start_progress("Unwinding threads");
+        let mut roots = Vec::new();
+        let mut truncated_thread_ids = Vec::new();
+        for (thread_index, thread) in process.threads().enumerate() {
+            if self.current_cancellation.is_cancelled() {
+                self.end_progress(progress_id, Some("Canceled"));
+                bail!("canceled");
+            }
+            let thread_id = thread.thread_id() as i64;
+            // Not using thread.frames(), because that would unwind the entire stack.
+            let mut stack = Vec::new();
+            for index in 0..max_depth {
+                let frame = thread.frame_at_index(index);
+                if !frame.is_valid() {
+                    break;
+                }
+                stack.push(self.make_stack_node(&frame));
+            }
+            if stack.len() == max_depth as usize && thread.frame_at_index(max_depth).is_valid() {
+                truncated_thread_ids.push(thread_id);
+                stack.push(StackNode {
+                    name: "...".into(),
+                    instruction_pointer_reference: String::new(),
+                    source: None,
+                    line: None,
+                    thread_ids: Vec::new(),
+                    children: Vec::new(),
+                });
+            }
+            stack.reverse();
+            insert_stack(&mut roots, thread_id, stack);
+            self.update_progress(
+                progress_id,
+                (thread_index + 1) as f64 * 100.0 / num_threads.max(1) as f64,
+            );
+        }
+        self.end_progress(progress_id, None);
+        Ok(ParallelStacksResponse {
+            roots,
+            truncated_thread_ids,
+        })
+    }
+
+    fn make_stack_node(&self, frame: &SBFrame) -> StackNode {
+        let pc_address = frame.pc_address();
+        let name = match frame.function_name() {
+            Some(name) => name.to_owned(),
+            None => format!("{:X}", pc_address.file_address()),
+        };
+        let mut node = StackNode {
+            name,
+            instruction_pointer_reference: format!("0x{:X}", frame.pc()),
+            source: None,
+            line: None,
+            thread_ids: Vec::new(),
+            children: Vec::new(),
+        };
+        if let Some(le) = frame.line_entry() {
+            if let Some(local_path) = self.map_filespec_to_local(&le.file_spec()) {
+                node.source = Some(codelldb_types::Source {
+                    path: local_path.to_string_lossy().into_owned(),
+                });
+                node.line = Some(le.line());
+            }
+        }
+        node
+    }
+}
+
+// Merges a call stack, ordered from the outermost frame inwards, into the tree.
+// Frames are considered identical if they have the same function name and program counter.
+fn insert_stack(mut nodes: &mut Vec<StackNode>, thread_id: i64, stack: Vec<StackNode>) {
+    for frame in stack {
+        let index = match nodes.iter().position(|n| {
+            n.name == frame.name && n.instruction_pointer_reference == frame.instruction_pointer_reference
+        }) {
+            Some(index) => index,
+            None => {
+                nodes.push(frame);
+                nodes.len() - 1
+            }
+        };
+        let node = &mut nodes[index];
+        node.thread_ids.push(thread_id);
+        nodes = &mut node.children;
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_insert_stack() {
+    fn node(name: &str, pc: u64) -> StackNode {
+        StackNode {
+            name: name.into(),
+            instruction_pointer_reference: format!("0x{:X}", pc),
+            source: None,
+            line: None,
+            thread_ids: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+    let mut roots = Vec::new();
+    insert_stack(
+        &mut roots,
+        1,
+        vec![node("start", 0x10), node("worker", 0x20), node("wait", 0x30)],
+    );
+    insert_stack(
+        &mut roots,
+        2,
+        vec![node("start", 0x10), node("worker", 0x20), node("wait", 0x30)],
+    );
+    insert_stack(
+        &mut roots,
+        3,
+        vec![node("start", 0x10), node("worker", 0x24), node("wait", 0x30)],
+    );
+    insert_stack(&mut roots, 4, vec![node("main", 0x40)]);
+
+    assert_eq!(roots.len(), 2);
+    assert_eq!(roots[0].thread_ids, [1, 2, 3]);
+    let workers = &roots[0].children;
+    assert_eq!(workers.len(), 2);
+    assert_eq!(workers[0].thread_ids, [1, 2]);
+    assert_eq!(workers[0].children[0].thread_ids, [1, 2]);
+    assert_eq!(workers[1].thread_ids, [3]);
+    assert_eq!(workers[1].children[0].name, "wait");
+    assert_eq!(roots[1].thread_ids, [4]);
+    assert!(roots[1].children.is_empty());
+}
@@ -1,16 +1,19 @@
+use crate::disassembly;
 use crate::prelude::*;
 
 use super::variables::Container;
 
 use std::cmp;
+use std::collections::HashMap;
 use std::ops::Range;
 
 use adapter_protocol::*;
 use lldb::*;
-use regex_lite::Regex;
 
 #[derive(Debug, Clone)]
 pub(super) struct StepInTargetInternal {
+    /// Address of the call instruction
+    call_addr: Address,
     target_fn: SBFunction,
     /// Range of addresses occupied by the statement for which this target was computed
     stmt_range: Range<Address>,
@@ -27,6 +30,7 @@ impl super::DebugSession {
         let Container::StackFrame(frame) = frame else {
             bail!("Invalid frame id.");
         };
+        let frame = frame.clone();
         let Some(curr_le) = frame.line_entry() else {
             bail!("No line entry for frame.");
         };
@@ -58,42 +62,56 @@ impl super::DebugSession {
                 max_end_addr = cmp::max(max_end_addr, end_address);
             }
         }
+        let stmt_range = frame.pc()..max_end_addr;
         // Get instructions for address range PC..max_end_addr
         let instr_count = (max_end_addr - frame.pc()) as u32;
-        let instructions = self.target.read_instructions(&frame.pc_address(), instr_count, Some("att"));
+        let flavor = self.disasm_ranges.options().flavor(&self.target);
+        let instructions = self.target.read_instructions(&frame.pc_address(), instr_count, flavor.as_deref());
         let instructions = instructions
             .iter()
             .take_while(|instr| instr.address().load_address(&self.target) < max_end_addr);
-        // Find `call` instructions and try to determine their targets.
-        let mut target_fns = Vec::new();
+        // Find `call` instructions and try to determine their targets.  Each call site is a separate target.
+        let mut call_sites: Vec<(SBAddress, SBFunction)> = Vec::new();
+        let mut tracker = RegisterTracker::new(&self.target, &frame);
         for instr in instructions {
-            match self.control_flow_kind(&instr) {
-                InstructionControlFlowKind::Call | InstructionControlFlowKind::FarCall => {
-                    if let Ok(func) = self.call_target_function(&instr) {
-                        // Include only targets that have line numbers debug info
-                        if func.is_valid() && func.start_address().line_entry().is_some() {
-                            // Deduplicate since we don't currently distinguish between invocation instances.
-                            if !target_fns.contains(&func) {
-                                target_fns.push(func);
-                            }
-                        }
+            let kind = self.control_flow_kind(&instr);
+            if let InstructionControlFlowKind::Call | InstructionControlFlowKind::FarCall = kind {
+                let func = match self.call_target_function(&instr) {
+                    Ok(func) => Some(func),
+                    Err(_) => self.indirect_call_target(&instr, &tracker),
+                };
+                if let Some(func) = func {
+                    // Include only targets that have line numbers debug info
+                    if func.is_valid() && func.start_address().line_entry().is_some() {
+                        call_sites.push((instr.address(), func));
                     }
                 }
-                _ => (),
             }
+            tracker.step(&instr, kind);
         }
-        let curr_pc = frame.pc();
+
         let mut targets = Vec::new();
-        for target_fn in target_fns {
+        for (index, (call_addr, target_fn)) in call_sites.iter().enumerate() {
+            // Distinguish invocation instances of the same function by their ordinals.
+            let display_name = target_fn.display_name();
+            let label = if call_sites.iter().filter(|(_, f)| f == target_fn).count() > 1 {
+                let ordinal = call_sites[..index].iter().filter(|(_, f)| f == target_fn).count() + 1;
+                format!("{} ({} call)", display_name, ordinal_name(ordinal))
+            } else {
+                display_name.to_owned()
+            };
+            let line_entry = call_addr.line_entry();
             let target_id = self.step_in_targets.len() as i64;
-            let display_name = target_fn.display_name().to_owned();
             self.step_in_targets.push(StepInTargetInternal {
-                target_fn: target_fn,
-                stmt_range: curr_pc..max_end_addr,
+                call_addr: call_addr.load_address(&self.target),
+                target_fn: target_fn.clone(),
+                stmt_range: stmt_range.clone(),
             });
             targets.push(StepInTarget {
                 id: target_id,
-                label: display_name,
+                label: label,
+                line: line_entry.as_ref().map(|le| le.line() as i64),
+                column: line_entry.as_ref().map(|le| le.column() as i64).filter(|c| *c > 0),
                 ..Default::default()
             });
         }
@@ -102,30 +120,30 @@ impl super::DebugSession {
     }
 
     fn control_flow_kind(&self, instr: &SBInstruction) -> InstructionControlFlowKind {
-        let kind = instr.control_flow_kind(&self.target);
-        if kind == InstructionControlFlowKind::Unknown {
-            if self.target.triple().starts_with("arm") {
-                match instr.mnemonic(&self.target) {
-                    "bl" => InstructionControlFlowKind::Call,
-                    "b" => InstructionControlFlowKind::Jump,
-                    _ => InstructionControlFlowKind::Other,
-                }
-            } else {
-                kind
-            }
-        } else {
-            kind
-        }
+        disassembly::control_flow_kind(&self.target, instr)
     }
 
     // Try to determine target function of a call instruction.
     fn call_target_function(&self, instruction: &SBInstruction) -> Result<SBFunction, Error> {
         let address = self.instruction_target_address(instruction)?;
+        self.function_at_address(&address)
+    }
+
+    // Try to determine target function of an indirect call (a virtual call, a call through a function pointer, etc),
+    // by evaluating its operand as it will be just before the call.
+    fn indirect_call_target(&self, instruction: &SBInstruction, tracker: &RegisterTracker) -> Option<SBFunction> {
+        let operand = disassembly::indirect_operand(instruction.operands(&self.target))?;
+        let next_pc = instruction.address().load_address(&self.target) + instruction.byte_size() as u64;
+        let address = tracker.branch_target(&operand, next_pc)?;
+        self.function_at_address(&SBAddress::from_load_address(address, &self.target)).ok()
+    }
+
+    fn function_at_address(&self, address: &SBAddress) -> Result<SBFunction, Error> {
         if let Some(func) = address.function() {
             return Ok(func);
         }
         // Check if there's a `jmp <immed>` at that address.
-        let target_instr = self.target.read_instructions(&address, 1, None).instruction_at_index(0);
+        let target_instr = self.target.read_instructions(address, 1, None).instruction_at_index(0);
         if target_instr.is_valid() {
             if let InstructionControlFlowKind::Jump | InstructionControlFlowKind::FarJump =
                 self.control_flow_kind(&target_instr)
@@ -142,21 +160,16 @@ impl super::DebugSession {
     // Try to determine the target address of a call or a jmp instruction.
     fn instruction_target_address(&self, instruction: &SBInstruction) -> Result<SBAddress, Error> {
         let operands = instruction.operands(&self.target);
-        lazy_static::lazy_static! {
-            // 0x12345
-            static ref IMMED: Regex = Regex::new(r"^0[xX][[:xdigit:]]+$").unwrap();
-            // *0x1234(%rip)
-            static ref RIP_REL: Regex = Regex::new(r"^\*(0[xX][[:xdigit:]]+)\(%rip\)$").unwrap();
-        }
-        let load_addr = if IMMED.is_match(operands) {
-            parse_int::parse::<u64>(operands)?
-        } else if let Some(caps) = RIP_REL.captures(operands) {
-            let offset = parse_int::parse::<u64>(&caps[1])?;
+        let load_addr = if let Some(addr) = disassembly::immediate_operand(operands) {
+            addr
+        } else if let Some(offset) = disassembly::pc_relative_displacement(operands) {
+            // An indirect call via GOT entry, e.g. `*0x1234(%rip)` or `qword ptr [rip + 0x1234]`.
             let mut got_ptr = instruction.address();
-            got_ptr.add_offset(offset.wrapping_add(instruction.byte_size() as u64));
-            let mut buffer = [0u8; 8];
-            self.target.read_memory(&got_ptr, &mut buffer)?;
-            u64::from_le_bytes(buffer)
+            got_ptr.add_offset((offset as u64).wrapping_add(instruction.byte_size() as u64));
+            match disassembly::read_pointer(&self.target, got_ptr.load_address(&self.target)) {
+                Some(addr) => addr,
+                None => bail!("Could not read the call target pointer"),
+            }
         } else {
             bail!("Unrecognized operand form");
         };
@@ -171,6 +184,7 @@ impl super::DebugSession {
                 bail!("Invalid thread id.")
             }
         };
+        self.ensure_not_frozen(&thread)?;
         let frame = thread.frame_at_index(0);
 
         let step_target = if let Some(id) = args.target_id {
@@ -193,6 +207,9 @@ impl super::DebugSession {
             if let Some(step_target) = step_target {
                 log_errors!(self.step_into_target(&thread, &step_target));
                 self.notify_process_stopped();
+            } else if self.just_my_code.is_some() {
+                log_errors!(self.step_into_user_code(&thread));
+                self.notify_process_stopped();
             } else {
                 thread.step_into(RunMode::OnlyDuringStepping)?;
             }
@@ -204,6 +221,20 @@ impl super::DebugSession {
         let _token = lldb_stub::v16.resolve()?;
         self.with_sync_mode(|| {
             let start_frame = thread.frame_at_index(0);
+            // Step to the call site, unless we are already there.  Stepping by instruction keeps us within
+            // the statement, even if the call site gets skipped over, and lets the user cancel the operation.
+            let mut curr_pc = start_frame.pc();
+            while curr_pc != step_target.call_addr {
+                if self.current_cancellation.is_cancelled() {
+                    bail!("Cancelled");
+                }
+                thread.step_instruction(true)?;
+                let curr_frame = thread.frame_at_index(0);
+                curr_pc = curr_frame.pc();
+                if curr_frame != start_frame || !step_target.stmt_range.contains(&curr_pc) {
+                    bail!("Could not reach the call site");
+                }
+            }
             thread.step_into(RunMode::OnlyThisThread)?;
             loop {
                 if self.current_cancellation.is_cancelled() {
@@ -260,3 +291,195 @@ impl super::DebugSession {
         })
     }
 }
+
+// Tracks register values between the current PC and a call site, so that operands of indirect calls
+// may be evaluated before the call executes.  Only simple data movement instructions are emulated;
+// registers modified in any other way are considered unknown.
+struct RegisterTracker<'a> {
+    target: &'a SBTarget,
+    frame: &'a SBFrame,
+    // Registers written by the instructions seen so far, by canonical name.
+    written: HashMap<String, Option<u64>>,
+    // Whether caller-saved registers have been clobbered by a call.
+    clobbered: bool,
+    // Whether we've encountered an instruction whose effects we can't track.
+    lost: bool,
+}
+
+// Instructions that modify registers other than their destination operand.
+const IMPLICIT_WRITES: &[&str] = &[
+    "mul", "imul", "div", "idiv", "cqo", "cdq", "cwd", "xchg", "cmpxchg", "xadd", "cpuid", "rdtsc", "syscall", "rep",
+    "lods", "stos", "movs", "scas", "cmps", "ldp", "ldnp",
+];
+// Instructions that modify the stack pointer.
+const STACK_WRITES: &[&str] = &["push", "pop", "enter", "leave"];
+// Instructions that do not write their destination operand.
+const NO_WRITES: &[&str] = &["cmp", "test", "bt", "comis", "ucomis", "nop", "tst"];
+
+// LLDB does not classify control flow of AArch64 instructions (other than `b` and `bl`), so we check mnemonics.
+fn is_aarch64_branch(mnemonic: &str) -> bool {
+    matches!(mnemonic, "b" | "br" | "ret" | "cbz" | "cbnz" | "tbz" | "tbnz") || mnemonic.starts_with("b.")
+}
+
+impl<'a> RegisterTracker<'a> {
+    fn new(target: &'a SBTarget, frame: &'a SBFrame) -> Self {
+        RegisterTracker {
+            target,
+            frame,
+            written: HashMap::new(),
+            clobbered: false,
+            lost: false,
+        }
+    }
+
+    fn register(&self, name: &str) -> Option<u64> {
+        if self.lost {
+            return None;
+        }
+        let canonical = disassembly::canonical_register(name);
+        match self.written.get(&canonical) {
+            Some(value) => match disassembly::register_width(name)? {
+                4 => value.map(|v| v & 0xFFFF_FFFF),
+                _ => *value,
+            },
+            None if self.clobbered && !disassembly::is_callee_saved(&canonical) => None,
+            None => self.frame.find_register(name)?.try_value_as_unsigned().ok(),
+        }
+    }
+
+    fn address(&self, mem: &disassembly::MemoryOperand, next_pc: Address) -> Option<Address> {
+        let base = match mem.base.as_deref() {
+            None => 0,
+            Some("rip") => next_pc,
+            Some(reg) => self.register(reg)?,
+        };
+        let index = match mem.index.as_deref() {
+            None => 0,
+            Some(reg) => self.register(reg)?.wrapping_mul(mem.scale),
+        };
+        Some(base.wrapping_add(index).wrapping_add(mem.displacement as u64))
+    }
+
+    fn read(&self, address: Address, size: usize) -> Option<u64> {
+        let mut buffer = [0u8; 8];
+        let size = cmp::min(size, buffer.len());
+        let count = self.target.process().read_memory(address, &mut buffer[..size]).ok()?;
+        if count < size {
+            return None;
+        }
+        Some(disassembly::decode_uint(&buffer[..size], self.target.byte_order()))
+    }
+
+    // Compute the address an indirect branch will transfer control to.
+    fn branch_target(&self, operand: &disassembly::IndirectOperand, next_pc: Address) -> Option<Address> {
+        match operand {
+            disassembly::IndirectOperand::Register(reg) => self.register(reg),
+            disassembly::IndirectOperand::Memory(mem) => {
+                disassembly::read_pointer(self.target, self.address(mem, next_pc)?)
+            }
+        }
+    }
+
+    // Account for the effects of an instruction.
+    fn step(&mut self, instr: &SBInstruction, kind: InstructionControlFlowKind) {
+        match kind {
+            InstructionControlFlowKind::Call | InstructionControlFlowKind::FarCall => {
+                self.clobbered = true;
+                self.written.retain(|reg, _| disassembly::is_callee_saved(reg));
+                return;
+            }
+            InstructionControlFlowKind::Other | InstructionControlFlowKind::Unknown => {}
+            // We only emulate straight-line code; past a branch, the register values are unknown.
+            _ => {
+                self.lost = true;
+                return;
+            }
+        }
+        let mnemonic = instr.mnemonic(self.target).to_ascii_lowercase();
+        if is_aarch64_branch(&mnemonic) {
+            self.lost = true;
+            return;
+        }
+        let operands = instr.operands(self.target);
+        if IMPLICIT_WRITES.iter().any(|m| mnemonic.starts_with(m)) || operands.contains('!') || operands.contains("], ")
+        {
+            // Includes pre- and post-indexed addressing modes, which update the base register.
+            self.lost = true;
+            return;
+        }
+        if STACK_WRITES.iter().any(|m| mnemonic.starts_with(m)) {
+            self.written.insert(disassembly::canonical_register("rsp"), None);
+        }
+        if NO_WRITES.iter().any(|m| mnemonic.starts_with(m)) {
+            return;
+        }
+        // In AT&T syntax the destination is the last operand, otherwise it's the first one.
+        let att_syntax = operands.contains('%');
+        let operands = disassembly::split_operands(operands);
+        let (dest, source) =
+            if att_syntax { (operands.last(), operands.first()) } else { (operands.first(), operands.last()) };
+        let Some(dest) = dest.and_then(|dest| disassembly::register_operand(dest)) else {
+            return; // No register destination
+        };
+        let value = match source {
+            Some(source) if operands.len() == 2 => {
+                let next_pc = instr.address().load_address(self.target) + instr.byte_size() as u64;
+                self.emulate_move(&mnemonic, dest, source, att_syntax, next_pc)
+            }
+            _ => None,
+        };
+        self.written.insert(disassembly::canonical_register(dest), value);
+    }
+
+    fn emulate_move(
+        &self,
+        mnemonic: &str,
+        dest: &str,
+        source: &str,
+        att_syntax: bool,
+        next_pc: Address,
+    ) -> Option<u64> {
+        let width = disassembly::register_width(dest)?;
+        let value = match mnemonic {
+            "mov" | "movq" | "movl" | "movabs" | "movabsq" | "ldr" | "ldur" => {
+                if let Some(value) = disassembly::immediate_value(source, att_syntax) {
+                    value
+                } else if let Some(reg) = disassembly::register_operand(source) {
+                    self.register(reg)?
+                } else {
+                    let mem = disassembly::memory_operand(source)?;
+                    self.read(self.address(&mem, next_pc)?, width)?
+                }
+            }
+            "lea" | "leaq" | "leal" => self.address(&disassembly::memory_operand(source)?, next_pc)?,
+            _ => return None,
+        };
+        Some(if width == 4 { value & 0xFFFF_FFFF } else { value })
+    }
+}
+
+// English ordinal for a number, e.g. 1 -> "1st", 12 -> "12th", 22 -> "22nd".
+fn ordinal_name(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_ordinal_name() {
+    assert_eq!(ordinal_name(1), "1st");
+    assert_eq!(ordinal_name(2), "2nd");
+    assert_eq!(ordinal_name(3), "3rd");
+    assert_eq!(ordinal_name(4), "4th");
+    assert_eq!(ordinal_name(11), "11th");
+    assert_eq!(ordinal_name(12), "12th");
+    assert_eq!(ordinal_name(21), "21st");
+    assert_eq!(ordinal_name(113), "113th");
+}
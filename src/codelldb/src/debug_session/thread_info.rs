@@ -0,0 +1,137 @@
+use crate::prelude::*;
+
+use adapter_protocol::*;
+use lldb::*;
+
+impl super::DebugSession {
+    pub(super) fn handle_thread_info(&mut self) -> Result<ThreadInfoResponse, Error> {
+        let process = self.target.process();
+        if !process.is_valid() {
+            bail!(blame_user(str_error("No process.")));
+        }
+        if process.state().is_running() {
+            bail!(blame_nobody(str_error("Not available while the process is running.")));
+        }
+        // /proc is only meaningful if the debuggee runs on this machine.
+        let is_local = self.target.platform().name() == "host";
+
+        let mut threads = Vec::new();
+        for thread in process.threads() {
+            let stop_description = thread.stop_description();
+            let mut info = ThreadInfo {
+                id: thread.thread_id() as i64,
+                index: thread.index_id(),
+                name: thread.name().map(|s| s.to_owned()),
+                stop_reason: format!("{:?}", thread.stop_reason()),
+                stop_description: if stop_description.is_empty() { None } else { Some(stop_description) },
+                location: None,
+                queue_name: thread.queue_name().map(|s| s.to_owned()),
+                frozen: self.frozen_threads.contains(&thread.thread_id()),
+                stack_start: None,
+                stack_end: None,
+                scheduler_state: None,
+                cpu_time: None,
+            };
+            let frame = thread.frame_at_index(0);
+            if frame.is_valid() {
+                info.location = Some(self.frame_location(&frame));
+                if let Ok(region) = process.memory_region_info(frame.sp()) {
+                    if region.region_end() > region.region_base() {
+                        info.stack_start = Some(format!("0x{:X}", region.region_base()));
+                        info.stack_end = Some(format!("0x{:X}", region.region_end()));
+                    }
+                }
+            }
+            if is_local {
+                if let Some(stat) = read_task_stat(process.process_id(), thread.thread_id()) {
+                    info.scheduler_state = Some(stat.state.to_owned());
+                    info.cpu_time = Some(stat.cpu_time);
+                }
+            }
+            threads.push(info);
+        }
+        Ok(ThreadInfoResponse { threads })
+    }
+
+    pub(super) fn frame_location(&self, frame: &SBFrame) -> CodeLocation {
+        let function = match frame.function_name() {
+            Some(name) => name.to_owned(),
+            None => format!("{:X}", frame.pc_address().file_address()),
+        };
+        let mut location = CodeLocation {
+            function,
+            source: None,
+            line: None,
+        };
+        if let Some(le) = frame.line_entry() {
+            if let Some(local_path) = self.map_filespec_to_local(&le.file_spec()) {
+                location.source = Some(codelldb_types::Source {
+                    path: local_path.to_string_lossy().into_owned(),
+                });
+                location.line = Some(le.line());
+            }
+        }
+        location
+    }
+}
+
+struct TaskStat {
+    state: &'static str,
+    // User + system time, in seconds.
+    cpu_time: f64,
+}
+
+#[cfg(target_os = "linux")]
+fn read_task_stat(pid: ProcessID, tid: ThreadID) -> Option<TaskStat> {
+    let text = std::fs::read_to_string(format!("/proc/{}/task/{}/stat", pid, tid)).ok()?;
+    let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks_per_second <= 0 {
+        return None;
+    }
+    parse_task_stat(&text, ticks_per_second as f64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_task_stat(_pid: ProcessID, _tid: ThreadID) -> Option<TaskStat> {
+    None
+}
+
+// Parses the contents of /proc/<pid>/task/<tid>/stat.  See proc(5).
+#[cfg_attr(not(target_os = "linux"), allow(unused))]
+fn parse_task_stat(text: &str, ticks_per_second: f64) -> Option<TaskStat> {
+    // The command name may contain spaces and parentheses, so skip past the last ')'.
+    let fields: Vec<&str> = text[text.rfind(')')? + 1..].split_whitespace().collect();
+    let state = match *fields.get(0)? {
+        "R" => "running",
+        "S" => "sleeping",
+        "D" => "disk sleep",
+        "T" => "stopped",
+        "t" => "tracing stop",
+        "Z" => "zombie",
+        "X" | "x" => "dead",
+        "I" => "idle",
+        "P" => "parked",
+        _ => "unknown",
+    };
+    // utime and stime are fields 14 and 15 of the full line.
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(TaskStat {
+        state,
+        cpu_time: (utime + stime) as f64 / ticks_per_second,
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_parse_task_stat() {
+    let text = "4242 (tokio-runtime) wo) t 4200 4200 4100 34816 4200 1077936192 \
+                1234 0 0 0 250 50 0 0 20 0 12 0 8805 1146880 1024 18446744073709551615";
+    let stat = parse_task_stat(text, 100.0).unwrap();
+    assert_eq!(stat.state, "tracing stop");
+    assert_eq!(stat.cpu_time, 3.0);
+
+    assert!(parse_task_stat("4242 (truncated", 100.0).is_none());
+    assert!(parse_task_stat("4242 (short) S 1 2", 100.0).is_none());
+}
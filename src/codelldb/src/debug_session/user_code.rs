@@ -0,0 +1,136 @@
+use crate::fsutil::glob_to_regex;
+use crate::prelude::*;
+
+use std::path::Path;
+
+use adapter_protocol::*;
+use lldb::*;
+use regex_lite::Regex;
+
+// Determines which source files are "user code" for the purposes of "Just My Code" stepping.
+pub(super) struct UserCodeFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl UserCodeFilter {
+    pub(super) fn new(base: &Path, settings: &JustMyCode) -> Result<UserCodeFilter, Error> {
+        let compile = |patterns: &[String]| -> Result<Vec<Regex>, Error> {
+            let mut result = Vec::new();
+            for pattern in patterns {
+                // Patterns starting with `**` match anywhere, other relative patterns are relative to `base`.
+                let pattern = if pattern.starts_with("**") || Path::new(pattern).is_absolute() {
+                    pattern.clone()
+                } else {
+                    base.join(pattern).to_string_lossy().into_owned()
+                };
+                let mut regex = glob_to_regex(&pattern);
+                if cfg!(windows) {
+                    regex.insert_str(0, "(?i)");
+                }
+                match Regex::new(&regex) {
+                    Ok(regex) => result.push(regex),
+                    Err(err) => bail!(blame_user(str_error(format!(
+                        "Invalid pattern \"{}\": {}",
+                        pattern, err
+                    )))),
+                }
+            }
+            Ok(result)
+        };
+        // By default, user code is everything under `base`.
+        let default_include = [base.join("**").to_string_lossy().into_owned()];
+        Ok(UserCodeFilter {
+            include: compile(settings.include.as_deref().unwrap_or(&default_include))?,
+            exclude: compile(settings.exclude.as_deref().unwrap_or_default())?,
+        })
+    }
+
+    pub(super) fn is_user_path(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy().replace('\\', "/");
+        self.include.iter().any(|r| r.is_match(&path)) && !self.exclude.iter().any(|r| r.is_match(&path))
+    }
+}
+
+impl super::DebugSession {
+    pub(super) fn init_just_my_code(&mut self, just_my_code: &Either<bool, JustMyCode>) -> Result<(), Error> {
+        self.just_my_code = match just_my_code {
+            Either::First(false) => None,
+            Either::First(true) => Some(UserCodeFilter::new(&self.relative_path_base, &JustMyCode::default())?),
+            Either::Second(settings) => Some(UserCodeFilter::new(&self.relative_path_base, settings)?),
+        };
+        Ok(())
+    }
+
+    // Frames without source information are never user code.
+    pub(super) fn is_user_code(&self, frame: &SBFrame) -> bool {
+        let Some(filter) = &self.just_my_code else {
+            return true;
+        };
+        match frame.line_entry() {
+            Some(le) => match self.map_filespec_to_local(&le.file_spec()) {
+                Some(local_path) => filter.is_user_path(&local_path),
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    // Step into, stepping back out of any functions that aren't user code.
+    pub(super) fn step_into_user_code(&self, thread: &SBThread) -> Result<(), Error> {
+        self.with_sync_mode(|| {
+            let start_frame = thread.frame_at_index(0);
+            thread.step_into(RunMode::OnlyDuringStepping)?;
+            loop {
+                if self.current_cancellation.is_cancelled() {
+                    bail!("Cancelled");
+                }
+                // The step was interrupted by something else (a breakpoint, a signal, etc), which the user
+                // should see, so report the stop where it happened.
+                if !matches!(thread.stop_reason(), StopReason::PlanComplete | StopReason::Trace) {
+                    return Ok(());
+                }
+                let frame = thread.frame_at_index(0);
+                if !frame.is_valid() || self.is_user_code(&frame) {
+                    return Ok(());
+                }
+                thread.step_out()?;
+                if thread.frame_at_index(0) == start_frame {
+                    // Back in the statement we were stepping through - keep going.
+                    thread.step_into(RunMode::OnlyDuringStepping)?;
+                }
+            }
+        })
+    }
+
+    // Step out to the first user code frame above the current one.
+    pub(super) fn step_out_to_user_code(&self, thread: &SBThread) -> Result<(), Error> {
+        let user_frame = thread.frames().enumerate().skip(1).find(|(_, frame)| self.is_user_code(frame));
+        match user_frame {
+            Some((index, _)) if index > 1 => thread.step_out_of_frame(&thread.frame_at_index(index as u32 - 1))?,
+            _ => thread.step_out()?,
+        }
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_default_filter() {
+    let base = Path::new("/home/user/project");
+    let filter = UserCodeFilter::new(base, &JustMyCode::default()).unwrap();
+    assert!(filter.is_user_path(Path::new("/home/user/project/src/main.rs")));
+    assert!(filter.is_user_path(Path::new("/home/user/project/tests/a/b.rs")));
+    assert!(!filter.is_user_path(Path::new("/rustc/1234abcd/library/std/src/rt.rs")));
+    assert!(!filter.is_user_path(Path::new("/home/user/.cargo/registry/src/serde-1.0/src/lib.rs")));
+    assert!(!filter.is_user_path(Path::new("/home/user/project2/src/main.rs")));
+
+    let settings = JustMyCode {
+        include: None,
+        exclude: Some(vec!["vendor/**".into()]),
+    };
+    let filter = UserCodeFilter::new(base, &settings).unwrap();
+    assert!(filter.is_user_path(Path::new("/home/user/project/src/main.rs")));
+    assert!(!filter.is_user_path(Path::new("/home/user/project/vendor/foo/src/lib.rs")));
+}
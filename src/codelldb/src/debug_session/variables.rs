@@ -2,8 +2,9 @@ use crate::prelude::*;
 
 use crate::expressions::{self, FormatSpec, PreparedExpression};
 use crate::handles::Handle;
-use crate::python::EvalContext;
+use crate::python::{EvalContext, PyObject, PythonSession};
 
+use super::breakpoints::{bind_exception_var, EXCEPTION_VAR};
 use super::into_string_lossy;
 use super::AsyncResponse;
 
@@ -16,6 +17,9 @@ use std::collections::HashMap;
 use std::fmt::Write;
 use std::time;
 
+// Upper limit on the number of characters read by the wide string formats, even when the summary is unlimited.
+const MAX_WIDE_STRING_LENGTH: usize = 1 << 20;
+
 pub enum Container {
     StackFrame(SBFrame),
     Locals(SBFrame),
@@ -71,6 +75,7 @@ impl super::DebugSession {
         let variables = match container {
             Container::Locals(frame) => {
                 let ret_val = frame.thread().stop_return_value();
+                let exception = self.exception_value(&frame.thread());
                 let variables = frame.variables(&VariableOptions {
                     arguments: true,
                     locals: true,
@@ -85,6 +90,16 @@ impl super::DebugSession {
                     variable.name = "[return value]".to_owned();
                     variables.insert(0, variable);
                 }
+                // Prepend the exception object or panic payload, if stopped on an exception breakpoint.
+                if let Some(exception) = exception {
+                    let eval_name = format!("${}", EXCEPTION_VAR);
+                    let mut variable = self.var_to_variable(&exception, "", Some(container_handle));
+                    variable.name = "[exception]".to_owned();
+                    variable.variables_reference =
+                        self.get_var_handle(Some(container_handle), &eval_name, &exception).unwrap_or(0);
+                    variable.evaluate_name = Some(eval_name);
+                    variables.insert(0, variable);
+                }
                 variables
             }
             Container::Statics(frame) => {
@@ -115,11 +130,30 @@ impl super::DebugSession {
             Container::SBValue(var) => {
                 let container_eval_name = self.compose_container_eval_name(container_handle);
                 let var = var.clone();
-                let mut vars_iter = var.children();
+                // Natvis visualizers do not apply to the [raw] view of a value, nor to expressions with the `!` specifier.
+                let is_raw_view = self
+                    .var_refs
+                    .get_full_info(container_handle)
+                    .map_or(false, |(_, key, _)| key == "[raw]" || key.ends_with(",!"));
+                // Natvis items are collected up front, because converting them requires mutable access to the session.
+                let natvis_expansion = match self.natvis_expand(&var) {
+                    Some(expansion) if !is_raw_view => {
+                        Some((expansion.hide_raw_view, expansion.items.collect::<Vec<_>>()))
+                    }
+                    _ => None,
+                };
+                let mut vars_iter: Box<dyn Iterator<Item = SBValue>> = match &natvis_expansion {
+                    Some((_, items)) => Box::new(items.iter().cloned()),
+                    None => Box::new(var.children()),
+                };
                 let mut variables =
                     self.convert_scope_values(&mut vars_iter, &container_eval_name, Some(container_handle), false)?;
-                // If synthetic, add [raw] view.
-                if var.is_synthetic() {
+                // If synthetic or expanded via Natvis, add [raw] view.
+                let show_raw_view = match &natvis_expansion {
+                    Some((hide_raw_view, _)) => !hide_raw_view,
+                    None => var.is_synthetic(),
+                };
+                if show_raw_view {
                     let raw_var = var.non_synthetic_value();
                     let handle = self.var_refs.create(Some(container_handle), "[raw]", Container::SBValue(raw_var));
                     let raw = Variable {
@@ -266,7 +300,7 @@ impl super::DebugSession {
 
     // Generate a handle for a variable.
     fn get_var_handle(&mut self, parent_handle: Option<Handle>, key: &str, var: &SBValue) -> Option<Handle> {
-        if var.num_children() > 0 || var.is_synthetic() {
+        if var.num_children() > 0 || var.is_synthetic() || self.has_natvis_expansion(var) {
             Some(self.var_refs.create(parent_handle, key, Container::SBValue(var.clone())))
         } else {
             None
@@ -302,7 +336,9 @@ impl super::DebugSession {
             }
         }
 
-        let summary = if let Some(summary_str) = var.summary().map(|s| into_string_lossy(s)) {
+        let summary = if let Some(summary_str) = self.natvis_summary(var.as_ref(), unlimited) {
+            summary_str
+        } else if let Some(summary_str) = var.summary().map(|s| into_string_lossy(s)) {
             summary_str
         } else if let Some(value_str) = var.value().map(|s| into_string_lossy(s)) {
             value_str
@@ -358,11 +394,24 @@ impl super::DebugSession {
     }
 
     // unlimited: if true, return the full summary without truncating or timing out.
-    fn get_container_summary(&self, var: &SBValue, unlimited: bool) -> String {
+    pub(super) fn get_container_summary(&self, var: &SBValue, unlimited: bool) -> String {
+        match self.natvis_expand(var) {
+            Some(expansion) => self.summarize_children(expansion.items, unlimited),
+            None => self.summarize_children(var.children(), unlimited),
+        }
+    }
+
+    // Same as get_container_summary(), but bypasses visualizers and synthetic children providers.
+    fn get_raw_container_summary(&self, var: &SBValue, unlimited: bool) -> String {
+        let var = var.non_synthetic_value();
+        self.summarize_children(var.children(), unlimited)
+    }
+
+    fn summarize_children(&self, children: impl Iterator<Item = SBValue>, unlimited: bool) -> String {
         let start = time::SystemTime::now();
         let mut summary = String::from("{");
         let mut sep = "";
-        for child in var.children() {
+        for child in children {
             let should_stop = self.current_cancellation.is_cancelled()
                 || (!unlimited
                     && (summary.len() > self.max_summary_length
@@ -526,7 +575,7 @@ impl super::DebugSession {
             Ok(sbval) => {
                 let sbval = self.apply_format_spec(sbval, &format_spec).map_err(blame_user)?;
                 let handle = self.get_var_handle(None, expression, &sbval);
-                let summary = self.get_var_summary(&sbval, for_clipboard);
+                let summary = self.get_formatted_summary(&sbval, &format_spec, for_clipboard);
                 Ok(EvaluateResponseBody {
                     result: summary,
                     type_: sbval.display_type_name().map(|s| s.to_owned()),
@@ -546,33 +595,54 @@ impl super::DebugSession {
         expression: &PreparedExpression,
         frame: Option<&SBFrame>,
     ) -> Result<SBValue, Error> {
+        // Substitute `$exception`, if the thread is stopped on an exception breakpoint.
+        let bound_expr = match frame {
+            Some(frame) if expression.references_pseudo_var(EXCEPTION_VAR) => self
+                .exception_value(&frame.thread())
+                .and_then(|exception| bind_exception_var(expression, &exception)),
+            _ => None,
+        };
+        let expression = bound_expr.as_ref().unwrap_or(expression);
+
         match (expression, self.python.as_ref()) {
-        (PreparedExpression::Native(pp_expr), _) => {
-            let result = match frame {
-                Some(frame) => frame.evaluate_expression(&pp_expr).into_result(),
-                None => self.target.evaluate_expression(&pp_expr).into_result(),
-            };
-            let result = result.map_err(|err| blame_user(err.into()))?;
-            Ok(result)
-        }
-        (PreparedExpression::Python(pp_expr), Some(python)) | //.
-        (PreparedExpression::Simple(pp_expr), Some(python)) => {
-            let pycode = python.compile_code(pp_expr, "<input>").map_err(blame_user)?;
-            let exec_context = if let Some(frame) = frame {
-                SBExecutionContext::from_frame(frame)
-            } else {
-                SBExecutionContext::new()
-            };
-            let eval_cotext = if matches!(expression, PreparedExpression::Simple(_)) {
-                EvalContext::SimpleExpression
-            }else {
-                EvalContext::PythonExpression
-            };
-            let result = python.evaluate(&pycode, &exec_context, eval_cotext).map_err(blame_user)?;
-            Ok(result)
+            (PreparedExpression::Native(pp_expr), _) => {
+                let result = match frame {
+                    Some(frame) => frame.evaluate_expression(&pp_expr).into_result(),
+                    None => self.target.evaluate_expression(&pp_expr).into_result(),
+                };
+                let result = result.map_err(|err| blame_user(err.into()))?;
+                Ok(result)
+            }
+            (PreparedExpression::Simple(expr), Some(python)) if !expr.has_rust_syntax() => {
+                let pycode = python.compile_code(&expr.to_python(), "<input>").map_err(blame_user)?;
+                self.evaluate_pycode(python, &pycode, frame, EvalContext::SimpleExpression)
+            }
+            // Without Python, or when using Rust syntax, simple expressions are evaluated natively.
+            (PreparedExpression::Simple(expr), _) => {
+                expressions::evaluate_simple_expr(expr, frame, &self.target).map_err(|err| blame_user(err).into())
+            }
+            (PreparedExpression::Python(pp_expr), Some(python)) => {
+                let pycode = python.compile_code(pp_expr, "<input>").map_err(blame_user)?;
+                self.evaluate_pycode(python, &pycode, frame, EvalContext::PythonExpression)
+            }
+            (PreparedExpression::Python(_), None) => bail!(blame_user("Python expressions are disabled.".into())),
         }
-        _ => bail!(blame_user("Python expressions are disabled.".into())),
     }
+
+    fn evaluate_pycode(
+        &self,
+        python: &PythonSession,
+        pycode: &PyObject,
+        frame: Option<&SBFrame>,
+        eval_context: EvalContext,
+    ) -> Result<SBValue, Error> {
+        let exec_context = if let Some(frame) = frame {
+            SBExecutionContext::from_frame(frame)
+        } else {
+            SBExecutionContext::new()
+        };
+        let result = python.evaluate(pycode, &exec_context, eval_context).map_err(blame_user)?;
+        Ok(result)
     }
 
     pub(super) fn handle_set_variable(&mut self, args: SetVariableArguments) -> Result<SetVariableResponseBody, Error> {
@@ -588,6 +658,7 @@ impl super::DebugSession {
         if let Some(child) = child {
             match child.set_value(&args.value) {
                 Ok(()) => {
+                    self.notify_memory_views();
                     let handle = self.get_var_handle(Some(container_handle), child.name().unwrap_or_default(), &child);
                     let response = SetVariableResponseBody {
                         value: self.get_var_summary(&child, false),
@@ -606,30 +677,177 @@ impl super::DebugSession {
 
     pub(super) fn apply_format_spec(&self, sbval: SBValue, format_spec: &FormatSpec) -> Result<SBValue, Error> {
         let mut sbval = sbval;
+        if format_spec.raw {
+            sbval = sbval.non_synthetic_value();
+        }
         if let Some(size) = format_spec.array {
             let var_type = sbval.type_();
             let type_class = var_type.type_class();
-            sbval = if type_class.intersects(TypeClass::Pointer | TypeClass::Reference) {
+            let (mut addr, elem_type) = if type_class.intersects(TypeClass::Pointer | TypeClass::Reference) {
                 // For pointers and references we re-interpret the pointee.
-                let array_type = var_type.pointee_type().array_type(size as u64);
                 let pointee = sbval.dereference().into_result()?;
                 let addr = pointee.address().ok_or_else(|| str_error("No address"))?;
-                sbval.target().create_value_from_address("(as array)", &addr, &array_type)
+                (addr, var_type.pointee_type())
             } else if type_class.intersects(TypeClass::Array) {
                 // For arrays, re-interpret the array length.
-                let array_type = var_type.array_element_type().array_type(size as u64);
                 let addr = sbval.address().ok_or_else(|| str_error("No address"))?;
-                sbval.target().create_value_from_address("(as array)", &addr, &array_type)
+                (addr, var_type.array_element_type())
+            } else if sbval.is_synthetic() && sbval.num_children() > 0 {
+                // For collections with synthetic children, e.g. std::vector, assume that elements are stored contiguously.
+                let first = sbval.child_at_index(0);
+                let addr = first.address().ok_or_else(|| str_error("No address"))?;
+                (addr, first.type_())
             } else {
                 // For other types re-interpret the value itself.
-                let array_type = var_type.array_type(size as u64);
                 let addr = sbval.address().ok_or_else(|| str_error("No address"))?;
-                sbval.target().create_value_from_address("(as array)", &addr, &array_type)
+                (addr, var_type)
             };
+            if format_spec.array_offset > 0 {
+                addr.add_offset(format_spec.array_offset as u64 * elem_type.byte_size());
+            }
+            let array_type = elem_type.array_type(size as u64);
+            sbval = sbval.target().create_value_from_address("(as array)", &addr, &array_type);
         }
         sbval.set_format(format_spec.format.unwrap_or(self.global_format));
         Ok(sbval)
     }
+
+    // Get displayable string for a value, taking into account the parts of the format specifier that
+    // affect presentation of the value itself, rather than of its children.
+    pub(super) fn get_formatted_summary(&self, var: &SBValue, format_spec: &FormatSpec, unlimited: bool) -> String {
+        if format_spec.hresult {
+            return hresult_summary(var.value_as_unsigned(0) as u32);
+        }
+        if let Some(format @ (Format::Unicode16 | Format::Unicode32)) = format_spec.format {
+            if let Some(string) = self.read_wide_string(var, format, unlimited) {
+                return string;
+            }
+        }
+        if format_spec.raw {
+            return match var.value() {
+                Some(value) => into_string_lossy(value),
+                None => self.get_raw_container_summary(var, unlimited),
+            };
+        }
+        let summary = if format_spec.no_address
+            && var.format() == Format::Default
+            && var.type_().type_class().intersects(TypeClass::Pointer | TypeClass::Reference)
+        {
+            match self.try_deref_pointer(var) {
+                Either::First(summary) => summary,
+                Either::Second(Some(pointee)) => self.get_var_summary(&pointee, unlimited),
+                Either::Second(None) => self.get_var_summary(var, unlimited),
+            }
+        } else {
+            self.get_var_summary(var, unlimited)
+        };
+        if format_spec.no_address {
+            strip_address(&summary).to_owned()
+        } else {
+            summary
+        }
+    }
+
+    // Decode a UTF-16 or UTF-32 string pointed to by `var` (or contained in an array).
+    fn read_wide_string(&self, var: &SBValue, format: Format, unlimited: bool) -> Option<String> {
+        let char_size = if format == Format::Unicode16 { 2 } else { 4 };
+        let var_type = var.type_();
+        let max_len = if unlimited { MAX_WIDE_STRING_LENGTH } else { self.max_summary_length };
+        let mut bytes = Vec::new();
+        if var_type.is_array_type() {
+            let data = var.data();
+            bytes.resize(data.byte_size(), 0);
+            data.read_raw_data(0, &mut bytes).ok()?;
+        } else if var_type.type_class().intersects(TypeClass::Pointer | TypeClass::Reference) {
+            let mut addr = var.value_as_unsigned(0);
+            let process = self.target.process();
+            let mut chunk = [0u8; 256];
+            while bytes.len() / char_size <= max_len {
+                // Keep reads aligned to the chunk size, so that they don't straddle page boundaries.
+                // The length is kept a multiple of the character size, so that a read never ends mid-character,
+                // even if the string itself is misaligned.
+                let len = chunk.len() - (addr % chunk.len() as u64) as usize;
+                let len = (len - len % char_size).max(char_size);
+                // If the string runs into unreadable memory, display the part that could be read.
+                let count = match process.read_memory(addr, &mut chunk[..len]) {
+                    Ok(count) => count,
+                    Err(_) if !bytes.is_empty() => break,
+                    Err(_) => return None,
+                };
+                let count_whole = count - count % char_size;
+                bytes.extend_from_slice(&chunk[..count_whole]);
+                if count < len || chunk[..count_whole].chunks(char_size).any(|c| c.iter().all(|b| *b == 0)) {
+                    break;
+                }
+                addr += count_whole as u64;
+            }
+        } else {
+            return None;
+        }
+
+        let big_endian = self.target.byte_order() == ByteOrder::Big;
+        let units = bytes.chunks_exact(char_size).map(|c| {
+            let mut unit = [0u8; 4];
+            unit[..char_size].copy_from_slice(c);
+            match (char_size, big_endian) {
+                (2, false) => u16::from_le_bytes([unit[0], unit[1]]) as u32,
+                (2, true) => u16::from_be_bytes([unit[0], unit[1]]) as u32,
+                (_, false) => u32::from_le_bytes(unit),
+                (_, true) => u32::from_be_bytes(unit),
+            }
+        });
+        let units = units.take_while(|u| *u != 0);
+        let (prefix, mut string) = if char_size == 2 {
+            let units: Vec<u16> = units.map(|u| u as u16).collect();
+            ('u', String::from_utf16_lossy(&units))
+        } else {
+            (
+                'U',
+                units.map(|u| char::from_u32(u).unwrap_or(char::REPLACEMENT_CHARACTER)).collect(),
+            )
+        };
+        if string.chars().count() > max_len {
+            string = string.chars().take(max_len).chain("...".chars()).collect();
+        }
+        Some(format!("{}{:?}", prefix, string))
+    }
+}
+
+// Strip the address that LLDB prepends to summaries of some pointers, e.g. `0x00001234 "string"`.
+fn strip_address(summary: &str) -> &str {
+    if let Some(rest) = summary.strip_prefix("0x") {
+        let end = rest.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(rest.len());
+        if end > 0 && rest[end..].starts_with(' ') {
+            return rest[end..].trim_start();
+        }
+    }
+    summary
+}
+
+fn hresult_summary(hr: u32) -> String {
+    let name = match hr {
+        0x00000000 => Some("S_OK"),
+        0x00000001 => Some("S_FALSE"),
+        0x80004001 => Some("E_NOTIMPL"),
+        0x80004002 => Some("E_NOINTERFACE"),
+        0x80004003 => Some("E_POINTER"),
+        0x80004004 => Some("E_ABORT"),
+        0x80004005 => Some("E_FAIL"),
+        0x8000FFFF => Some("E_UNEXPECTED"),
+        0x80070005 => Some("E_ACCESSDENIED"),
+        0x80070006 => Some("E_HANDLE"),
+        0x8007000E => Some("E_OUTOFMEMORY"),
+        0x80070057 => Some("E_INVALIDARG"),
+        _ => None,
+    };
+    let facility = (hr >> 16) & 0x1FFF;
+    match name {
+        Some(name) => format!("0x{:08X} {}", hr, name),
+        // FACILITY_WIN32
+        None if hr & 0x80000000 != 0 && facility == 7 => format!("0x{:08X} HRESULT_FROM_WIN32({})", hr, hr & 0xFFFF),
+        None if hr & 0x80000000 != 0 => format!("0x{:08X} (facility {}, code {})", hr, facility, hr & 0xFFFF),
+        None => format!("0x{:08X}", hr),
+    }
 }
 
 fn compose_eval_name<'a, 'b, A, B>(prefix: A, suffix: B) -> String
@@ -4,34 +4,115 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Write;
 use std::ops::Range;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::str;
 
 use crate::handles::Handle;
 use lldb::*;
+use regex_lite::Regex;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisassemblyOptions {
+    /// Disassembly flavor; if None, the target's default is used.
+    pub flavor: Option<String>,
+    pub show_bytes: bool,
+    pub max_bytes: usize,
+    pub uppercase_mnemonics: bool,
+    pub demangle: bool,
+    pub interleave_source: bool,
+}
+
+impl Default for DisassemblyOptions {
+    fn default() -> Self {
+        DisassemblyOptions {
+            flavor: None,
+            show_bytes: true,
+            max_bytes: 9,
+            uppercase_mnemonics: false,
+            demangle: true,
+            interleave_source: false,
+        }
+    }
+}
+
+impl DisassemblyOptions {
+    pub fn flavor(&self, target: &SBTarget) -> Option<String> {
+        self.flavor.clone().or_else(|| target.disassembly_flavor())
+    }
+
+    pub fn symbol_name<'a>(&self, symbol: &'a SBSymbol) -> &'a str {
+        let mangled = symbol.mangled_name();
+        if self.demangle || mangled.is_empty() {
+            symbol.name()
+        } else {
+            mangled
+        }
+    }
+
+    pub fn mnemonic(&self, mnemonic: &str) -> String {
+        if self.uppercase_mnemonics {
+            mnemonic.to_uppercase()
+        } else {
+            mnemonic.to_owned()
+        }
+    }
+
+    // Format instruction bytes, padded to a fixed width.
+    pub fn format_bytes(&self, bytes: &[u8]) -> String {
+        let mut dump = String::with_capacity(self.max_bytes * 3 + 2);
+        for (i, b) in bytes.iter().enumerate() {
+            if i >= self.max_bytes {
+                dump.push('>');
+                break;
+            }
+            let _ = write!(dump, "{:02X} ", b);
+        }
+        format!("{dump:<width$}", width = self.max_bytes * 3 + 2)
+    }
+}
 
 struct Ranges {
     pub by_handle: HashMap<Handle, Rc<DisassembledRange>>,
     pub by_address: Vec<Rc<DisassembledRange>>,
+    pub next_handle: Handle,
 }
 pub struct DisassembledRanges {
     target: SBTarget,
+    options: DisassemblyOptions,
     ranges: RefCell<Ranges>,
 }
 
 impl DisassembledRanges {
-    pub fn new(target: &SBTarget) -> DisassembledRanges {
+    pub fn new(target: &SBTarget, options: DisassemblyOptions) -> DisassembledRanges {
         DisassembledRanges {
             target: target.clone(),
+            options: options,
             ranges: RefCell::new(Ranges {
                 by_handle: HashMap::new(),
                 by_address: Vec::new(),
+                next_handle: 1000,
             }),
         }
     }
 
-    pub fn find_by_handle(&self, handle: Handle) -> Option<Rc<DisassembledRange>> {
-        self.ranges.borrow_mut().by_handle.get(&handle).map(|dasm| dasm.clone())
+    pub fn options(&self) -> &DisassemblyOptions {
+        &self.options
+    }
+
+    /// Change disassembly options.  Cached ranges are re-disassembled with the new options when next accessed;
+    /// their handles remain valid, since the client may still refer to them.
+    pub fn set_options(&mut self, options: DisassemblyOptions) {
+        self.options = options;
+    }
+
+    pub fn find_by_handle(
+        &self,
+        handle: Handle,
+        local_path: &dyn Fn(&SBFileSpec) -> Option<Rc<PathBuf>>,
+    ) -> Option<Rc<DisassembledRange>> {
+        let dasm = self.ranges.borrow().by_handle.get(&handle).cloned()?;
+        Some(self.refresh(dasm, local_path))
     }
 
     fn find_by_address(&self, load_addr: Address) -> Option<Rc<DisassembledRange>> {
@@ -46,44 +127,69 @@ impl DisassembledRanges {
         None
     }
 
-    pub fn from_address(&self, load_addr: Address) -> Result<Rc<DisassembledRange>, Error> {
+    /// `local_path` maps source files referenced by debug info to local paths (see `interleave_source`).
+    pub fn from_address(
+        &self,
+        load_addr: Address,
+        local_path: &dyn Fn(&SBFileSpec) -> Option<Rc<PathBuf>>,
+    ) -> Result<Rc<DisassembledRange>, Error> {
         if let Some(dasm) = self.find_by_address(load_addr) {
-            return Ok(dasm);
+            return Ok(self.refresh(dasm, local_path));
         }
 
         let addr = SBAddress::from_load_address(load_addr, &self.target);
         debug!("{:?}", addr);
 
-        let start_addr;
-        let end_addr;
-        let instructions;
+        let (start_addr, end_addr, instructions) = self.disassemble(&addr);
+        if instructions.len() > 0 {
+            Ok(self.add(start_addr, end_addr, instructions, local_path))
+        } else {
+            bail!("Can't read instructions at that address.")
+        }
+    }
+
+    // Disassemble the symbol containing `addr`, or a fixed number of instructions, if `addr` is not in scope of any symbol.
+    fn disassemble(&self, addr: &SBAddress) -> (SBAddress, SBAddress, SBInstructionList) {
+        let flavor = self.options.flavor(&self.target);
         match addr.symbol() {
             Some(symbol) => {
-                start_addr = symbol.start_address();
-                end_addr = symbol.end_address();
-                instructions = symbol.instructions(&self.target);
+                let instructions = symbol.instructions(&self.target, flavor.as_deref());
+                (symbol.start_address(), symbol.end_address(), instructions)
             }
             None => {
                 // How many instructions to put into DisassembledRange if the address is not in scope of any symbol.
                 const NO_SYMBOL_INSTRUCTIONS: u32 = 32;
-                start_addr = addr.clone();
-                let flavor = self.target.disassembly_flavor();
-                instructions =
+                let start_addr = addr.clone();
+                let instructions =
                     self.target.read_instructions(&start_addr, NO_SYMBOL_INSTRUCTIONS + 1, flavor.as_deref());
-                end_addr = if instructions.len() > 0 {
+                let end_addr = if instructions.len() > 0 {
                     let last_instr = instructions.instruction_at_index((instructions.len() - 1) as u32);
                     last_instr.address()
                 } else {
                     start_addr.clone()
                 };
+                (start_addr, end_addr, instructions)
             }
         }
+    }
 
-        if instructions.len() > 0 {
-            Ok(self.add(start_addr, end_addr, instructions))
-        } else {
-            bail!("Can't read instructions at that address.")
+    // Re-disassemble a cached range, if it had been produced with options other than the current ones.
+    fn refresh(
+        &self,
+        dasm: Rc<DisassembledRange>,
+        local_path: &dyn Fn(&SBFileSpec) -> Option<Rc<PathBuf>>,
+    ) -> Rc<DisassembledRange> {
+        if dasm.options == self.options {
+            return dasm;
         }
+        let (start_addr, end_addr, instructions) = self.disassemble(&dasm.start_addr);
+        let refreshed = Rc::new(self.build(dasm.handle, start_addr, end_addr, instructions, local_path));
+        let mut ranges = self.ranges.borrow_mut();
+        ranges.by_handle.insert(dasm.handle, refreshed.clone());
+        if let Some(entry) = ranges.by_address.iter_mut().find(|entry| entry.handle == dasm.handle) {
+            *entry = refreshed.clone();
+        }
+        refreshed
     }
 
     fn add(
@@ -91,29 +197,56 @@ impl DisassembledRanges {
         start_addr: SBAddress,
         end_addr: SBAddress,
         instructions: SBInstructionList,
+        local_path: &dyn Fn(&SBFileSpec) -> Option<Rc<PathBuf>>,
     ) -> Rc<DisassembledRange> {
+        let handle = {
+            let mut ranges = self.ranges.borrow_mut();
+            ranges.next_handle += 1;
+            ranges.next_handle - 1
+        };
+        let dasm = Rc::new(self.build(handle, start_addr, end_addr, instructions, local_path));
         let mut ranges = self.ranges.borrow_mut();
-        let handle = (ranges.by_handle.len() + 1000) as Handle;
-        let instruction_addrs = instructions.iter().map(|i| i.address().load_address(&self.target)).collect();
+        ranges.by_handle.insert(handle, dasm.clone());
+        let idx = ranges.by_address.partition_point(|entry| entry.load_range.start < dasm.load_range.start);
+        ranges.by_address.insert(idx, dasm.clone());
+        dasm
+    }
+
+    fn build(
+        &self,
+        handle: Handle,
+        start_addr: SBAddress,
+        end_addr: SBAddress,
+        instructions: SBInstructionList,
+        local_path: &dyn Fn(&SBFileSpec) -> Option<Rc<PathBuf>>,
+    ) -> DisassembledRange {
+        let instruction_addrs: Vec<Address> =
+            instructions.iter().map(|i| i.address().load_address(&self.target)).collect();
+        let source_annotations = if self.options.interleave_source {
+            source_annotations(&instructions, local_path)
+        } else {
+            vec![Vec::new(); instruction_addrs.len()]
+        };
+        let (line_addresses, instruction_lines) = layout_lines(&instruction_addrs, &source_annotations);
         let start_load_addr = start_addr.load_address(&self.target);
         let end_load_addr = end_addr.load_address(&self.target);
         let source_name = match start_addr.symbol() {
-            Some(symbol) => format!("@{}", symbol.name()),
+            Some(symbol) => format!("@{}", self.options.symbol_name(&symbol)),
             None => format!("@{:x}..{:x}", start_load_addr, end_load_addr),
         };
-        let dasm = Rc::new(DisassembledRange {
+        DisassembledRange {
             handle: handle,
             target: self.target.clone(),
+            options: self.options.clone(),
             start_addr: start_addr,
             load_range: start_load_addr..end_load_addr,
             source_name: source_name,
             instructions: instructions,
             instruction_addresses: instruction_addrs,
-        });
-        ranges.by_handle.insert(handle, dasm.clone());
-        let idx = ranges.by_address.partition_point(|dasm| dasm.load_range.start < start_load_addr);
-        ranges.by_address.insert(idx, dasm.clone());
-        dasm
+            source_annotations: source_annotations,
+            line_addresses: line_addresses,
+            instruction_lines: instruction_lines,
+        }
     }
 }
 
@@ -129,11 +262,18 @@ pub struct AdapterData {
 pub struct DisassembledRange {
     handle: Handle,
     target: SBTarget,
+    options: DisassemblyOptions,
     start_addr: SBAddress,
     load_range: Range<Address>,
     source_name: String,
     instructions: SBInstructionList,
     instruction_addresses: Vec<Address>,
+    // Comment lines to be displayed before each instruction.
+    source_annotations: Vec<Vec<String>>,
+    // Address corresponding to each document line, starting from the first line after the header.
+    line_addresses: Vec<Address>,
+    // Document line number of each instruction.
+    instruction_lines: Vec<u32>,
 }
 
 impl DisassembledRange {
@@ -146,15 +286,19 @@ impl DisassembledRange {
     }
 
     pub fn line_num_by_address(&self, load_addr: Address) -> u32 {
-        self.instruction_addresses.partition_point(|addr| *addr < load_addr) as u32 + 3
+        let idx = self.instruction_addresses.partition_point(|addr| *addr < load_addr);
+        match self.instruction_lines.get(idx) {
+            Some(line) => *line,
+            None => self.line_addresses.len() as u32 + HEADER_LINES + 1,
+        }
     }
 
     pub fn address_by_line_num(&self, line: u32) -> Address {
-        self.instruction_addresses[line as usize - 3]
+        self.line_addresses[(line - HEADER_LINES - 1) as usize]
     }
 
     pub fn adapter_data(&self) -> AdapterData {
-        let line_offsets = self.instruction_addresses.windows(2).map(|w| (w[1] - w[0]) as u32).collect();
+        let line_offsets = self.line_addresses.windows(2).map(|w| (w[1] - w[0]) as u32).collect();
         AdapterData {
             start: self.load_range.start,
             end: self.load_range.end,
@@ -174,17 +318,21 @@ impl DisassembledRange {
             .collect()
     }
 
-    pub fn get_source_text(&self, max_instr_bytes: usize) -> String {
+    pub fn get_source_text(&self) -> String {
         let mut text = String::new();
 
         #[allow(unused_must_use)]
         {
             write!(text, "; Symbol: ");
             if let Some(symbol) = self.start_addr.symbol() {
-                write!(text, "{}", symbol.display_name());
                 let mangled = symbol.mangled_name();
-                if mangled.len() > 0 {
-                    write!(text, ", mangled name={}", mangled);
+                if self.options.demangle || mangled.is_empty() {
+                    write!(text, "{}", symbol.display_name());
+                    if mangled.len() > 0 {
+                        write!(text, ", mangled name={}", mangled);
+                    }
+                } else {
+                    write!(text, "{}", mangled);
                 }
             } else {
                 write!(text, "no symbol info");
@@ -201,27 +349,28 @@ impl DisassembledRange {
         }
 
         let mut instr_data = vec![];
-        let mut dump = String::new();
-        for instr in self.instructions.iter() {
+        for (instr, annotations) in self.instructions.iter().zip(self.source_annotations.iter()) {
+            for annotation in annotations {
+                let _ = writeln!(text, "; {}", annotation);
+            }
             let load_addr = instr.address().load_address(&self.target);
-            dump.clear();
-            instr_data.resize(instr.byte_size(), 0);
-            if instr.data(&self.target).read_raw_data(0, &mut instr_data).is_ok() {
-                for (i, b) in instr_data.iter().enumerate() {
-                    if i >= max_instr_bytes {
-                        dump.push('>');
-                        break;
-                    }
-                    let _ = write!(dump, "{:02X} ", b);
+            let dump = if self.options.show_bytes {
+                instr_data.resize(instr.byte_size(), 0);
+                if instr.data(&self.target).read_raw_data(0, &mut instr_data).is_err() {
+                    instr_data.clear();
                 }
-            }
-            let mnemonic = instr.mnemonic(&self.target);
+                format!("{} ", self.options.format_bytes(&instr_data))
+            } else {
+                String::new()
+            };
+            let mnemonic = self.options.mnemonic(instr.mnemonic(&self.target));
             let operands = instr.operands(&self.target);
-            let comment = instr.comment(&self.target);
+            let annotation = annotate_instruction(&self.target, &instr, &self.options);
+            let comment = compose_comment(instr.comment(&self.target), annotation.as_deref());
             let comment_sep = if comment.is_empty() { "" } else { "  ; " };
-            #[rustfmt::skip]
-            let _ = writeln!(text, "{load_addr:08X}: {dump:<dump_width$} {mnemonic:<6} {operands}{comment_sep}{comment}",
-                dump_width=max_instr_bytes * 3 + 2
+            let _ = writeln!(
+                text,
+                "{load_addr:08X}: {dump}{mnemonic:<6} {operands}{comment_sep}{comment}"
             );
         }
 
@@ -229,19 +378,499 @@ impl DisassembledRange {
     }
 }
 
+// Number of header lines at the top of a disassembly document.
+const HEADER_LINES: u32 = 2;
+// Maximum number of preceding source lines to include when line number increases within the same file.
+const MAX_SOURCE_CONTEXT: u32 = 4;
+
+// Compute addresses of all document lines (excluding the header) and line numbers of instructions.
+// Annotation lines are mapped to the address of the instruction that follows them.
+fn layout_lines(instruction_addresses: &[Address], annotations: &[Vec<String>]) -> (Vec<Address>, Vec<u32>) {
+    let mut line_addresses = Vec::new();
+    let mut instruction_lines = Vec::new();
+    for (addr, annotations) in instruction_addresses.iter().zip(annotations.iter()) {
+        line_addresses.extend(std::iter::repeat(*addr).take(annotations.len()));
+        line_addresses.push(*addr);
+        instruction_lines.push(line_addresses.len() as u32 + HEADER_LINES);
+    }
+    (line_addresses, instruction_lines)
+}
+
+// Generate source line comments to be displayed before each instruction in the interleaved mode.
+fn source_annotations(
+    instructions: &SBInstructionList,
+    local_path: &dyn Fn(&SBFileSpec) -> Option<Rc<PathBuf>>,
+) -> Vec<Vec<String>> {
+    let mut source_files: HashMap<PathBuf, Option<Vec<String>>> = HashMap::new();
+    let mut prev_line: Option<(PathBuf, u32)> = None;
+    let mut prev_inlined: Option<String> = None;
+    let mut result = Vec::new();
+    for instr in instructions.iter() {
+        let mut annotations = Vec::new();
+        let addr = instr.address();
+
+        let inlined_block = addr.block().and_then(|b| b.containing_inlined_block());
+        let inlined = inlined_block.as_ref().map(|b| b.inlined_name().to_owned());
+        if inlined != prev_inlined {
+            match &inlined_block {
+                Some(block) => {
+                    let call_site = block.inlined_call_site_file();
+                    annotations.push(format!(
+                        "Inlined {} (called from {}:{})",
+                        block.inlined_name(),
+                        call_site.filename().display(),
+                        block.inlined_call_site_line()
+                    ));
+                }
+                None => annotations.push(format!(
+                    "End of inlined {}",
+                    prev_inlined.as_deref().unwrap_or_default()
+                )),
+            }
+            prev_inlined = inlined;
+            // Always show the source line after an inlining boundary.
+            prev_line = None;
+        }
+
+        // Line 0 denotes compiler-generated code, which we attribute to the preceding line.
+        if let Some(le) = addr.line_entry().filter(|le| le.line() > 0) {
+            let file_spec = le.file_spec();
+            let path = match local_path(&file_spec) {
+                Some(path) => (*path).clone(),
+                None => file_spec.path(),
+            };
+            let line = le.line();
+            let first_line = match &prev_line {
+                Some((prev_path, prev)) if *prev_path == path && *prev == line => None,
+                Some((prev_path, prev)) if *prev_path == path && *prev < line && line - prev <= MAX_SOURCE_CONTEXT => {
+                    Some(prev + 1)
+                }
+                _ => Some(line),
+            };
+            if let Some(first_line) = first_line {
+                annotations.push(format!("{}:{}", path.display(), line));
+                let lines = source_files.entry(path.clone()).or_insert_with(|| {
+                    std::fs::read_to_string(&path)
+                        .ok()
+                        .map(|text| text.lines().map(|l| l.to_owned()).collect())
+                });
+                if let Some(lines) = lines {
+                    for n in first_line..=line {
+                        if let Some(text) = lines.get(n as usize - 1) {
+                            annotations.push(format!("{:>6} | {}", n, text.trim_end()));
+                        }
+                    }
+                }
+                prev_line = Some((path, line));
+            }
+        }
+        result.push(annotations);
+    }
+    result
+}
+
+pub fn control_flow_kind(target: &SBTarget, instr: &SBInstruction) -> InstructionControlFlowKind {
+    let kind = instr.control_flow_kind(target);
+    if kind == InstructionControlFlowKind::Unknown {
+        if target.triple().starts_with("arm") {
+            match instr.mnemonic(target) {
+                "bl" => InstructionControlFlowKind::Call,
+                "b" => InstructionControlFlowKind::Jump,
+                _ => InstructionControlFlowKind::Other,
+            }
+        } else {
+            kind
+        }
+    } else {
+        kind
+    }
+}
+
+// Generate a symbolic annotation for the instruction's operands:
+// - `symbol+offset` for targets of control flow instructions,
+// - the name of the referenced global or static variable for PC-relative data references,
+// - a preview of the referenced string literal, if the memory looks like a C string.
+pub fn annotate_instruction(target: &SBTarget, instr: &SBInstruction, options: &DisassemblyOptions) -> Option<String> {
+    let operands = instr.operands(target);
+    // Instruction classification requires LLDB 16+.
+    let kind = match lldb_stub::v16.resolve() {
+        Ok(_token) => control_flow_kind(target, instr),
+        Err(_) => InstructionControlFlowKind::Unknown,
+    };
+    match kind {
+        InstructionControlFlowKind::Call
+        | InstructionControlFlowKind::FarCall
+        | InstructionControlFlowKind::Jump
+        | InstructionControlFlowKind::FarJump
+        | InstructionControlFlowKind::CondJump => {
+            let addr = match immediate_operand(operands) {
+                Some(addr) => addr,
+                None => {
+                    // Indirect jump via a PC-relative pointer, e.g. `jmpq *0x1234(%rip)`.
+                    let ptr = pc_relative_address(target, instr, operands)?;
+                    read_pointer(target, ptr)?
+                }
+            };
+            symbolize_address(target, addr, options)
+        }
+        _ => {
+            let addr = pc_relative_address(target, instr, operands)?;
+            let name = SBAddress::from_load_address(addr, target)
+                .symbol()
+                .filter(|symbol| symbol.symbol_type() != SymbolType::Code)
+                .map(|symbol| symbol_offset(target, &symbol, addr, options));
+            let mut buffer = [0u8; MAX_STRING_PREVIEW + 1];
+            let preview = match target.read_memory(&SBAddress::from_load_address(addr, target), &mut buffer) {
+                Ok(count) => c_string_preview(&buffer[..count]),
+                Err(_) => None,
+            };
+            match (name, preview) {
+                (Some(name), Some(preview)) => Some(format!("{} {}", name, preview)),
+                (Some(name), None) => Some(name),
+                (None, Some(preview)) => Some(preview),
+                (None, None) => None,
+            }
+        }
+    }
+}
+
+// Combine LLDB's own instruction comment with our annotation.
+pub fn compose_comment(comment: &str, annotation: Option<&str>) -> String {
+    match annotation {
+        Some(annotation) if comment.is_empty() => annotation.to_owned(),
+        Some(annotation) if !comment.contains(annotation) => format!("{}, {}", comment, annotation),
+        _ => comment.to_owned(),
+    }
+}
+
+pub fn symbolize_address(target: &SBTarget, addr: Address, options: &DisassemblyOptions) -> Option<String> {
+    let symbol = SBAddress::from_load_address(addr, target).symbol()?;
+    Some(symbol_offset(target, &symbol, addr, options))
+}
+
+fn symbol_offset(target: &SBTarget, symbol: &SBSymbol, addr: Address, options: &DisassemblyOptions) -> String {
+    let name = options.symbol_name(symbol);
+    let offset = addr.wrapping_sub(symbol.start_address().load_address(target));
+    if offset == 0 {
+        name.to_owned()
+    } else {
+        format!("{}+0x{:x}", name, offset)
+    }
+}
+
+fn pc_relative_address(target: &SBTarget, instr: &SBInstruction, operands: &str) -> Option<Address> {
+    let displacement = pc_relative_displacement(operands)?;
+    let next_instr = instr.address().load_address(target) + instr.byte_size() as u64;
+    Some(next_instr.wrapping_add(displacement as u64))
+}
+
+// Parse an immediate branch target, e.g. `0x1234` (also accepts AT&T-style `$0x1234`).
+pub fn immediate_operand(operands: &str) -> Option<Address> {
+    let operands = operands.trim();
+    let operands = operands.strip_prefix('$').unwrap_or(operands);
+    let hex = operands.strip_prefix("0x").or_else(|| operands.strip_prefix("0X"))?;
+    u64::from_str_radix(hex, 16).ok()
+}
+
+// Find displacement of a PC-relative memory operand, e.g. `0x1234(%rip)` or `[rip + 0x1234]`.
+pub fn pc_relative_displacement(operands: &str) -> Option<i64> {
+    lazy_static::lazy_static! {
+        static ref ATT: Regex = Regex::new(r"(-?)0x([[:xdigit:]]+)\(%rip\)").unwrap();
+        static ref INTEL: Regex = Regex::new(r"\[rip\s*([+-])\s*0x([[:xdigit:]]+)\]").unwrap();
+    }
+    let (negative, value) = if let Some(caps) = ATT.captures(operands) {
+        (&caps[1] == "-", i64::from_str_radix(&caps[2], 16).ok()?)
+    } else if let Some(caps) = INTEL.captures(operands) {
+        (&caps[1] == "-", i64::from_str_radix(&caps[2], 16).ok()?)
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// A memory operand addressing `base + index * scale + displacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryOperand {
+    pub base: Option<String>,
+    pub index: Option<String>,
+    pub scale: u64,
+    pub displacement: i64,
+}
+
+/// Operand of an indirect call or jump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndirectOperand {
+    Register(String),
+    Memory(MemoryOperand),
+}
+
+// Parse the operand of an indirect branch, e.g. `*%rax`, `*0x10(%rax)`, `qword ptr [rax + 0x10]` or `x8`.
+pub fn indirect_operand(operand: &str) -> Option<IndirectOperand> {
+    let operand = operand.trim();
+    let operand = operand.strip_prefix('*').unwrap_or(operand);
+    if let Some(reg) = register_operand(operand) {
+        Some(IndirectOperand::Register(reg.to_owned()))
+    } else {
+        memory_operand(operand).map(IndirectOperand::Memory)
+    }
+}
+
+// Parse a register operand, e.g. `%rax`, `rax` or `x8`.
+pub fn register_operand(operand: &str) -> Option<&str> {
+    lazy_static::lazy_static! {
+        static ref REGISTER: Regex = Regex::new(r"^%?([a-z][a-z0-9]*)$").unwrap();
+    }
+    REGISTER.captures(operand.trim()).map(|caps| caps.get(1).unwrap().as_str())
+}
+
+// Parse a memory operand in AT&T (`-0x8(%rbp,%rax,8)`), Intel (`qword ptr [rbp + 8*rax - 0x8]`)
+// or AArch64 (`[x29, #-0x8]`) syntax.
+pub fn memory_operand(operand: &str) -> Option<MemoryOperand> {
+    lazy_static::lazy_static! {
+        static ref ATT: Regex =
+            Regex::new(r"^(-?0x[[:xdigit:]]+|-?\d+)?\((?:%(\w+))?(?:,%(\w+)(?:,(\d+))?)?\)$").unwrap();
+        static ref BRACKETS: Regex = Regex::new(r"^(?:\w+ ptr )?\[([^\]]+)\]$").unwrap();
+    }
+    let operand = operand.trim();
+    let mut mem = MemoryOperand {
+        base: None,
+        index: None,
+        scale: 1,
+        displacement: 0,
+    };
+    if let Some(caps) = ATT.captures(operand) {
+        mem.base = caps.get(2).map(|m| m.as_str().to_owned());
+        mem.index = caps.get(3).map(|m| m.as_str().to_owned());
+        if let Some(scale) = caps.get(4) {
+            mem.scale = scale.as_str().parse().ok()?;
+        }
+        if let Some(displacement) = caps.get(1) {
+            mem.displacement = parse_signed(displacement.as_str())?;
+        }
+    } else if let Some(caps) = BRACKETS.captures(operand) {
+        let add_register = |mem: &mut MemoryOperand, reg: &str| {
+            if mem.base.is_none() {
+                mem.base = Some(reg.to_owned());
+            } else if mem.index.is_none() {
+                mem.index = Some(reg.to_owned());
+            } else {
+                return None;
+            }
+            Some(())
+        };
+        let inner = &caps[1];
+        if inner.contains(',') {
+            // AArch64: `[base, #imm]` or `[base, index, lsl #n]`
+            for term in inner.split(',').map(str::trim) {
+                if let Some(shift) = term.strip_prefix("lsl #") {
+                    mem.scale = 1u64.checked_shl(shift.parse().ok()?)?;
+                } else if let Some(imm) = term.strip_prefix('#') {
+                    mem.displacement = parse_signed(imm)?;
+                } else {
+                    add_register(&mut mem, register_operand(term)?)?;
+                }
+            }
+        } else {
+            // Intel: `[base + scale*index + displacement]`
+            for term in inner.replace(" - ", " + -").split('+').map(str::trim) {
+                if let Some((left, right)) = term.split_once('*') {
+                    let (scale, index) = if left.trim().starts_with(|c: char| c.is_ascii_digit()) {
+                        (left, right)
+                    } else {
+                        (right, left)
+                    };
+                    mem.scale = scale.trim().parse().ok()?;
+                    mem.index = Some(register_operand(index)?.to_owned());
+                } else if let Some(reg) = register_operand(term) {
+                    add_register(&mut mem, reg)?;
+                } else {
+                    mem.displacement = mem.displacement.wrapping_add(parse_signed(term)?);
+                }
+            }
+        }
+    } else {
+        return None;
+    }
+    Some(mem)
+}
+
+// Parse an immediate operand, e.g. `$0x10` (AT&T), `#-8` (AArch64) or `16` (Intel).
+// In AT&T syntax, bare numbers denote absolute memory addresses, rather than immediates.
+pub fn immediate_value(operand: &str, att_syntax: bool) -> Option<u64> {
+    let operand = operand.trim();
+    let number = match operand.strip_prefix('$').or_else(|| operand.strip_prefix('#')) {
+        Some(number) => number,
+        None if !att_syntax => operand,
+        None => return None,
+    };
+    parse_signed(number).map(|n| n as u64)
+}
+
+// Split an instruction's operand list on top-level commas, e.g. `[x9, #0x10], x8` -> [`[x9, #0x10]`, `x8`].
+pub fn split_operands(operands: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in operands.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                result.push(operands[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    let last = operands[start..].trim();
+    if !last.is_empty() {
+        result.push(last);
+    }
+    result
+}
+
+// Map register name to the name of the architectural register it is a part of, e.g. `eax` -> `rax`, `w8` -> `x8`.
+pub fn canonical_register(name: &str) -> String {
+    let name = name.trim_start_matches('%').to_ascii_lowercase();
+    let digits = |s: &str| s.chars().take_while(|c| c.is_ascii_digit()).collect::<String>();
+    // x86-64 extended registers: r8, r8d, r8w, r8b
+    if let Some(rest) = name.strip_prefix('r') {
+        if rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return format!("r{}", digits(rest));
+        }
+    }
+    // AArch64 general purpose registers: x0, w0
+    if let Some(rest) = name.strip_prefix('x').or_else(|| name.strip_prefix('w')) {
+        if !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()) {
+            return format!("x{}", rest);
+        }
+    }
+    match name.as_str() {
+        "fp" => return "x29".into(),
+        "lr" => return "x30".into(),
+        _ => (),
+    }
+    // x86 legacy registers
+    for family in ["ax", "bx", "cx", "dx"] {
+        let low = &family[..1];
+        if name == format!("r{family}")
+            || name == format!("e{family}")
+            || name == family
+            || name == format!("{low}l")
+            || name == format!("{low}h")
+        {
+            return format!("r{family}");
+        }
+    }
+    for family in ["si", "di", "bp", "sp"] {
+        if name == format!("r{family}")
+            || name == format!("e{family}")
+            || name == family
+            || name == format!("{family}l")
+        {
+            return format!("r{family}");
+        }
+    }
+    name
+}
+
+// Width of a general purpose register in bytes, if it is a full-width or a 32-bit one.
+pub fn register_width(name: &str) -> Option<usize> {
+    let name = name.trim_start_matches('%').to_ascii_lowercase();
+    let canonical = canonical_register(&name);
+    if name == canonical && (name.starts_with('r') || name.starts_with('x')) {
+        Some(8)
+    } else if name.starts_with('e') || name.starts_with('w') || (name.starts_with('r') && name.ends_with('d')) {
+        Some(4)
+    } else {
+        None
+    }
+}
+
+// Whether the register is preserved across calls (SysV x86-64 and AArch64 calling conventions).
+pub fn is_callee_saved(canonical: &str) -> bool {
+    match canonical {
+        "rbx" | "rbp" | "rsp" | "r12" | "r13" | "r14" | "r15" => true,
+        _ => match canonical.strip_prefix('x').and_then(|n| n.parse::<u32>().ok()) {
+            Some(n) => (19..=29).contains(&n),
+            None => false,
+        },
+    }
+}
+
+// Decode an unsigned integer stored in the given byte order.
+pub fn decode_uint(bytes: &[u8], byte_order: ByteOrder) -> u64 {
+    let fold = |value: u64, byte: &u8| (value << 8) | *byte as u64;
+    match byte_order {
+        ByteOrder::Big => bytes.iter().fold(0, fold),
+        _ => bytes.iter().rev().fold(0, fold),
+    }
+}
+
+// Read a pointer-sized value from the target's memory.
+pub fn read_pointer(target: &SBTarget, address: Address) -> Option<u64> {
+    let mut buffer = [0u8; 8];
+    let size = target.address_byte_size().min(buffer.len());
+    let count = target.process().read_memory(address, &mut buffer[..size]).ok()?;
+    if count < size {
+        return None;
+    }
+    Some(decode_uint(&buffer[..size], target.byte_order()))
+}
+
+// Parse a signed decimal or hexadecimal number, e.g. `-0x10` or `16`.
+fn parse_signed(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let value = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()? as i64,
+        None => s.parse::<u64>().ok()? as i64,
+    };
+    Some(if negative { value.wrapping_neg() } else { value })
+}
+
+// Maximum length of string literal previews.
+const MAX_STRING_PREVIEW: usize = 48;
+
+// If the bytes look like a NUL-terminated string of printable characters, return a quoted preview.
+fn c_string_preview(bytes: &[u8]) -> Option<String> {
+    let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    // Require a few characters, so that random data isn't mistaken for a string.
+    if len < 2 {
+        return None;
+    }
+    let text = str::from_utf8(&bytes[..len]).ok()?;
+    if !text.chars().all(|c| !c.is_control() || c == '\n' || c == '\t' || c == '\r') {
+        return None;
+    }
+    if len == bytes.len() && len > MAX_STRING_PREVIEW {
+        let text: String = text.chars().take(MAX_STRING_PREVIEW).collect();
+        Some(format!("{:?}...", text))
+    } else if len == bytes.len() {
+        // No terminator within the memory we've read.
+        None
+    } else {
+        Some(format!("{:?}", text))
+    }
+}
+
 #[test]
 fn test_range_lookup() {
     use crate::TEST_DEBUGGER;
     use std::rc::Rc;
     let target = TEST_DEBUGGER.dummy_target();
-    let ranges = DisassembledRanges::new(&target);
+    let ranges = DisassembledRanges::new(&target, DisassemblyOptions::default());
 
     assert!(ranges.find_by_address(1234).is_none());
 
     let add = |start, end| {
         let start = SBAddress::from_load_address(start, &target);
         let end = SBAddress::from_load_address(end, &target);
-        ranges.add(start.clone(), end, target.get_instructions(&start, &[], None))
+        let instructions = target.get_instructions(&start, &[], None);
+        ranges.add(start.clone(), end, instructions, &|_| None)
     };
 
     let dasm1 = add(1000, 2000);
@@ -269,3 +898,164 @@ fn test_adapter_data() {
     let addresses2 = DisassembledRange::lines_from_adapter_data(&adapter_data);
     assert_eq!(addresses, &addresses2[3..]);
 }
+
+#[test]
+fn test_layout_lines() {
+    let addresses = &[10, 20, 23, 25];
+    let annotations = vec![
+        vec!["a".to_owned(), "b".to_owned()],
+        vec![],
+        vec!["c".to_owned()],
+        vec![],
+    ];
+    let (line_addresses, instruction_lines) = layout_lines(addresses, &annotations);
+    assert_eq!(line_addresses, &[10, 10, 10, 20, 23, 23, 25]);
+    assert_eq!(instruction_lines, &[5, 6, 8, 9]);
+
+    // Adapter data must round-trip line addresses.
+    let adapter_data = AdapterData {
+        start: 10,
+        end: 30,
+        line_offsets: line_addresses.windows(2).map(|w| (w[1] - w[0]) as u32).collect(),
+    };
+    let addresses2 = DisassembledRange::lines_from_adapter_data(&adapter_data);
+    assert_eq!(&line_addresses[..], &addresses2[3..]);
+    assert_eq!(addresses2[instruction_lines[2] as usize], 23);
+}
+
+#[test]
+fn test_operand_parsing() {
+    assert_eq!(immediate_operand("0x401000"), Some(0x401000));
+    assert_eq!(immediate_operand("$0x10"), Some(0x10));
+    assert_eq!(immediate_operand("*0x1234(%rip)"), None);
+    assert_eq!(immediate_operand("rax"), None);
+
+    assert_eq!(pc_relative_displacement("0x2fe2(%rip), %rdi"), Some(0x2fe2));
+    assert_eq!(pc_relative_displacement("-0x10(%rip), %eax"), Some(-0x10));
+    assert_eq!(pc_relative_displacement("rdi, [rip + 0x2fe2]"), Some(0x2fe2));
+    assert_eq!(pc_relative_displacement("eax, dword ptr [rip - 0x10]"), Some(-0x10));
+    assert_eq!(pc_relative_displacement("0x10(%rbp), %eax"), None);
+}
+
+#[test]
+fn test_indirect_operand_parsing() {
+    let mem = |base: Option<&str>, index: Option<&str>, scale, displacement| MemoryOperand {
+        base: base.map(Into::into),
+        index: index.map(Into::into),
+        scale,
+        displacement,
+    };
+    assert_eq!(indirect_operand("*%rax"), Some(IndirectOperand::Register("rax".into())));
+    assert_eq!(indirect_operand("rax"), Some(IndirectOperand::Register("rax".into())));
+    assert_eq!(indirect_operand("x8"), Some(IndirectOperand::Register("x8".into())));
+    assert_eq!(
+        indirect_operand("*0x10(%rax)"),
+        Some(IndirectOperand::Memory(mem(Some("rax"), None, 1, 0x10)))
+    );
+    assert_eq!(
+        indirect_operand("qword ptr [rax + 0x10]"),
+        Some(IndirectOperand::Memory(mem(Some("rax"), None, 1, 0x10)))
+    );
+    assert_eq!(indirect_operand("0x401000"), None);
+
+    assert_eq!(
+        memory_operand("-0x8(%rbp,%rcx,8)"),
+        Some(mem(Some("rbp"), Some("rcx"), 8, -8))
+    );
+    assert_eq!(memory_operand("(,%rcx,8)"), Some(mem(None, Some("rcx"), 8, 0)));
+    assert_eq!(
+        memory_operand("qword ptr [rbp + 8*rcx - 0x8]"),
+        Some(mem(Some("rbp"), Some("rcx"), 8, -8))
+    );
+    assert_eq!(memory_operand("[x29, #-0x8]"), Some(mem(Some("x29"), None, 1, -8)));
+    assert_eq!(
+        memory_operand("[x8, x9, lsl #3]"),
+        Some(mem(Some("x8"), Some("x9"), 8, 0))
+    );
+    assert_eq!(memory_operand("%fs:0x28"), None);
+
+    assert_eq!(immediate_value("$0x10", true), Some(0x10));
+    assert_eq!(immediate_value("0x10", true), None);
+    assert_eq!(immediate_value("16", false), Some(16));
+    assert_eq!(immediate_value("#-0x8", false), Some(-8i64 as u64));
+
+    assert_eq!(split_operands("-0x8(%rbp,%rcx,8), %rax"), ["-0x8(%rbp,%rcx,8)", "%rax"]);
+    assert_eq!(split_operands("x8, [x9, #0x10]"), ["x8", "[x9, #0x10]"]);
+    assert!(split_operands("").is_empty());
+}
+
+#[test]
+fn test_registers() {
+    assert_eq!(canonical_register("%eax"), "rax");
+    assert_eq!(canonical_register("al"), "rax");
+    assert_eq!(canonical_register("sil"), "rsi");
+    assert_eq!(canonical_register("r8d"), "r8");
+    assert_eq!(canonical_register("w19"), "x19");
+    assert_eq!(canonical_register("fp"), "x29");
+    assert_eq!(canonical_register("rip"), "rip");
+
+    assert_eq!(register_width("rax"), Some(8));
+    assert_eq!(register_width("r10"), Some(8));
+    assert_eq!(register_width("eax"), Some(4));
+    assert_eq!(register_width("r10d"), Some(4));
+    assert_eq!(register_width("x8"), Some(8));
+    assert_eq!(register_width("w8"), Some(4));
+    assert_eq!(register_width("ax"), None);
+    assert_eq!(register_width("r10w"), None);
+
+    assert!(is_callee_saved("rbx"));
+    assert!(is_callee_saved("x19"));
+    assert!(!is_callee_saved("rax"));
+    assert!(!is_callee_saved("x8"));
+}
+
+#[test]
+fn test_decode_uint() {
+    assert_eq!(decode_uint(&[0x78, 0x56, 0x34, 0x12], ByteOrder::Little), 0x12345678);
+    assert_eq!(decode_uint(&[0x12, 0x34, 0x56, 0x78], ByteOrder::Big), 0x12345678);
+    assert_eq!(
+        decode_uint(&[1, 0, 0, 0, 0, 0, 0, 0x80], ByteOrder::Little),
+        0x8000_0000_0000_0001
+    );
+    assert_eq!(decode_uint(&[], ByteOrder::Little), 0);
+}
+
+#[test]
+fn test_c_string_preview() {
+    assert_eq!(c_string_preview(b"Hello\n\0garbage"), Some(r#""Hello\n""#.to_owned()));
+    assert_eq!(c_string_preview(b"A\0"), None);
+    assert_eq!(c_string_preview(b"\x01\x02\x03\0"), None);
+    assert_eq!(c_string_preview(b"no terminator"), None);
+    let long = [b'x'; MAX_STRING_PREVIEW + 1];
+    assert_eq!(
+        c_string_preview(&long),
+        Some(format!("{:?}...", "x".repeat(MAX_STRING_PREVIEW)))
+    );
+}
+
+#[test]
+fn test_compose_comment() {
+    assert_eq!(compose_comment("", Some("main+0x10")), "main+0x10");
+    assert_eq!(compose_comment("foo", None), "foo");
+    assert_eq!(compose_comment("foo", Some("main")), "foo, main");
+    assert_eq!(
+        compose_comment("symbol stub for: puts", Some("puts")),
+        "symbol stub for: puts"
+    );
+}
+
+#[test]
+fn test_format_bytes() {
+    let options = DisassemblyOptions {
+        max_bytes: 2,
+        ..Default::default()
+    };
+    assert_eq!(options.format_bytes(&[0x90]), "90      ");
+    assert_eq!(options.format_bytes(&[0x48, 0x89, 0xE5]), "48 89 > ");
+    assert_eq!(options.mnemonic("mov"), "mov");
+    let options = DisassemblyOptions {
+        uppercase_mnemonics: true,
+        ..Default::default()
+    };
+    assert_eq!(options.mnemonic("mov"), "MOV");
+}
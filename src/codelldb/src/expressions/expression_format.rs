@@ -1,7 +1,9 @@
 use nom::{
-    character::complete::{anychar, char},
-    combinator::{opt, verify},
-    sequence::{delimited, pair},
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{alphanumeric1, char},
+    combinator::opt,
+    sequence::{delimited, pair, preceded},
     Parser,
 };
 
@@ -10,64 +12,118 @@ use super::prelude::*;
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct FormatSpec {
     pub format: Option<lldb::Format>,
+    // Number of array elements to display.
     pub array: Option<u32>,
+    // Index of the first array element, for slices like `[2..5]`.
+    pub array_offset: u32,
+    // Bypass synthetic children providers and visualizers (`!`).
+    pub raw: bool,
+    // Don't show the pointer address (`na`).
+    pub no_address: bool,
+    // Decode the value as an HRESULT-style error code (`hr`).
+    pub hresult: bool,
 }
 
 impl FormatSpec {
     pub const NONE: FormatSpec = FormatSpec {
         format: None,
         array: None,
+        array_offset: 0,
+        raw: false,
+        no_address: false,
+        hresult: false,
     };
 }
 
 pub fn get_expression_format<'a>(expr: &'a str) -> Result<(&'a str, FormatSpec), Error> {
     if let Some(pos) = expr.rfind(',') {
-        let spec = &expr[pos + 1..];
-
-        fn convert_format(c: char) -> Option<lldb::Format> {
-            match c {
-                'c' => Some(lldb::Format::Char),
-                'h' => Some(lldb::Format::Hex),
-                'x' => Some(lldb::Format::Hex),
-                'o' => Some(lldb::Format::Octal),
-                'd' => Some(lldb::Format::Decimal),
-                'b' => Some(lldb::Format::Binary),
-                'f' => Some(lldb::Format::Float),
-                'p' => Some(lldb::Format::Pointer),
-                'u' => Some(lldb::Format::Unsigned),
-                's' => Some(lldb::Format::CString),
-                'y' => Some(lldb::Format::Bytes),
-                'Y' => Some(lldb::Format::BytesWithASCII),
-                _ => None,
-            }
+        match parse_format_spec(&expr[pos + 1..])? {
+            Some(format_spec) => Ok((&expr[..pos], format_spec)),
+            // Not a format specifier, return expression as-is
+            None => Ok((expr, FormatSpec::NONE)),
+        }
+    } else {
+        // No format specifier, return expression as-is
+        Ok((expr, FormatSpec::NONE))
+    }
+}
+
+// Parses the part of an expression following the comma.  Returns None if it is not a format specifier.
+pub fn parse_format_spec(spec: &str) -> Result<Option<FormatSpec>, Error> {
+    fn convert_format(c: char) -> Option<lldb::Format> {
+        match c {
+            'c' => Some(lldb::Format::Char),
+            'h' => Some(lldb::Format::Hex),
+            'x' => Some(lldb::Format::Hex),
+            'H' => Some(lldb::Format::HexUppercase),
+            'X' => Some(lldb::Format::HexUppercase),
+            'o' => Some(lldb::Format::Octal),
+            'd' => Some(lldb::Format::Decimal),
+            'i' => Some(lldb::Format::Decimal),
+            'b' => Some(lldb::Format::Binary),
+            'f' => Some(lldb::Format::Float),
+            'e' => Some(lldb::Format::Float),
+            'g' => Some(lldb::Format::Float),
+            'p' => Some(lldb::Format::Pointer),
+            'u' => Some(lldb::Format::Unsigned),
+            's' => Some(lldb::Format::CString),
+            'y' => Some(lldb::Format::Bytes),
+            'Y' => Some(lldb::Format::BytesWithASCII),
+            _ => None,
         }
+    }
 
-        let mut parser = pair(
-            opt(verify(anychar, |c| c.is_alphabetic())),
-            opt(delimited(char('['), unsigned, char(']'))),
-        );
-
-        match parser.parse(spec) {
-            // Fully parsed
-            Ok(("", (format_ch, array))) => {
-                let format = match format_ch {
-                    Some(c) => match convert_format(c) {
-                        Some(format) => Some(format),
-                        None => bail!(format!("Invlaid format specifier: {}", c)),
-                    },
-                    _ => None,
-                };
+    // Array length (`[10]`) or slice (`[2..5]`)
+    fn array(input: Span) -> IResult<Span, (u32, Option<u32>)> {
+        delimited(char('['), pair(unsigned, opt(preceded(tag(".."), unsigned))), char(']')).parse(input)
+    }
+
+    let mut parser = (opt(array), opt(alt((alphanumeric1, tag("!")))), opt(array));
 
-                Ok((&expr[..pos], FormatSpec { format, array }))
+    match parser.parse(spec) {
+        // Fully parsed
+        Ok(("", (array1, word, array2))) => {
+            let mut format_spec = FormatSpec::NONE;
+            match word {
+                Some("!") => format_spec.raw = true,
+                Some("na") => format_spec.no_address = true,
+                Some("hr") => format_spec.hresult = true,
+                Some("en") => format_spec.format = Some(lldb::Format::Enum),
+                Some("bb") => format_spec.format = Some(lldb::Format::Binary),
+                Some("s8" | "sb" | "s8b") => format_spec.format = Some(lldb::Format::CString),
+                Some("su" | "sub") => format_spec.format = Some(lldb::Format::Unicode16),
+                Some("s32" | "s32b") => format_spec.format = Some(lldb::Format::Unicode32),
+                // Specifiers that only affect presentation in Visual Studio.
+                Some("nd" | "nr" | "nvo" | "wc" | "wm") => {}
+                Some(word) if word.len() == 1 => {
+                    let c = word.chars().next().unwrap();
+                    match convert_format(c) {
+                        Some(format) => format_spec.format = Some(format),
+                        None => bail!(format!("Invlaid format specifier: {}", c)),
+                    }
+                }
+                // Not a format specifier
+                Some(_) => return Ok(None),
+                None => {}
             }
-            // Partially parsed
-            Ok(_) => Ok((expr, FormatSpec::NONE)),
-            // Error
-            Err(err) => Err(str_error(err.to_string())),
+            match (array1, array2) {
+                (Some(_), Some(_)) => return Ok(None),
+                (Some((len, None)), None) | (None, Some((len, None))) => format_spec.array = Some(len),
+                (Some((start, Some(end))), None) | (None, Some((start, Some(end)))) => {
+                    if end < start {
+                        bail!(format!("Invalid slice: [{}..{}]", start, end));
+                    }
+                    format_spec.array = Some(end - start);
+                    format_spec.array_offset = start;
+                }
+                (None, None) => {}
+            }
+            Ok(Some(format_spec))
         }
-    } else {
-        // No format specifier, return expression as-is
-        Ok((expr, FormatSpec::NONE))
+        // Partially parsed
+        Ok(_) => Ok(None),
+        // Error
+        Err(err) => Err(str_error(err.to_string())),
     }
 }
 
@@ -79,15 +135,33 @@ fn test_expression_format() {
     assert_matches!(get_expression_format("foo"), Ok(("foo", FormatSpec::NONE)));
     assert_matches!(get_expression_format("foo,bar"), Ok(("foo,bar", FormatSpec::NONE)));
 
-    assert_matches!(get_expression_format("foo,h"), Ok(("foo", FormatSpec { format: Some(lldb::Format::Hex), array: None})));
-    assert_matches!(get_expression_format("foo,x"), Ok(("foo", FormatSpec { format: Some(lldb::Format::Hex), array: None})));
-    assert_matches!(get_expression_format("foo,y"), Ok(("foo", FormatSpec { format: Some(lldb::Format::Bytes), array: None})));
-    assert_matches!(get_expression_format("foo,Y"), Ok(("foo", FormatSpec { format: Some(lldb::Format::BytesWithASCII), array: None})));
+    assert_matches!(get_expression_format("foo,h"), Ok(("foo", FormatSpec { format: Some(lldb::Format::Hex), array: None, .. })));
+    assert_matches!(get_expression_format("foo,x"), Ok(("foo", FormatSpec { format: Some(lldb::Format::Hex), array: None, .. })));
+    assert_matches!(get_expression_format("foo,y"), Ok(("foo", FormatSpec { format: Some(lldb::Format::Bytes), array: None, .. })));
+    assert_matches!(get_expression_format("foo,Y"), Ok(("foo", FormatSpec { format: Some(lldb::Format::BytesWithASCII), array: None, .. })));
 
-    assert_matches!(get_expression_format("foo,[42]"), Ok(("foo", FormatSpec{ format: None, array: Some(42) })));
-    assert_matches!(get_expression_format("foo,x[42]"), Ok(("foo", FormatSpec{ format:Some(lldb::Format::Hex), array: Some(42), })));
+    assert_matches!(get_expression_format("foo,[42]"), Ok(("foo", FormatSpec{ format: None, array: Some(42), .. })));
+    assert_matches!(get_expression_format("foo,x[42]"), Ok(("foo", FormatSpec{ format:Some(lldb::Format::Hex), array: Some(42), .. })));
     assert_matches!(get_expression_format("foo, x"), Ok(("foo, x", FormatSpec::NONE)));
     assert_matches!(get_expression_format("foo,x [42]"), Ok(("foo,x [42]", FormatSpec::NONE)));
 
     assert_matches!(get_expression_format("foo,Z"), Err(_));
+
+    assert_matches!(get_expression_format("foo,s8"), Ok(("foo", FormatSpec { format: Some(lldb::Format::CString), array: None, .. })));
+    assert_matches!(get_expression_format("foo,su"), Ok(("foo", FormatSpec { format: Some(lldb::Format::Unicode16), array: None, .. })));
+    assert_matches!(get_expression_format("foo,s32"), Ok(("foo", FormatSpec { format: Some(lldb::Format::Unicode32), array: None, .. })));
+    assert_matches!(get_expression_format("foo,en"), Ok(("foo", FormatSpec { format: Some(lldb::Format::Enum), array: None, .. })));
+    assert_matches!(get_expression_format("foo,[2..5]"), Ok(("foo", FormatSpec { format: None, array: Some(3), array_offset: 2, .. })));
+    assert_matches!(get_expression_format("foo,[10]na"), Ok(("foo", FormatSpec { array: Some(10), no_address: true, .. })));
+    assert_matches!(get_expression_format("foo,!"), Ok(("foo", FormatSpec { format: None, raw: true, .. })));
+    assert_matches!(get_expression_format("foo,hr"), Ok(("foo", FormatSpec { format: None, hresult: true, .. })));
+    assert_matches!(get_expression_format("foo,[5..2]"), Err(_));
+    assert_matches!(get_expression_format("foo,[1][2]"), Ok(("foo,[1][2]", FormatSpec::NONE)));
+
+    assert_matches!(get_expression_format("foo,X"), Ok(("foo", FormatSpec { format: Some(lldb::Format::HexUppercase), array: None, .. })));
+    assert_matches!(get_expression_format("foo,sub"), Ok(("foo", FormatSpec { format: Some(lldb::Format::Unicode16), array: None, .. })));
+    assert_matches!(get_expression_format("foo,[4]nd"), Ok(("foo", FormatSpec { format: None, array: Some(4), .. })));
+
+    assert_matches!(parse_format_spec("[size]na"), Ok(None));
+    assert_matches!(parse_format_spec("x"), Ok(Some(FormatSpec { format: Some(lldb::Format::Hex), .. })));
 }
@@ -56,16 +56,19 @@ mod hit_condition;
 mod literals;
 mod preprocess;
 mod qualified_ident;
+mod simple_eval;
 mod simple_expressions;
 
-pub use expression_format::{get_expression_format, FormatSpec};
+pub use expression_format::{get_expression_format, parse_format_spec, FormatSpec};
 pub use hit_condition::{parse_hit_condition, HitCondition};
-pub use preprocess::{preprocess_python_expr, preprocess_simple_expr};
+pub use preprocess::{parse_simple_expr, preprocess_python_expr};
+pub use simple_eval::evaluate_simple_expr;
+pub use simple_expressions::SimpleExpr;
 
 #[derive(Debug)]
 pub enum PreparedExpression {
     Native(String),
-    Simple(String),
+    Simple(SimpleExpr),
     Python(String),
 }
 
@@ -74,7 +77,7 @@ pub fn prepare(expression: &str, default_type: Expressions) -> Result<PreparedEx
     let (expr, ty) = get_expression_type(expression, default_type);
     match ty {
         Expressions::Native => Ok(PreparedExpression::Native(expr.to_owned())),
-        Expressions::Simple => Ok(PreparedExpression::Simple(preprocess_simple_expr(expr)?)),
+        Expressions::Simple => Ok(PreparedExpression::Simple(parse_simple_expr(expr)?)),
         Expressions::Python => Ok(PreparedExpression::Python(preprocess_python_expr(expr)?)),
     }
 }
@@ -89,7 +92,7 @@ pub fn prepare_with_format(
     let (expr, format_spec) = get_expression_format(expr)?;
     let pp_expr = match ty {
         Expressions::Native => PreparedExpression::Native(expr.to_owned()),
-        Expressions::Simple => PreparedExpression::Simple(preprocess_simple_expr(expr)?),
+        Expressions::Simple => PreparedExpression::Simple(parse_simple_expr(expr)?),
         Expressions::Python => PreparedExpression::Python(preprocess_python_expr(expr)?),
     };
     Ok((pp_expr, format_spec))
@@ -106,3 +109,98 @@ fn get_expression_type<'a>(expr: &'a str, default_type: Expressions) -> (&'a str
         (expr, default_type)
     }
 }
+
+impl PreparedExpression {
+    // Checks whether the expression refers to the given pseudo-variable, e.g. `$exception`.
+    pub fn references_pseudo_var(&self, name: &str) -> bool {
+        match self {
+            PreparedExpression::Native(expr) => find_native_var(expr, name).is_some(),
+            PreparedExpression::Simple(expr) => expr.references_pseudo_var(name),
+            PreparedExpression::Python(expr) => expr.contains(&format!("__eval('{}')", name)),
+        }
+    }
+
+    // Replaces references to a pseudo-variable with the provided native expression.
+    pub fn replace_pseudo_var(&self, name: &str, native_expr: &str) -> PreparedExpression {
+        match self {
+            PreparedExpression::Native(expr) => {
+                let mut result = String::new();
+                let mut rest = expr.as_str();
+                while let Some(pos) = find_native_var(rest, name) {
+                    result.push_str(&rest[..pos]);
+                    result.push_str(native_expr);
+                    rest = &rest[pos + name.len() + 1..];
+                }
+                result.push_str(rest);
+                PreparedExpression::Native(result)
+            }
+            PreparedExpression::Simple(expr) => {
+                let mut expr = expr.clone();
+                expr.replace_pseudo_var(name, native_expr);
+                PreparedExpression::Simple(expr)
+            }
+            PreparedExpression::Python(expr) => PreparedExpression::Python(
+                expr.replace(&format!("__eval('{}')", name), &format!("__eval('{}')", native_expr)),
+            ),
+        }
+    }
+}
+
+// Finds the first occurrence of `$name` in a native expression, not counting ones that are a part of a longer identifier.
+fn find_native_var(expr: &str, name: &str) -> Option<usize> {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    let pattern = format!("${}", name);
+    let mut start = 0;
+    while let Some(pos) = expr[start..].find(&pattern) {
+        let pos = start + pos;
+        let end = pos + pattern.len();
+        let before_ok = !expr[..pos].ends_with(is_ident_char);
+        let after_ok = !expr[end..].starts_with(is_ident_char);
+        if before_ok && after_ok {
+            return Some(pos);
+        }
+        start = end;
+    }
+    None
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_pseudo_vars() {
+    fn text(expr: PreparedExpression) -> String {
+        match expr {
+            PreparedExpression::Native(s) | PreparedExpression::Python(s) => s,
+            PreparedExpression::Simple(e) => e.to_python(),
+        }
+    }
+
+    let expr = prepare("$exception->code == 5 && $exceptions", Expressions::Native).unwrap();
+    assert!(expr.references_pseudo_var("exception"));
+    assert_eq!(
+        text(expr.replace_pseudo_var("exception", "(*(Err*)0x1000)")),
+        "(*(Err*)0x1000)->code == 5 && $exceptions"
+    );
+
+    let expr = prepare("$exception.code == 5", Expressions::Simple).unwrap();
+    assert!(expr.references_pseudo_var("exception"));
+    assert_eq!(
+        text(expr.replace_pseudo_var("exception", "(*(Err*)0x1000)")),
+        "(__eval('(*(Err*)0x1000)').__getattr__('code') == 5)"
+    );
+
+    let expr = prepare("$exception_count > 1", Expressions::Native).unwrap();
+    assert!(!expr.references_pseudo_var("exception"));
+
+    // A local variable named `exception` is not the pseudo-variable.
+    let expr = prepare("exception.code == $exception.code", Expressions::Simple).unwrap();
+    assert!(expr.references_pseudo_var("exception"));
+    assert_eq!(
+        text(expr.replace_pseudo_var("exception", "(*(Err*)0x1000)")),
+        "(__eval('exception').__getattr__('code') == __eval('(*(Err*)0x1000)').__getattr__('code'))"
+    );
+    let expr = prepare("exception.code == 5", Expressions::Simple).unwrap();
+    assert!(!expr.references_pseudo_var("exception"));
+    let expr = prepare("exception != 0", Expressions::Python).unwrap();
+    assert!(!expr.references_pseudo_var("exception"));
+}
@@ -11,6 +11,7 @@ use std::{borrow::Cow, fmt::Write};
 
 use super::prelude::*;
 use super::qualified_ident::qualified_ident;
+use super::simple_expressions::SimpleExpr;
 
 // Recognize Python strings
 pub fn python_string(input: Span) -> IResult<Span, Span> {
@@ -44,8 +45,8 @@ pub fn native_expr(input: Span) -> IResult<Span, Span> {
     .parse(input)
 }
 
-// Translates a Simple Expression into a Python expression.
-pub fn preprocess_simple_expr(expr: &str) -> Result<String, Error> {
+// Parses a Simple Expression.
+pub fn parse_simple_expr(expr: &str) -> Result<SimpleExpr, Error> {
     match super::simple_expressions::expression(expr).finish() {
         Ok(("", result)) => Ok(result),
         Ok((input, _)) => Err(syntax_error_message(expr, input).into()),
@@ -0,0 +1,534 @@
+use crate::prelude::*;
+
+use super::simple_expressions::{BinaryOp, SimpleExpr, UnaryOp};
+
+use lldb::*;
+
+// Evaluates a Simple Expression directly on top of SBValue, without involving Python.
+// The semantics follow those of the Python-based evaluator (see `codelldb.value.Value`).
+pub fn evaluate_simple_expr(expr: &SimpleExpr, frame: Option<&SBFrame>, target: &SBTarget) -> Result<SBValue, Error> {
+    if expr.has_rust_syntax() && frame.map(|f| f.guess_language()) != Some(LanguageType::Rust) {
+        bail!("Rust syntax is only supported in Rust frames.");
+    }
+    let evaluator = Evaluator { frame, target };
+    let value = evaluator.eval(expr)?;
+    evaluator.into_sbvalue(value)
+}
+
+enum Value {
+    Object(SBValue),
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+struct Evaluator<'a> {
+    frame: Option<&'a SBFrame>,
+    target: &'a SBTarget,
+}
+
+impl<'a> Evaluator<'a> {
+    fn eval(&self, expr: &SimpleExpr) -> Result<Value, Error> {
+        Ok(match expr {
+            SimpleExpr::Int(lexeme) => Value::Int(parse_int::parse::<i128>(&lexeme.to_ascii_lowercase())?),
+            SimpleExpr::Float(lexeme) => Value::Float(lexeme.parse::<f64>()?),
+            SimpleExpr::Bool(b) => Value::Bool(*b),
+            SimpleExpr::Str(lexeme) => Value::Str(unquote(lexeme)),
+            SimpleExpr::Var(name) | SimpleExpr::DollarVar(name) => Value::Object(self.lookup(name)?),
+            SimpleExpr::Paren(e) => self.eval(e)?,
+            SimpleExpr::Cast(ty, e) | SimpleExpr::As(e, ty) => {
+                let ty = self.resolve_type(ty)?;
+                let value = match self.eval(e)? {
+                    Value::Object(value) => value,
+                    value => self.into_sbvalue(value)?,
+                };
+                Value::Object(check(value.cast(&ty))?)
+            }
+            SimpleExpr::Unary(op, e) => self.eval_unary(*op, e)?,
+            SimpleExpr::Binary(lhs, op, rhs) => self.eval_binary(lhs, *op, rhs)?,
+            SimpleExpr::Member(e, name) => {
+                let value = self.eval_object(e)?;
+                // Rust tuple fields are named `__0`, `__1`, etc in the debug info.
+                let child = match value.child_member_with_name(name) {
+                    None if name.starts_with(|c: char| c.is_ascii_digit()) => {
+                        value.child_member_with_name(&format!("__{}", name))
+                    }
+                    child => child,
+                };
+                match child {
+                    Some(child) => Value::Object(child),
+                    None => bail!(format!("Attribute '{}' is not defined", name)),
+                }
+            }
+            SimpleExpr::Index(e, index) => {
+                let value = self.eval_object(e)?;
+                let index = match self.eval_scalar(index)? {
+                    Value::Int(index) => index,
+                    _ => bail!("Index must be an integer"),
+                };
+                let child = value.value_for_expression_path(&format!("[{}]", index));
+                match child.check() {
+                    Some(child) => Value::Object(child),
+                    None => bail!(format!("Index '{}' is out of range", index)),
+                }
+            }
+            SimpleExpr::Slice {
+                expr,
+                start,
+                end,
+                inclusive,
+            } => {
+                let value = self.eval_object(expr)?;
+                let start = match start {
+                    Some(start) => Some(self.eval_int(start)?),
+                    None => None,
+                };
+                let end = match end {
+                    Some(end) => Some(self.eval_int(end)? + *inclusive as i128),
+                    None => None,
+                };
+                Value::Object(self.slice(&value, start, end)?)
+            }
+            SimpleExpr::Call(e, method) => {
+                let value = self.eval_object(e)?;
+                match method.as_str() {
+                    "len" => Value::Int(collection_len(&value) as i128),
+                    "is_empty" => Value::Bool(collection_len(&value) == 0),
+                    "is_some" => Value::Bool(is_variant(&value, "Some")),
+                    "is_none" => Value::Bool(is_variant(&value, "None")),
+                    "is_ok" => Value::Bool(is_variant(&value, "Ok")),
+                    "is_err" => Value::Bool(is_variant(&value, "Err")),
+                    _ => bail!(format!("Method '{}' is not supported", method)),
+                }
+            }
+            SimpleExpr::Matches(e, variants) => {
+                let value = self.eval_object(e)?;
+                Value::Bool(variants.iter().any(|variant| is_variant(&value, variant)))
+            }
+        })
+    }
+
+    fn eval_int(&self, expr: &SimpleExpr) -> Result<i128, Error> {
+        match self.eval_scalar(expr)? {
+            Value::Int(i) => Ok(i),
+            _ => bail!("Expected an integer"),
+        }
+    }
+
+    // Creates an array value covering elements in the start..end range, provided they are laid out contiguously.
+    fn slice(&self, value: &SBValue, start: Option<i128>, end: Option<i128>) -> Result<SBValue, Error> {
+        let len = collection_len(value) as i128;
+        let (start, end) = (start.unwrap_or(0), end.unwrap_or(len));
+        if start < 0 || start > end || end > len {
+            bail!(format!("Range {}..{} is out of bounds for length {}", start, end, len));
+        }
+        let first = match value.value_for_expression_path("[0]").check() {
+            Some(first) => first,
+            None => bail!("Value cannot be sliced"),
+        };
+        let elem_type = first.type_();
+        let elem_size = elem_type.byte_size();
+        let base = first.load_address();
+        let contiguous = match value.value_for_expression_path("[1]").check() {
+            Some(second) => second.load_address() == base + elem_size,
+            None => true,
+        };
+        if base == INVALID_ADDRESS || !contiguous {
+            bail!("Elements are not stored contiguously in memory");
+        }
+        let addr = SBAddress::from_load_address(base + start as u64 * elem_size, self.target);
+        let ty = elem_type.array_type((end - start) as u64);
+        Ok(self.target.create_value_from_address(&format!("[{}..{}]", start, end), &addr, &ty))
+    }
+
+    fn eval_unary(&self, op: UnaryOp, e: &SimpleExpr) -> Result<Value, Error> {
+        Ok(match op {
+            UnaryOp::Deref => Value::Object(check(self.eval_object(e)?.dereference())?),
+            UnaryOp::AddressOf => Value::Object(check(self.eval_object(e)?.address_of())?),
+            UnaryOp::Not => Value::Bool(!truthy(&self.eval_scalar(e)?)),
+            UnaryOp::Plus => match self.eval_scalar(e)? {
+                Value::Bool(b) => Value::Int(b as i128),
+                value @ (Value::Int(_) | Value::Float(_)) => value,
+                _ => bail!("Bad operand type for unary +"),
+            },
+            UnaryOp::Neg => match self.eval_scalar(e)? {
+                Value::Bool(b) => Value::Int(-(b as i128)),
+                Value::Int(i) => Value::Int(-i),
+                Value::Float(f) => Value::Float(-f),
+                _ => bail!("Bad operand type for unary -"),
+            },
+            UnaryOp::BitNot => match self.eval_scalar(e)? {
+                Value::Bool(b) => Value::Int(!(b as i128)),
+                Value::Int(i) => Value::Int(!i),
+                _ => bail!("Bad operand type for unary ~"),
+            },
+        })
+    }
+
+    fn eval_binary(&self, lhs: &SimpleExpr, op: BinaryOp, rhs: &SimpleExpr) -> Result<Value, Error> {
+        // Logical operators short-circuit, so the right operand is evaluated only when needed.
+        match op {
+            BinaryOp::And => {
+                return Ok(Value::Bool(
+                    truthy(&self.eval_scalar(lhs)?) && truthy(&self.eval_scalar(rhs)?),
+                ));
+            }
+            BinaryOp::Or => {
+                return Ok(Value::Bool(
+                    truthy(&self.eval_scalar(lhs)?) || truthy(&self.eval_scalar(rhs)?),
+                ));
+            }
+            _ => {}
+        }
+
+        let (lhs, rhs) = (self.eval_scalar(lhs)?, self.eval_scalar(rhs)?);
+        let result = match (lhs, rhs) {
+            (Value::Str(l), Value::Str(r)) => match op {
+                BinaryOp::Add => Value::Str(l + &r),
+                _ => Value::Bool(compare(op, l.cmp(&r))?),
+            },
+            (Value::Str(_), _) | (_, Value::Str(_)) => match op {
+                BinaryOp::Eq => Value::Bool(false),
+                BinaryOp::Ne => Value::Bool(true),
+                _ => bail!(format!("Unsupported operand types for {}", op.as_str())),
+            },
+            (Value::Float(l), r) => float_op(op, l, as_float(&r))?,
+            (l, Value::Float(r)) => float_op(op, as_float(&l), r)?,
+            (l, r) => int_op(op, as_int(&l), as_int(&r))?,
+        };
+        Ok(result)
+    }
+
+    fn eval_object(&self, expr: &SimpleExpr) -> Result<SBValue, Error> {
+        match self.eval(expr)? {
+            Value::Object(value) => Ok(value),
+            _ => bail!("Expected an object"),
+        }
+    }
+
+    // Evaluates an expression and converts SBValue results into a primitive value.
+    fn eval_scalar(&self, expr: &SimpleExpr) -> Result<Value, Error> {
+        match self.eval(expr)? {
+            Value::Object(value) => Ok(to_scalar(&value)),
+            value => Ok(value),
+        }
+    }
+
+    // Same lookup order as `nat_eval` in the Python interface.
+    fn lookup(&self, name: &str) -> Result<SBValue, Error> {
+        let value = match self.frame {
+            Some(frame) => {
+                let value = frame.find_variable(name).or_else(|| {
+                    [
+                        ValueType::VariableGlobal,
+                        ValueType::VariableStatic,
+                        ValueType::Register,
+                        ValueType::ConstResult,
+                    ]
+                    .into_iter()
+                    .find_map(|value_type| frame.find_value(name, value_type))
+                });
+                match value.or_else(|| frame.get_value_for_variable_path(name).check()) {
+                    Some(value) => value,
+                    None => frame.evaluate_expression(name),
+                }
+            }
+            None => self.target.evaluate_expression(name),
+        };
+        Ok(value.into_result()?)
+    }
+
+    // Parses type names like "char * &".
+    fn resolve_type(&self, ty: &str) -> Result<SBType, Error> {
+        let name = ty.trim_end_matches(|c| c == '*' || c == '&' || c == ' ').trim();
+        let Some(mut sbtype) = self.target.find_first_type(name) else {
+            bail!(format!("Could not resolve type \"{}\"", ty));
+        };
+        for ch in ty[ty.find(name).unwrap() + name.len()..].chars() {
+            match ch {
+                '*' => sbtype = sbtype.pointer_type(),
+                '&' => sbtype = sbtype.reference_type(),
+                _ => {}
+            }
+        }
+        Ok(sbtype)
+    }
+
+    fn into_sbvalue(&self, value: Value) -> Result<SBValue, Error> {
+        let byte_order = self.target.byte_order();
+        let addr_size = self.target.address_byte_size();
+        let (bytes, basic_type) = match value {
+            Value::Object(value) => return Ok(value),
+            Value::Str(s) => {
+                let cstr = std::ffi::CString::new(s)?;
+                let data = SBData::from_cstr(&cstr, byte_order, addr_size);
+                let ty = self.target.get_basic_type(BasicType::Char).array_type(data.byte_size() as u64);
+                return Ok(self.target.create_value_from_data("result", &data, &ty));
+            }
+            Value::Bool(b) => (vec![b as u8], BasicType::Bool),
+            Value::Float(f) => (
+                to_bytes(f.to_le_bytes(), f.to_be_bytes(), byte_order),
+                BasicType::Double,
+            ),
+            Value::Int(i) => match (i64::try_from(i), u64::try_from(i)) {
+                (Ok(i), _) => (
+                    to_bytes(i.to_le_bytes(), i.to_be_bytes(), byte_order),
+                    BasicType::LongLong,
+                ),
+                (_, Ok(u)) => (
+                    to_bytes(u.to_le_bytes(), u.to_be_bytes(), byte_order),
+                    BasicType::UnsignedLongLong,
+                ),
+                _ => bail!("Integer overflow"),
+            },
+        };
+        let data = SBData::borrow_bytes(&bytes, byte_order, addr_size);
+        let ty = self.target.get_basic_type(basic_type);
+        Ok(self.target.create_value_from_data("result", &data, &ty))
+    }
+}
+
+// Number of elements, as shown by the formatted view of the value.
+fn collection_len(value: &SBValue) -> u32 {
+    if !value.is_synthetic() {
+        // Rust slices without formatters: a pointer + length pair.
+        if let Some(length) = value.child_member_with_name("length") {
+            return length.value_as_unsigned(0) as u32;
+        }
+    }
+    value.num_children()
+}
+
+// Checks whether a Rust enum value holds the specified variant.
+// The active variant is determined from the formatted view: its summary (e.g. `Some(42)`) or type name.
+fn is_variant(value: &SBValue, variant: &str) -> bool {
+    fn last_segment(path: &str) -> &str {
+        let path = path.split('<').next().unwrap_or_default().trim();
+        path.rsplit("::").next().unwrap_or_default()
+    }
+    fn leading_ident(text: &str) -> &str {
+        let end = text.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':')).unwrap_or(text.len());
+        &text[..end]
+    }
+    let variant = last_segment(variant);
+    let candidates = [
+        value.summary().map(|s| s.to_string_lossy().into_owned()),
+        value.value().map(|s| s.to_string_lossy().into_owned()),
+    ];
+    if candidates.iter().flatten().any(|text| last_segment(leading_ident(text)) == variant) {
+        return true;
+    }
+    match (value.display_type_name(), value.type_name()) {
+        (Some(display_name), Some(type_name)) if display_name != type_name => last_segment(display_name) == variant,
+        _ => false,
+    }
+}
+
+fn check(value: SBValue) -> Result<SBValue, Error> {
+    Ok(value.into_result()?)
+}
+
+fn to_bytes(le: [u8; 8], be: [u8; 8], byte_order: ByteOrder) -> Vec<u8> {
+    match byte_order {
+        ByteOrder::Big => be.to_vec(),
+        _ => le.to_vec(),
+    }
+}
+
+// Converts SBValue into a number, a boolean or, for non-scalar types, its summary string.
+fn to_scalar(value: &SBValue) -> Value {
+    let ty = value.type_().canonical_type();
+    if ty.is_pointer_type() {
+        return Value::Int(value.value_as_unsigned(0) as i128);
+    }
+    match ty.basic_type() {
+        BasicType::Bool => Value::Bool(value.value_as_unsigned(0) != 0),
+        BasicType::SignedChar
+        | BasicType::SignedWChar
+        | BasicType::Short
+        | BasicType::Int
+        | BasicType::Long
+        | BasicType::LongLong
+        | BasicType::Int128 => Value::Int(value.value_as_signed(0) as i128),
+        BasicType::Char
+        | BasicType::UnsignedChar
+        | BasicType::WChar
+        | BasicType::UnsignedWChar
+        | BasicType::Char16
+        | BasicType::Char32
+        | BasicType::UnsignedShort
+        | BasicType::UnsignedInt
+        | BasicType::UnsignedLong
+        | BasicType::UnsignedLongLong
+        | BasicType::UnsignedInt128 => Value::Int(value.value_as_unsigned(0) as i128),
+        BasicType::Half | BasicType::Float | BasicType::Double | BasicType::LongDouble => {
+            let text = value.value().map(|v| v.to_string_lossy()).unwrap_or_default();
+            Value::Float(text.parse().unwrap_or(f64::NAN))
+        }
+        _ if ty.type_flags().contains(TypeFlags::IsEnumeration) => Value::Int(value.value_as_signed(0) as i128),
+        _ => {
+            let summary = value.summary().or(value.value()).map(|s| s.to_string_lossy().into_owned());
+            let summary = summary.unwrap_or_default();
+            match summary.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                Some(s) => Value::Str(s.to_owned()),
+                None => Value::Str(summary),
+            }
+        }
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Object(_) => true,
+        Value::Int(i) => *i != 0,
+        Value::Float(f) => *f != 0.0,
+        Value::Bool(b) => *b,
+        Value::Str(s) => !s.is_empty(),
+    }
+}
+
+fn as_int(value: &Value) -> i128 {
+    match value {
+        Value::Int(i) => *i,
+        Value::Bool(b) => *b as i128,
+        _ => 0,
+    }
+}
+
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Float(f) => *f,
+        value => as_int(value) as f64,
+    }
+}
+
+fn compare(op: BinaryOp, ordering: std::cmp::Ordering) -> Result<bool, Error> {
+    Ok(match op {
+        BinaryOp::Eq => ordering.is_eq(),
+        BinaryOp::Ne => ordering.is_ne(),
+        BinaryOp::Lt => ordering.is_lt(),
+        BinaryOp::Le => ordering.is_le(),
+        BinaryOp::Gt => ordering.is_gt(),
+        BinaryOp::Ge => ordering.is_ge(),
+        _ => bail!(format!("Unsupported operand types for {}", op.as_str())),
+    })
+}
+
+// Integer arithmetic follows Python: `/` produces a float, while `//` and `%` round towards negative infinity.
+fn int_op(op: BinaryOp, l: i128, r: i128) -> Result<Value, Error> {
+    let overflow = || str_error("Integer overflow");
+    let div_by_zero = || str_error("Division by zero");
+    Ok(Value::Int(match op {
+        BinaryOp::Add => l.checked_add(r).ok_or_else(overflow)?,
+        BinaryOp::Sub => l.checked_sub(r).ok_or_else(overflow)?,
+        BinaryOp::Mul => l.checked_mul(r).ok_or_else(overflow)?,
+        BinaryOp::Div => return float_op(op, l as f64, r as f64),
+        BinaryOp::FloorDiv => {
+            let q = l.checked_div(r).ok_or_else(div_by_zero)?;
+            if l % r != 0 && (l < 0) != (r < 0) {
+                q - 1
+            } else {
+                q
+            }
+        }
+        BinaryOp::Mod => {
+            let m = l.checked_rem(r).ok_or_else(div_by_zero)?;
+            if m != 0 && (m < 0) != (r < 0) {
+                m + r
+            } else {
+                m
+            }
+        }
+        BinaryOp::Pow => match u32::try_from(r) {
+            Ok(r) => l.checked_pow(r).ok_or_else(overflow)?,
+            Err(_) => return float_op(op, l as f64, r as f64),
+        },
+        BinaryOp::Shl => match u32::try_from(r) {
+            Ok(r) if r < 127 => l.checked_mul(1 << r).ok_or_else(overflow)?,
+            _ => bail!("Invalid shift count"),
+        },
+        BinaryOp::Shr => match u32::try_from(r) {
+            Ok(r) => l >> r.min(127),
+            _ => bail!("Invalid shift count"),
+        },
+        BinaryOp::BitAnd => l & r,
+        BinaryOp::BitOr => l | r,
+        BinaryOp::BitXor => l ^ r,
+        _ => return Ok(Value::Bool(compare(op, l.cmp(&r))?)),
+    }))
+}
+
+fn float_op(op: BinaryOp, l: f64, r: f64) -> Result<Value, Error> {
+    Ok(Value::Float(match op {
+        BinaryOp::Add => l + r,
+        BinaryOp::Sub => l - r,
+        BinaryOp::Mul => l * r,
+        BinaryOp::Div | BinaryOp::FloorDiv | BinaryOp::Mod if r == 0.0 => bail!("Division by zero"),
+        BinaryOp::Div => l / r,
+        BinaryOp::FloorDiv => (l / r).floor(),
+        BinaryOp::Mod => l - r * (l / r).floor(),
+        BinaryOp::Pow => l.powf(r),
+        _ => match l.partial_cmp(&r) {
+            Some(ordering) => return Ok(Value::Bool(compare(op, ordering)?)),
+            None => return Ok(Value::Bool(op == BinaryOp::Ne)),
+        },
+    }))
+}
+
+// Strips quotes from a Python string literal and processes escape sequences.
+fn unquote(lexeme: &str) -> String {
+    if let Some(raw) = lexeme.strip_prefix('r') {
+        return raw[1..raw.len() - 1].to_owned();
+    }
+    let mut result = String::new();
+    let mut chars = lexeme[1..lexeme.len() - 1].chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            result.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('0') => result.push('\0'),
+            Some(ch @ ('\\' | '\'' | '"')) => result.push(ch),
+            Some(ch) => {
+                result.push('\\');
+                result.push(ch);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_unquote() {
+    assert_eq!(unquote(r#""abc""#), "abc");
+    assert_eq!(unquote(r#"'a\'b\n'"#), "a'b\n");
+    assert_eq!(unquote(r#"'\q'"#), "\\q");
+    assert_eq!(unquote(r#"r"a\nb""#), "a\\nb");
+}
+
+#[test]
+fn test_int_op() {
+    fn int(value: Value) -> i128 {
+        match value {
+            Value::Int(i) => i,
+            _ => panic!("Expected an integer"),
+        }
+    }
+    assert_eq!(int(int_op(BinaryOp::FloorDiv, -7, 2).unwrap()), -4);
+    assert_eq!(int(int_op(BinaryOp::FloorDiv, 7, -2).unwrap()), -4);
+    assert_eq!(int(int_op(BinaryOp::Mod, -7, 3).unwrap()), 2);
+    assert_eq!(int(int_op(BinaryOp::Mod, 7, -3).unwrap()), -2);
+    assert_eq!(int(int_op(BinaryOp::Shl, 1, 40).unwrap()), 1 << 40);
+    assert_eq!(int(int_op(BinaryOp::Pow, 2, 10).unwrap()), 1024);
+    assert!(int_op(BinaryOp::FloorDiv, 1, 0).is_err());
+    assert!(matches!(int_op(BinaryOp::Div, 7, 2).unwrap(), Value::Float(f) if f == 3.5));
+    assert!(matches!(int_op(BinaryOp::Le, 7, 7).unwrap(), Value::Bool(true)));
+}
@@ -1,12 +1,10 @@
-use std::borrow::Cow;
-
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{char, one_of},
-    combinator::{opt, recognize},
-    multi::many0,
-    sequence::{delimited, pair, preceded},
+    bytes::complete::{is_not, tag},
+    character::complete::{char, one_of, satisfy, space1},
+    combinator::{not, opt, recognize},
+    multi::{many0, separated_list1},
+    sequence::{delimited, pair, preceded, terminated},
     Parser,
 };
 use nom_language::precedence::{binary_op, precedence, unary_op, Assoc, Binary, Unary};
@@ -17,9 +15,222 @@ use super::qualified_ident::{ident, qualified_ident};
 
 use super::prelude::*;
 
-#[allow(mismatched_lifetime_syntaxes)]
-pub fn expression(input: Span) -> IResult<Span, String> {
-    fn operand(input: Span) -> IResult<Span, Cow<str>> {
+// Syntax tree of a simple expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimpleExpr {
+    Int(String),
+    Float(String),
+    Bool(bool),
+    // Python string literal, including the quotes.
+    Str(String),
+    // A variable name.
+    Var(String),
+    // A $-prefixed name or native expression.  Pseudo-variables, such as `$exception`, only ever appear in this form.
+    DollarVar(String),
+    Paren(Box<SimpleExpr>),
+    Cast(String, Box<SimpleExpr>),
+    Unary(UnaryOp, Box<SimpleExpr>),
+    Binary(Box<SimpleExpr>, BinaryOp, Box<SimpleExpr>),
+    Member(Box<SimpleExpr>, String),
+    Index(Box<SimpleExpr>, Box<SimpleExpr>),
+    // Rust-specific syntax:
+    // `expr as Type`, with the type name converted to the form used by `Cast`, e.g. `*const u8` becomes `u8 *`.
+    As(Box<SimpleExpr>, String),
+    // `expr[start..end]`, `expr[start..=end]`
+    Slice {
+        expr: Box<SimpleExpr>,
+        start: Option<Box<SimpleExpr>>,
+        end: Option<Box<SimpleExpr>>,
+        inclusive: bool,
+    },
+    // `expr.method()`
+    Call(Box<SimpleExpr>, String),
+    // `matches!(expr, Variant1 | Variant2)`
+    Matches(Box<SimpleExpr>, Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+    Plus,
+    BitNot,
+    Deref,
+    AddressOf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Shl,
+    Shr,
+    Pow,
+    Ge,
+    Gt,
+    Le,
+    Lt,
+    Add,
+    Sub,
+    Mul,
+    FloorDiv,
+    Div,
+    Mod,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+}
+
+impl BinaryOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinaryOp::Or => "or",
+            BinaryOp::And => "and",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::Pow => "**",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Le => "<=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::FloorDiv => "//",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+        }
+    }
+}
+
+impl SimpleExpr {
+    // Translates the expression into Python code that uses the helpers provided by the `codelldb` Python package.
+    pub fn to_python(&self) -> String {
+        match self {
+            SimpleExpr::Int(lexeme) | SimpleExpr::Float(lexeme) | SimpleExpr::Str(lexeme) => lexeme.clone(),
+            SimpleExpr::Bool(true) => "True".into(),
+            SimpleExpr::Bool(false) => "False".into(),
+            SimpleExpr::Var(name) | SimpleExpr::DollarVar(name) => format!("__eval('{name}')"),
+            SimpleExpr::Paren(e) => format!("({})", e.to_python()),
+            SimpleExpr::Cast(ty, e) => format!("Value.cast('{ty}', {})", e.to_python()),
+            SimpleExpr::Unary(op, e) => {
+                let e = e.to_python();
+                match op {
+                    UnaryOp::Not => format!("(not {e})"),
+                    UnaryOp::Neg => format!("-{e}"),
+                    UnaryOp::Plus => format!("+{e}"),
+                    UnaryOp::BitNot => format!("~{e}"),
+                    UnaryOp::Deref => format!("Value.dereference({e})"),
+                    UnaryOp::AddressOf => format!("Value.address_of({e})"),
+                }
+            }
+            SimpleExpr::Binary(lhs, op, rhs) => {
+                format!("({} {} {})", lhs.to_python(), op.as_str(), rhs.to_python())
+            }
+            SimpleExpr::Member(e, name) => format!("{}.__getattr__('{name}')", e.to_python()),
+            SimpleExpr::Index(e, index) => format!("{}[{}]", e.to_python(), index.to_python()),
+            SimpleExpr::As(e, ty) => format!("Value.cast('{ty}', {})", e.to_python()),
+            // These have no Python equivalent; expressions that use them are always evaluated natively.
+            SimpleExpr::Slice {
+                expr,
+                start,
+                end,
+                inclusive,
+            } => format!(
+                "{}[{}{}{}]",
+                expr.to_python(),
+                start.as_ref().map(|e| e.to_python()).unwrap_or_default(),
+                if *inclusive { "..=" } else { ".." },
+                end.as_ref().map(|e| e.to_python()).unwrap_or_default()
+            ),
+            SimpleExpr::Call(e, method) => format!("{}.{method}()", e.to_python()),
+            SimpleExpr::Matches(e, variants) => format!("matches!({}, {})", e.to_python(), variants.join(" | ")),
+        }
+    }
+
+    // Checks whether the expression uses any of the Rust-specific syntax.
+    pub fn has_rust_syntax(&self) -> bool {
+        match self {
+            SimpleExpr::Int(_)
+            | SimpleExpr::Float(_)
+            | SimpleExpr::Bool(_)
+            | SimpleExpr::Str(_)
+            | SimpleExpr::Var(_)
+            | SimpleExpr::DollarVar(_) => false,
+            SimpleExpr::As(..) | SimpleExpr::Slice { .. } | SimpleExpr::Call(..) | SimpleExpr::Matches(..) => true,
+            SimpleExpr::Paren(e) | SimpleExpr::Cast(_, e) | SimpleExpr::Unary(_, e) | SimpleExpr::Member(e, _) => {
+                e.has_rust_syntax()
+            }
+            SimpleExpr::Binary(lhs, _, rhs) | SimpleExpr::Index(lhs, rhs) => {
+                lhs.has_rust_syntax() || rhs.has_rust_syntax()
+            }
+        }
+    }
+
+    // Checks whether the expression refers to the given pseudo-variable, i.e. contains `$name`.
+    pub fn references_pseudo_var(&self, name: &str) -> bool {
+        match self {
+            SimpleExpr::Int(_) | SimpleExpr::Float(_) | SimpleExpr::Bool(_) | SimpleExpr::Str(_) => false,
+            SimpleExpr::Var(_) => false,
+            SimpleExpr::DollarVar(var) => var == name,
+            SimpleExpr::Paren(e)
+            | SimpleExpr::Cast(_, e)
+            | SimpleExpr::Unary(_, e)
+            | SimpleExpr::Member(e, _)
+            | SimpleExpr::As(e, _)
+            | SimpleExpr::Call(e, _)
+            | SimpleExpr::Matches(e, _) => e.references_pseudo_var(name),
+            SimpleExpr::Binary(lhs, _, rhs) | SimpleExpr::Index(lhs, rhs) => {
+                lhs.references_pseudo_var(name) || rhs.references_pseudo_var(name)
+            }
+            SimpleExpr::Slice { expr, start, end, .. } => {
+                expr.references_pseudo_var(name)
+                    || start.as_ref().map_or(false, |e| e.references_pseudo_var(name))
+                    || end.as_ref().map_or(false, |e| e.references_pseudo_var(name))
+            }
+        }
+    }
+
+    // Replaces references to the given pseudo-variable with a native expression.
+    pub fn replace_pseudo_var(&mut self, name: &str, replacement: &str) {
+        match self {
+            SimpleExpr::Int(_) | SimpleExpr::Float(_) | SimpleExpr::Bool(_) | SimpleExpr::Str(_) => {}
+            SimpleExpr::Var(_) => {}
+            SimpleExpr::DollarVar(var) => {
+                if var == name {
+                    *var = replacement.to_owned();
+                }
+            }
+            SimpleExpr::Paren(e)
+            | SimpleExpr::Cast(_, e)
+            | SimpleExpr::Unary(_, e)
+            | SimpleExpr::Member(e, _)
+            | SimpleExpr::As(e, _)
+            | SimpleExpr::Call(e, _)
+            | SimpleExpr::Matches(e, _) => e.replace_pseudo_var(name, replacement),
+            SimpleExpr::Binary(lhs, _, rhs) | SimpleExpr::Index(lhs, rhs) => {
+                lhs.replace_pseudo_var(name, replacement);
+                rhs.replace_pseudo_var(name, replacement);
+            }
+            SimpleExpr::Slice { expr, start, end, .. } => {
+                expr.replace_pseudo_var(name, replacement);
+                for e in [start, end].into_iter().flatten() {
+                    e.replace_pseudo_var(name, replacement);
+                }
+            }
+        }
+    }
+}
+
+pub fn expression(input: Span) -> IResult<Span, SimpleExpr> {
+    fn operand(input: Span) -> IResult<Span, SimpleExpr> {
         let cast = delimited(
             char('('),
             recognize(pair(ws(qualified_ident), many0(ws(one_of("*&"))))),
@@ -29,32 +240,71 @@ pub fn expression(input: Span) -> IResult<Span, String> {
         pair(
             opt(ws(cast)),
             ws(alt((
-                numeric_literal.map(Cow::from),
-                boolean_literal,
-                python_string.map(Cow::from),
-                recognize(qualified_ident).map(|e| format!("__eval('{e}')").into()),
-                native_expr.map(|e| format!("__eval('{e}')").into()),
-                delimited(tag("("), expression, tag(")")).map(|e| format!("({e})").into()),
+                // Don't let "1." swallow the first dot of a range, as in `v[1..5]`.
+                terminated(numeric_literal, not(char('.'))).map(|l| match integer_literal(l) {
+                    Ok(("", _)) => SimpleExpr::Int(l.into()),
+                    _ => SimpleExpr::Float(l.into()),
+                }),
+                integer_literal.map(|l| SimpleExpr::Int(l.into())),
+                boolean_literal.map(|b| SimpleExpr::Bool(b == "True")),
+                python_string.map(|s| SimpleExpr::Str(s.into())),
+                matches_macro,
+                recognize(qualified_ident).map(|e| SimpleExpr::Var(e.into())),
+                native_expr.map(|e| SimpleExpr::DollarVar(e.into())),
+                delimited(tag("("), expression, tag(")")).map(|e| SimpleExpr::Paren(Box::new(e))),
             ))),
         )
         .map(|(c, e)| match c {
-            Some(c) => format!("Value.cast('{c}', {e})").into(),
+            Some(c) => SimpleExpr::Cast(c.into(), Box::new(e)),
             None => e,
         })
         .parse(input)
     }
 
-    fn prefix(input: Span) -> IResult<Span, Unary<&str, i32>> {
+    // `matches!(expr, Pattern1 | Pattern2)`; only the variant paths of patterns are significant.
+    fn matches_macro(input: Span) -> IResult<Span, SimpleExpr> {
+        let pattern = terminated(
+            ws(recognize(qualified_ident)),
+            opt(ws(alt((
+                delimited(char('('), is_not(")"), char(')')),
+                delimited(char('{'), is_not("}"), char('}')),
+            )))),
+        );
+        (
+            tag("matches!"),
+            ws(char('(')),
+            expression,
+            char(','),
+            separated_list1(char('|'), pattern),
+            char(')'),
+        )
+            .map(|(_, _, e, _, variants, _)| {
+                SimpleExpr::Matches(Box::new(e), variants.into_iter().map(|v| v.to_owned()).collect())
+            })
+            .parse(input)
+    }
+
+    // Rust types in `as` casts: paths, primitive types, pointers and references.
+    fn rust_type(input: Span) -> IResult<Span, String> {
+        alt((
+            preceded(pair(alt((tag("*const"), tag("*mut"))), space1), rust_type).map(|ty| format!("{ty} *")),
+            preceded(pair(char('&'), opt(pair(tag("mut"), space1))), ws(rust_type)).map(|ty| format!("{ty} *")),
+            recognize(qualified_ident).map(|ty| ty.to_owned()),
+        ))
+        .parse(input)
+    }
+
+    fn prefix(input: Span) -> IResult<Span, Unary<UnaryOp, i32>> {
         unary_op(
             3,
             ws(alt((
-                tag("!"),
-                tag("not"),
-                tag("-"),
-                tag("+"),
-                tag("~"),
-                tag("*"),
-                tag("&"),
+                tag("!").map(|_| UnaryOp::Not),
+                tag("not").map(|_| UnaryOp::Not),
+                tag("-").map(|_| UnaryOp::Neg),
+                tag("+").map(|_| UnaryOp::Plus),
+                tag("~").map(|_| UnaryOp::BitNot),
+                tag("*").map(|_| UnaryOp::Deref),
+                tag("&").map(|_| UnaryOp::AddressOf),
             ))),
         )
         .parse(input)
@@ -63,60 +313,99 @@ pub fn expression(input: Span) -> IResult<Span, String> {
     // Operators and their precedence mostly follows C++, with two Python additions: `**` and `//`.
     // Nom tries alternates in the order they are declared, so for ambiguous tokens (e.g., `&&` vs `&`,
     // or `<<` vs `<`), the longer operators are listed before the shorter ones so they are matched first.
-    fn binary(input: Span) -> IResult<Span, Binary<&str, i32>> {
+    fn binary(input: Span) -> IResult<Span, Binary<BinaryOp, i32>> {
+        use BinaryOp::*;
+        fn op<'a>(
+            t: &'static str,
+            op: BinaryOp,
+        ) -> impl Parser<Span<'a>, Output = BinaryOp, Error = nom::error::Error<Span<'a>>> {
+            tag(t).map(move |_| op)
+        }
         ws(alt((
-            binary_op(15, Assoc::Left, alt((tag("or"), tag("||"))).map(|_| "or")),
-            binary_op(14, Assoc::Left, alt((tag("and"), tag("&&"))).map(|_| "and")),
-            binary_op(7, Assoc::Left, alt((tag("<<"), tag(">>")))),
-            binary_op(4, Assoc::Right, tag("**")),
-            binary_op(9, Assoc::Left, alt((tag(">="), tag(">"), tag("<="), tag("<")))),
-            binary_op(6, Assoc::Left, alt((tag("+"), tag("-")))),
-            binary_op(5, Assoc::Left, alt((tag("*"), tag("//"), tag("/"), tag("%")))),
-            binary_op(13, Assoc::Left, tag("|")),
-            binary_op(12, Assoc::Left, tag("^")),
-            binary_op(11, Assoc::Left, tag("&")),
-            binary_op(10, Assoc::Left, alt((tag("=="), tag("!=")))),
+            binary_op(15, Assoc::Left, alt((op("or", Or), op("||", Or)))),
+            binary_op(14, Assoc::Left, alt((op("and", And), op("&&", And)))),
+            binary_op(7, Assoc::Left, alt((op("<<", Shl), op(">>", Shr)))),
+            binary_op(4, Assoc::Right, op("**", Pow)),
+            binary_op(
+                9,
+                Assoc::Left,
+                alt((op(">=", Ge), op(">", Gt), op("<=", Le), op("<", Lt))),
+            ),
+            binary_op(6, Assoc::Left, alt((op("+", Add), op("-", Sub)))),
+            binary_op(
+                5,
+                Assoc::Left,
+                alt((op("*", Mul), op("//", FloorDiv), op("/", Div), op("%", Mod))),
+            ),
+            binary_op(13, Assoc::Left, op("|", BitOr)),
+            binary_op(12, Assoc::Left, op("^", BitXor)),
+            binary_op(11, Assoc::Left, op("&", BitAnd)),
+            binary_op(10, Assoc::Left, alt((op("==", Eq), op("!=", Ne)))),
         )))
         .parse(input)
     }
 
-    fn postfix(input: Span) -> IResult<Span, Unary<(&str, Cow<str>), i32>> {
-        unary_op(
-            2,
-            alt((
-                preceded(ws(char('.')), ident).map(|arg| (".", Cow::from(arg))),
-                preceded(ws(char('.')), recognize(unsigned)).map(|arg| (".", Cow::from(arg))),
-                preceded(ws(tag("->")), ident).map(|arg| ("->", Cow::from(arg))),
-                delimited(
-                    ws(char('[')),
-                    alt((integer_literal.map(Cow::from), expression.map(Cow::from))),
-                    ws(char(']')),
-                )
-                .map(|arg| ("[]", arg)),
-            )),
-        )
+    enum PostfixOp {
+        Member(String),
+        Arrow(String),
+        Index(SimpleExpr),
+        Slice(Option<SimpleExpr>, Option<SimpleExpr>, bool),
+        Call(String),
+        As(String),
+    }
+
+    fn postfix(input: Span) -> IResult<Span, Unary<PostfixOp, i32>> {
+        let keyword_as = terminated(tag("as"), not(satisfy(|c: char| c.is_alphanumeric() || c == '_')));
+        alt((
+            unary_op(
+                2,
+                alt((
+                    preceded(ws(char('.')), terminated(ident, pair(ws(char('(')), char(')'))))
+                        .map(|method| PostfixOp::Call(method.into())),
+                    preceded(ws(char('.')), ident).map(|arg| PostfixOp::Member(arg.into())),
+                    preceded(ws(char('.')), recognize(unsigned)).map(|arg| PostfixOp::Member(arg.into())),
+                    preceded(ws(tag("->")), ident).map(|arg| PostfixOp::Arrow(arg.into())),
+                    delimited(
+                        ws(char('[')),
+                        (opt(expression), ws(tag("..")), opt(char('=')), opt(expression)),
+                        ws(char(']')),
+                    )
+                    .map(|(start, _, inclusive, end)| PostfixOp::Slice(start, end, inclusive.is_some())),
+                    delimited(
+                        ws(char('[')),
+                        alt((integer_literal.map(|l| SimpleExpr::Int(l.into())), expression)),
+                        ws(char(']')),
+                    )
+                    .map(PostfixOp::Index),
+                )),
+            ),
+            // Binds tighter than binary operators, but looser than prefix ones.
+            unary_op(4, preceded(ws(keyword_as), ws(rust_type)).map(PostfixOp::As)),
+        ))
         .parse(input)
     }
 
     precedence(prefix, postfix, binary, operand, |op| {
         use nom_language::precedence::Operation::*;
-        Ok(match op {
-            Prefix(op, o) => match op {
-                "*" => format!("Value.dereference({o})").into(),
-                "&" => format!("Value.address_of({o})").into(),
-                "!" | "not" => format!("(not {o})").into(),
-                _ => format!("{op}{o}").into(),
-            },
-            Binary(lhs, op, rhs) => format!("({lhs} {op} {rhs})").into(),
-            Postfix(o, (op, arg)) => match op {
-                "." => format!("{o}.__getattr__('{arg}')").into(),
-                "->" => format!("Value.dereference({o}).__getattr__('{arg}')").into(),
-                "[]" => format!("{o}[{arg}]").into(),
-                _ => return Err(""),
+        Ok::<_, &str>(match op {
+            Prefix(op, o) => SimpleExpr::Unary(op, Box::new(o)),
+            Binary(lhs, op, rhs) => SimpleExpr::Binary(Box::new(lhs), op, Box::new(rhs)),
+            Postfix(o, PostfixOp::Member(arg)) => SimpleExpr::Member(Box::new(o), arg),
+            // `a->b` is the same as `(*a).b`
+            Postfix(o, PostfixOp::Arrow(arg)) => {
+                SimpleExpr::Member(Box::new(SimpleExpr::Unary(UnaryOp::Deref, Box::new(o))), arg)
+            }
+            Postfix(o, PostfixOp::Index(index)) => SimpleExpr::Index(Box::new(o), Box::new(index)),
+            Postfix(o, PostfixOp::Slice(start, end, inclusive)) => SimpleExpr::Slice {
+                expr: Box::new(o),
+                start: start.map(Box::new),
+                end: end.map(Box::new),
+                inclusive,
             },
+            Postfix(o, PostfixOp::Call(method)) => SimpleExpr::Call(Box::new(o), method),
+            Postfix(o, PostfixOp::As(ty)) => SimpleExpr::As(Box::new(o), ty),
         })
     })
-    .map(|r| r.into_owned())
     .parse(input)
 }
 
@@ -125,6 +414,10 @@ pub fn expression(input: Span) -> IResult<Span, String> {
 mod test {
     use super::*;
 
+    fn expression(input: Span) -> IResult<Span, String> {
+        super::expression.map(|e| e.to_python()).parse(input)
+    }
+
     #[test]
     fn primary_test() {
         test_parser!(expression, " foo", "__eval('foo')");
@@ -194,6 +487,28 @@ mod test {
         test_parser!(expression, " & foo->bar", "Value.address_of(Value.dereference(__eval('foo')).__getattr__('bar'))");
     }
 
+    #[test]
+    fn rust_test() {
+        test_parser!(expression, " x as u64", "Value.cast('u64', __eval('x'))");
+        test_parser!(expression, " p as *const foo::Bar", "Value.cast('foo::Bar *', __eval('p'))");
+        test_parser!(expression, " p as &mut *mut u8", "Value.cast('u8 * *', __eval('p'))");
+        test_parser!(expression, " -x as u8 * 2", "(Value.cast('u8', -__eval('x')) * 2)");
+        test_parser!(expression, " x.asset", "__eval('x').__getattr__('asset')");
+        test_parser!(expression, " v[2..5]", "__eval('v')[2..5]");
+        test_parser!(expression, " v[ 1.. ]", "__eval('v')[1..]");
+        test_parser!(expression, " v[..=n + 1]", "__eval('v')[..=(__eval('n') + 1)]");
+        test_parser!(expression, " v[..]", "__eval('v')[..]");
+        test_parser!(expression, " s.len() > 0", "(__eval('s').len() > 0)");
+        test_parser!(expression, " opt.is_some() && !v.is_empty()",
+                                 "(__eval('opt').is_some() and (not __eval('v').is_empty()))");
+        test_parser!(expression, " matches!(state, State::Ready)", "matches!(__eval('state'), State::Ready)");
+        test_parser!(expression, " matches!(r.x, Ok(_) | Err { .. })", "matches!(__eval('r').__getattr__('x'), Ok | Err)");
+        test_parser!(expression, " t.0.1", "__eval('t').__getattr__('0').__getattr__('1')");
+
+        assert!(super::expression("v[1..2]").unwrap().1.has_rust_syntax());
+        assert!(!super::expression("t.0 + v[1]").unwrap().1.has_rust_syntax());
+    }
+
     #[test]
     fn cast_test() {
         test_parser!(expression, " (foo) bar", "Value.cast('foo', __eval('bar'))");
@@ -43,6 +43,33 @@ pub fn lldb_quoted_string(input: &str) -> String {
     result
 }
 
+/// Convert a glob pattern into an equivalent regular expression: `**` matches any sequence of characters,
+/// `*` and `?` match any sequence of characters or a single character within a path component.
+pub fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if let Some('/' | '\\') = chars.peek() {
+                    // `**/` matches zero or more directories
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '/' | '\\' => regex.push('/'),
+            c => regex.push_str(&regex_lite::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
 #[test]
 fn test_normalize_path() {
     assert_eq!(normalize_path("/foo/bar"), Path::new("/foo/bar"));
@@ -61,3 +88,16 @@ fn test_lldb_quoting() {
     let quoted = lldb_quoted_string(&r#"foo " 'bar \ baz"#);
     assert_eq!(quoted, r#""foo \" 'bar \\ baz""#)
 }
+
+#[test]
+fn test_glob_to_regex() {
+    let matches = |glob: &str, path: &str| regex_lite::Regex::new(&glob_to_regex(glob)).unwrap().is_match(path);
+    assert!(matches("/src/**", "/src/foo/bar.rs"));
+    assert!(matches("/src/*.rs", "/src/main.rs"));
+    assert!(!matches("/src/*.rs", "/src/foo/main.rs"));
+    assert!(matches("**/vendor/**", "/project/vendor/foo/lib.rs"));
+    assert!(matches("/src/**/*.rs", "/src/main.rs"));
+    assert!(matches(r"c:\src\**", "c:/src/main.rs"));
+    assert!(matches("/src/ma?n.c++", "/src/main.c++"));
+    assert!(!matches("/src/ma?n.c++", "/src/maiin.c++"));
+}
@@ -1,4 +1,5 @@
 use crate::prelude::*;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use std::{env, net};
@@ -9,6 +10,7 @@ use dap_session::DAPChannel;
 use lldb::*;
 use tokio::io::AsyncWriteExt;
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio_util::codec::Decoder;
 
 #[allow(unused_imports)]
@@ -18,6 +20,8 @@ mod prelude {
 }
 #[macro_use]
 mod error;
+mod auth;
+mod batch;
 mod cancellation;
 mod dap_codec;
 mod dap_session;
@@ -28,8 +32,10 @@ mod expressions;
 mod fsutil;
 mod handles;
 mod must_initialize;
+mod natvis;
 mod platform;
 mod python;
+mod recording;
 mod shared;
 mod stdio_stream;
 mod terminal;
@@ -42,12 +48,31 @@ pub struct Cli {
     pub port: Option<u16>,
     #[arg(long)]
     pub connect: Option<u16>,
+    #[arg(long = "listen-unix")]
+    pub listen_unix: Option<PathBuf>,
+    #[arg(long = "connect-unix")]
+    pub connect_unix: Option<PathBuf>,
     #[arg(long = "auth-token")]
     pub auth_token: Option<String>,
+    /// Accept unauthenticated connections in --port mode.
+    #[arg(long = "no-auth")]
+    pub no_auth: bool,
     #[arg(long = "multi-session")]
     pub multi_session: bool,
+    /// The maximum number of concurrent sessions in multi-session mode.
+    #[arg(long = "max-sessions")]
+    pub max_sessions: Option<usize>,
     #[arg(long)]
     pub settings: Option<String>,
+    /// Record DAP messages into the specified file.
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+    /// Replay client messages from a recording and report divergences of the adapter's responses.
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+    /// Run a debugging session described by a batch script, without a DAP client.
+    #[arg(long)]
+    pub batch: Option<PathBuf>,
 }
 
 pub fn debug_server(cli: &Cli) -> Result<(), Error> {
@@ -84,12 +109,20 @@ pub fn debug_server(cli: &Cli) -> Result<(), Error> {
         }
     };
 
-    let (use_stdio, port, connect) = if let Some(port) = cli.connect {
-        (false, port, true)
+    let transport = if let Some(path) = &cli.batch {
+        Transport::Batch(path.clone())
+    } else if let Some(path) = &cli.replay {
+        Transport::Replay(path.clone())
+    } else if let Some(path) = &cli.connect_unix {
+        Transport::UnixConnect(path.clone())
+    } else if let Some(path) = &cli.listen_unix {
+        Transport::UnixListen(path.clone())
+    } else if let Some(port) = cli.connect {
+        Transport::TcpConnect(port)
     } else if let Some(port) = cli.port {
-        (false, port, false)
+        Transport::TcpListen(port)
     } else {
-        (true, 0, false)
+        Transport::Stdio
     };
 
     let rt = tokio::runtime::Builder::new_multi_thread() //
@@ -98,16 +131,56 @@ pub fn debug_server(cli: &Cli) -> Result<(), Error> {
         .build()
         .unwrap();
 
-    rt.block_on(async {
-        if use_stdio {
-            debug!("Starting on stdio");
-            let stream = stdio_stream::StdioStream::new();
-            let framed_stream = dap_codec::DAPCodec::new().framed(stream);
-            run_debug_session(Box::new(framed_stream), &adapter_settings, &python_interface).await;
-        } else {
-            let localhost = net::Ipv4Addr::new(127, 0, 0, 1);
-            let addr = net::SocketAddr::new(localhost.into(), port);
-            if connect {
+    // In listen mode, clients must authenticate themselves with the token.
+    if let Transport::TcpListen(_) = transport {
+        if cli.auth_token.is_none() {
+            if cli.no_auth {
+                warn!("Accepting unauthenticated connections.");
+            } else {
+                bail!("Listening on a TCP port requires --auth-token.");
+            }
+        }
+    }
+    let recorder = match &cli.record {
+        Some(path) => Some(recording::Recorder::create(path)?),
+        None => None,
+    };
+    let new_codec = || match &recorder {
+        Some(recorder) => dap_codec::DAPCodec::new().record_to(recorder.new_session()),
+        None => dap_codec::DAPCodec::new(),
+    };
+    let new_listener_codec = || match &cli.auth_token {
+        Some(auth_token) => new_codec().require_auth_token(auth_token.clone()),
+        None => new_codec(),
+    };
+
+    let max_sessions = cli.max_sessions.unwrap_or(Semaphore::MAX_PERMITS).max(1);
+    let session_slots = Arc::new(Semaphore::new(max_sessions));
+
+    let mut exit_code = None;
+    let result = rt.block_on(async {
+        let localhost = net::Ipv4Addr::new(127, 0, 0, 1);
+        match transport {
+            Transport::Batch(path) => {
+                exit_code = Some(batch::run_batch(&path, &adapter_settings, &python_interface).await?);
+            }
+            Transport::Replay(path) => {
+                let divergences = recording::replay(&path, &adapter_settings, &python_interface).await?;
+                if divergences > 0 {
+                    bail!(format!(
+                        "Replay diverged from the recording in {} place(s).",
+                        divergences
+                    ));
+                }
+            }
+            Transport::Stdio => {
+                debug!("Starting on stdio");
+                let stream = stdio_stream::StdioStream::new();
+                let framed_stream = new_codec().framed(stream);
+                run_debug_session(Box::new(framed_stream), &adapter_settings, &python_interface).await;
+            }
+            Transport::TcpConnect(port) => {
+                let addr = net::SocketAddr::new(localhost.into(), port);
                 debug!("Connecting to {}", addr);
                 let mut tcp_stream = TcpStream::connect(addr).await?;
                 tcp_stream.set_nodelay(true).unwrap();
@@ -115,30 +188,140 @@ pub fn debug_server(cli: &Cli) -> Result<(), Error> {
                     let auth_header = format!("Auth-Token: {}\r\n", auth_token);
                     tcp_stream.write_all(&auth_header.as_bytes()).await?;
                 }
-                let framed_stream = dap_codec::DAPCodec::new().framed(tcp_stream);
+                let framed_stream = new_codec().framed(tcp_stream);
                 run_debug_session(Box::new(framed_stream), &adapter_settings, &python_interface).await;
-            } else {
+            }
+            Transport::TcpListen(port) => {
+                let addr = net::SocketAddr::new(localhost.into(), port);
                 let listener = TcpListener::bind(&addr).await?;
-                while {
-                    debug!("Listening on {}", listener.local_addr()?);
+                debug!("Listening on {}", listener.local_addr()?);
+                loop {
+                    let permit = acquire_session_slot(&session_slots).await;
                     let (tcp_stream, _) = listener.accept().await?;
                     tcp_stream.set_nodelay(true).unwrap();
-                    let framed_stream = dap_codec::DAPCodec::new().framed(tcp_stream);
-                    run_debug_session(Box::new(framed_stream), &adapter_settings, &python_interface).await;
-                    cli.multi_session
-                } {}
+                    let framed_stream = new_listener_codec().framed(tcp_stream);
+                    if !cli.multi_session {
+                        run_debug_session(Box::new(framed_stream), &adapter_settings, &python_interface).await;
+                        break;
+                    }
+                    spawn_debug_session(Box::new(framed_stream), permit, &adapter_settings, &python_interface);
+                }
+            }
+            #[cfg(unix)]
+            Transport::UnixConnect(path) => {
+                debug!("Connecting to {:?}", path);
+                let mut unix_stream = tokio::net::UnixStream::connect(&path).await?;
+                if let Some(auth_token) = &cli.auth_token {
+                    let auth_header = format!("Auth-Token: {}\r\n", auth_token);
+                    unix_stream.write_all(&auth_header.as_bytes()).await?;
+                }
+                let framed_stream = new_codec().framed(unix_stream);
+                run_debug_session(Box::new(framed_stream), &adapter_settings, &python_interface).await;
+            }
+            #[cfg(unix)]
+            Transport::UnixListen(path) => {
+                let listener = unix_socket::bind(&path)?;
+                debug!("Listening on {:?}", path);
+                loop {
+                    let permit = acquire_session_slot(&session_slots).await;
+                    let (unix_stream, _) = listener.accept().await?;
+                    let framed_stream = new_listener_codec().framed(unix_stream);
+                    if !cli.multi_session {
+                        run_debug_session(Box::new(framed_stream), &adapter_settings, &python_interface).await;
+                        break;
+                    }
+                    spawn_debug_session(Box::new(framed_stream), permit, &adapter_settings, &python_interface);
+                }
+                drop(listener);
+                log_errors!(std::fs::remove_file(&path));
+            }
+            #[cfg(not(unix))]
+            Transport::UnixConnect(_) | Transport::UnixListen(_) => {
+                bail!("Unix domain sockets are not supported on this platform.");
             }
         }
         Ok::<(), Error>(())
-    })
-    .unwrap();
+    });
 
     rt.shutdown_timeout(Duration::from_millis(10));
 
     debug!("Exiting");
     #[cfg(not(windows))]
     SBDebugger::terminate();
-    Ok(())
+    if let (Ok(()), Some(exit_code)) = (&result, exit_code) {
+        std::process::exit(exit_code);
+    }
+    result
+}
+
+enum Transport {
+    Batch(PathBuf),
+    Replay(PathBuf),
+    Stdio,
+    TcpConnect(u16),
+    TcpListen(u16),
+    UnixConnect(PathBuf),
+    UnixListen(PathBuf),
+}
+
+#[cfg(unix)]
+mod unix_socket {
+    use socket2::{Domain, SockAddr, Socket, Type};
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::net::UnixStream;
+    use std::path::Path;
+    use tokio::net::UnixListener;
+
+    // Creates a listening socket accessible only to the current user.
+    pub fn bind(path: &Path) -> io::Result<UnixListener> {
+        // Remove a stale socket left over from a previous run, but don't clobber anything else.
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if metadata.file_type().is_socket() {
+                if UnixStream::connect(path).is_ok() {
+                    let message = format!("{:?} is in use by another process", path);
+                    return Err(io::Error::new(io::ErrorKind::AddrInUse, message));
+                }
+                fs::remove_file(path)?;
+            }
+        }
+        // Restrict permissions before the socket starts listening, so that other users never get a chance to connect.
+        let socket = Socket::new(Domain::UNIX, Type::STREAM, None)?;
+        socket.bind(&SockAddr::unix(path)?)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        socket.listen(128)?;
+        socket.set_nonblocking(true)?;
+        UnixListener::from_std(socket.into())
+    }
+}
+
+// Waits until the number of running sessions drops below the limit.
+async fn acquire_session_slot(session_slots: &Arc<Semaphore>) -> OwnedSemaphorePermit {
+    match session_slots.clone().try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => {
+            info!("Session limit reached, waiting for a session to end.");
+            session_slots.clone().acquire_owned().await.unwrap()
+        }
+    }
+}
+
+// Runs the session on a dedicated thread, so that it may proceed concurrently with other sessions.
+// The session slot is released when the session ends.
+fn spawn_debug_session(
+    framed_stream: Box<dyn DAPChannel>,
+    session_slot: OwnedSemaphorePermit,
+    adapter_settings: &AdapterSettings,
+    python_interface: &Option<Arc<python::PythonInterface>>,
+) {
+    let adapter_settings = adapter_settings.clone();
+    let python_interface = python_interface.clone();
+    let runtime = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        runtime.block_on(run_debug_session(framed_stream, &adapter_settings, &python_interface));
+        drop(session_slot);
+    });
 }
 
 async fn run_debug_session(
@@ -0,0 +1,460 @@
+// Support for Visual Studio Natvis type visualizers.
+// This module handles parsing of .natvis files and matching of type names; evaluation of the visualizers
+// is done by the debug session.
+
+use crate::prelude::*;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+mod parser;
+
+pub struct TypeVisualizer {
+    pub names: Vec<String>, // The type name pattern, followed by alternative names.
+    pub display_strings: Vec<DisplayString>,
+    pub expand: Option<Expand>,
+}
+
+pub struct DisplayString {
+    pub condition: Option<String>,
+    pub optional: bool,
+    pub parts: Vec<DisplayPart>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DisplayPart {
+    Text(String),
+    Expr(String), // Expression, possibly followed by a format specifier.
+}
+
+pub struct Expand {
+    pub hide_raw_view: bool,
+    pub items: Vec<ExpandItem>,
+}
+
+pub struct ExpandItem {
+    pub condition: Option<String>,
+    pub optional: bool,
+    pub kind: ExpandItemKind,
+}
+
+// An expression that only applies if its condition evaluates to true.
+#[derive(Debug, Clone)]
+pub struct CondExpr {
+    pub condition: Option<String>,
+    pub expr: String,
+}
+
+pub enum ExpandItemKind {
+    Item {
+        name: String,
+        expr: String,
+    },
+    ArrayItems {
+        size: Vec<CondExpr>,
+        value_pointer: Vec<CondExpr>,
+    },
+    IndexListItems {
+        size: Vec<CondExpr>,
+        value_node: Vec<CondExpr>,
+    },
+    LinkedListItems {
+        size: Option<String>,
+        head_pointer: String,
+        next_pointer: String,
+        value_node: CondExpr,
+    },
+    TreeItems {
+        size: Option<String>,
+        head_pointer: String,
+        left_pointer: String,
+        right_pointer: String,
+        value_node: CondExpr,
+    },
+    ExpandedItem {
+        expr: String,
+    },
+}
+
+pub struct Natvis {
+    visualizers: Vec<Rc<TypeVisualizer>>,
+    lookup_cache: RefCell<HashMap<String, Option<(Rc<TypeVisualizer>, Rc<Vec<String>>)>>>,
+}
+
+impl Natvis {
+    pub fn new() -> Self {
+        Natvis {
+            visualizers: Vec::new(),
+            lookup_cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.visualizers.is_empty()
+    }
+
+    // Load visualizers from a .natvis file.  Returns the number of visualizers loaded.
+    pub fn load_file(&mut self, path: &Path) -> Result<usize, Error> {
+        let text = fs::read_to_string(path)?;
+        let visualizers = parser::parse_natvis(&text)?;
+        let count = visualizers.len();
+        self.visualizers.extend(visualizers.into_iter().map(Rc::new));
+        self.lookup_cache.borrow_mut().clear();
+        Ok(count)
+    }
+
+    // Find visualizer for the specified type.  Also returns values of the template parameters ($T1, $T2, ...).
+    pub fn find_visualizer(&self, type_name: &str) -> Option<(Rc<TypeVisualizer>, Rc<Vec<String>>)> {
+        if let Some(result) = self.lookup_cache.borrow().get(type_name) {
+            return result.clone();
+        }
+        let normalized = normalize_type_name(type_name);
+        let mut result = None;
+        'outer: for visualizer in &self.visualizers {
+            for pattern in &visualizer.names {
+                let mut captures = Vec::new();
+                if match_type_name(&normalize_type_name(pattern), &normalized, &mut captures) {
+                    let tparams = captures.iter().flat_map(|c| split_template_args(c)).collect();
+                    result = Some((visualizer.clone(), Rc::new(tparams)));
+                    break 'outer;
+                }
+            }
+        }
+        self.lookup_cache.borrow_mut().insert(type_name.into(), result.clone());
+        result
+    }
+}
+
+// Removes insignificant whitespace, e.g. `std::vector<int, std::allocator<int> >` -> `std::vector<int,std::allocator<int>>`.
+fn normalize_type_name(name: &str) -> String {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_';
+    let mut result = String::with_capacity(name.len());
+    let mut pending_space = false;
+    for c in name.trim().chars() {
+        if c.is_whitespace() {
+            pending_space = true;
+        } else {
+            if pending_space && is_ident_char(c) && result.ends_with(is_ident_char) {
+                result.push(' ');
+            }
+            pending_space = false;
+            result.push(c);
+        }
+    }
+    result
+}
+
+// Matches type name against a pattern, where each `*` matches a sequence of template arguments.
+fn match_type_name(pattern: &str, name: &str, captures: &mut Vec<String>) -> bool {
+    match pattern.find('*') {
+        None => pattern == name,
+        Some(pos) => {
+            if !name.starts_with(&pattern[..pos]) {
+                return false;
+            }
+            let rest_pattern = &pattern[pos + 1..];
+            let name = &name[pos..];
+            let mut depth = 0;
+            for (i, c) in name.char_indices() {
+                if depth == 0 && i > 0 {
+                    captures.push(name[..i].into());
+                    if match_type_name(rest_pattern, &name[i..], captures) {
+                        return true;
+                    }
+                    captures.pop();
+                }
+                match c {
+                    '<' | '(' | '[' => depth += 1,
+                    '>' | ')' | ']' => {
+                        depth -= 1;
+                        if depth < 0 {
+                            return false;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            if depth == 0 && !name.is_empty() && rest_pattern.is_empty() {
+                captures.push(name.into());
+                return true;
+            }
+            false
+        }
+    }
+}
+
+// Splits comma-separated template arguments, taking nesting into account.
+fn split_template_args(args: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                result.push(args[start..i].trim().into());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(args[start..].trim().into());
+    result
+}
+
+// Substitutes template parameters ($T1, $T2, ...) and the current index ($i) into a Natvis expression.
+pub fn substitute_params(expr: &str, tparams: &[String], index: Option<u64>) -> String {
+    let mut result = String::with_capacity(expr.len());
+    let mut rest = expr;
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        let tail = &rest[pos + 1..];
+        let ident_len = tail.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(tail.len());
+        let ident = &tail[..ident_len];
+        let replacement = if ident == "i" {
+            index.map(|i| i.to_string())
+        } else if let Some(n) = ident.strip_prefix('T').and_then(|n| n.parse::<usize>().ok()) {
+            n.checked_sub(1).and_then(|n| tparams.get(n)).cloned()
+        } else {
+            None
+        };
+        match replacement {
+            Some(replacement) => result.push_str(&replacement),
+            None => {
+                result.push('$');
+                result.push_str(ident);
+            }
+        }
+        rest = &tail[ident_len..];
+    }
+    result.push_str(rest);
+    result
+}
+
+// Removes explicit references to the context object, i.e. `this->foo` and `(*this).foo`, since its members
+// are accessible directly.  String and character literals are left intact.
+pub fn strip_this(expr: &str) -> String {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_';
+    let mut result = String::with_capacity(expr.len());
+    let mut in_quotes = None;
+    let mut escaped = false;
+    let mut prev = None;
+    let mut rest = expr;
+    while let Some(c) = rest.chars().next() {
+        if in_quotes.is_none() {
+            let tail =
+                if prev.map_or(true, |p| !is_ident_char(p)) { match_tokens(rest, &["this", "->"]) } else { None };
+            let tail = tail.or_else(|| match prev {
+                Some(p) if is_ident_char(p) || p == ')' || p == ']' => None,
+                _ => match_tokens(rest, &["(", "*", "this", ")", "."]),
+            });
+            if let Some(tail) = tail {
+                rest = tail;
+                continue;
+            }
+        }
+        match in_quotes {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => in_quotes = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => in_quotes = Some(c),
+            None => {}
+        }
+        result.push(c);
+        prev = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    result
+}
+
+// If `text` starts with the given sequence of tokens (optionally separated by whitespace), returns the remainder.
+fn match_tokens<'a>(text: &'a str, tokens: &[&str]) -> Option<&'a str> {
+    let mut rest = text;
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            rest = rest.trim_start();
+        }
+        rest = rest.strip_prefix(token)?;
+    }
+    Some(rest.trim_start())
+}
+
+// Splits off the format specifier, if any, e.g. `_Mypair._Myval2._Myfirst,[size]na` -> (`_Mypair._Myval2._Myfirst`, `[size]na`).
+pub fn split_format_spec(expr: &str) -> (&str, Option<&str>) {
+    // Find the last top-level comma
+    let mut depth = 0;
+    let mut in_quotes = None;
+    let mut comma = None;
+    for (i, c) in expr.char_indices() {
+        match (c, in_quotes) {
+            ('"' | '\'', None) => in_quotes = Some(c),
+            (_, Some(q)) if c == q => in_quotes = None,
+            (_, Some(_)) => {}
+            ('(' | '[' | '{', None) => depth += 1,
+            (')' | ']' | '}', None) => depth -= 1,
+            (',', None) if depth == 0 => comma = Some(i),
+            _ => {}
+        }
+    }
+    match comma {
+        Some(comma) => (expr[..comma].trim_end(), Some(&expr[comma + 1..])),
+        None => (expr, None),
+    }
+}
+
+// Splits off the array size, which Natvis allows to be an expression, e.g. `[size]na` -> (`size`, `na`),
+// or a bare number, e.g. `5` -> (`5`, ``).
+pub fn split_array_count(spec: &str) -> (Option<&str>, &str) {
+    if let Some(rest) = spec.strip_prefix('[') {
+        match rest.find(']') {
+            Some(end) => (Some(rest[..end].trim()), &rest[end + 1..]),
+            None => (None, spec),
+        }
+    } else {
+        let digits = spec.find(|c: char| !c.is_ascii_digit()).unwrap_or(spec.len());
+        match digits {
+            0 => (None, spec),
+            _ => (Some(&spec[..digits]), &spec[digits..]),
+        }
+    }
+}
+
+// Parses DisplayString contents, e.g. `size={_Mysize}`
+pub fn parse_display_string(text: &str) -> Result<Vec<DisplayPart>, Error> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' if matches!(chars.peek(), Some((_, '{'))) => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if matches!(chars.peek(), Some((_, '}'))) => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut depth = 1;
+                let mut end = None;
+                for (j, c) in chars.by_ref() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                end = Some(j);
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                match end {
+                    Some(end) => {
+                        if !literal.is_empty() {
+                            parts.push(DisplayPart::Text(std::mem::take(&mut literal)));
+                        }
+                        parts.push(DisplayPart::Expr(text[i + 1..end].trim().into()));
+                    }
+                    None => bail!(format!("Unmatched '{{' in \"{}\"", text)),
+                }
+            }
+            _ => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(DisplayPart::Text(literal));
+    }
+    Ok(parts)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_match_type_name() {
+    fn check(pattern: &str, name: &str) -> Option<Vec<String>> {
+        let mut captures = Vec::new();
+        match match_type_name(&normalize_type_name(pattern), &normalize_type_name(name), &mut captures) {
+            true => Some(captures.iter().flat_map(|c| split_template_args(c)).collect()),
+            false => None,
+        }
+    }
+
+    assert_eq!(check("Foo", "Foo"), Some(vec![]));
+    assert_eq!(check("Foo", "Foobar"), None);
+    assert_eq!(
+        check("std::vector<*>", "std::vector<int, std::allocator<int> >"),
+        Some(vec!["int".into(), "std::allocator<int>".into()])
+    );
+    assert_eq!(
+        check("Map<*,*>", "Map<int, Pair<char, long> >"),
+        Some(vec!["int".into(), "Pair<char,long>".into()])
+    );
+    assert_eq!(check("Ptr<*>", "Ptr<unsigned int>"), Some(vec!["unsigned int".into()]));
+    assert_eq!(check("Ptr<*>", "Ptr<>"), None);
+    assert_eq!(check("Ptr<*>", "Other<int>"), None);
+}
+
+#[test]
+fn test_substitute_params() {
+    let tparams = vec!["int".to_owned(), "char".to_owned()];
+    assert_eq!(substitute_params("($T1*)ptr + $i", &tparams, Some(5)), "(int*)ptr + 5");
+    assert_eq!(
+        substitute_params("sizeof($T2) * $index", &tparams, Some(5)),
+        "sizeof(char) * $index"
+    );
+    assert_eq!(substitute_params("$T3", &tparams, None), "$T3");
+}
+
+#[test]
+fn test_strip_this() {
+    assert_eq!(strip_this("this->size"), "size");
+    assert_eq!(strip_this("this -> _Mypair._Myval2"), "_Mypair._Myval2");
+    assert_eq!(strip_this("(*this).size"), "size");
+    assert_eq!(strip_this("this->end - this->begin"), "end - begin");
+    assert_eq!(strip_this("x + (*this).y"), "x + y");
+    assert_eq!(strip_this("(int*)this->ptr"), "(int*)ptr");
+    assert_eq!(strip_this("other_this->size"), "other_this->size");
+    assert_eq!(strip_this("mythis->size + this2->size"), "mythis->size + this2->size");
+    assert_eq!(strip_this("func(*this).size"), "func(*this).size");
+    assert_eq!(
+        strip_this("strcmp(name, \"this->\") == 0"),
+        "strcmp(name, \"this->\") == 0"
+    );
+    assert_eq!(strip_this("c == '\\'' && this->x"), "c == '\\'' && x");
+    assert_eq!(strip_this("this == nullptr"), "this == nullptr");
+}
+
+#[test]
+fn test_split_format_spec() {
+    assert_eq!(split_format_spec("foo"), ("foo", None));
+    assert_eq!(split_format_spec("foo,x"), ("foo", Some("x")));
+    assert_eq!(split_format_spec("ptr,[size]na"), ("ptr", Some("[size]na")));
+    assert_eq!(split_format_spec("f(a, b)"), ("f(a, b)", None));
+    assert_eq!(split_format_spec("f(a, b),[n]"), ("f(a, b)", Some("[n]")));
+    assert_eq!(split_format_spec("c == ','"), ("c == ','", None));
+
+    assert_eq!(split_array_count("[size]na"), (Some("size"), "na"));
+    assert_eq!(split_array_count("5"), (Some("5"), ""));
+    assert_eq!(split_array_count("su"), (None, "su"));
+    assert_eq!(split_array_count("[size"), (None, "[size"));
+}
+
+#[test]
+fn test_parse_display_string() {
+    assert_eq!(
+        parse_display_string("size={_Mysize,d} {{}}").unwrap(),
+        vec![
+            DisplayPart::Text("size=".into()),
+            DisplayPart::Expr("_Mysize,d".into()),
+            DisplayPart::Text(" {}".into())
+        ]
+    );
+    assert!(parse_display_string("{unterminated").is_err());
+}
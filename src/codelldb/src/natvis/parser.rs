@@ -0,0 +1,202 @@
+use super::*;
+
+use roxmltree::{Document, Node};
+
+pub fn parse_natvis(text: &str) -> Result<Vec<TypeVisualizer>, Error> {
+    let doc = Document::parse(text)?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "AutoVisualizer" {
+        bail!(node_error(root, "Expected <AutoVisualizer> as the root element"));
+    }
+    let mut visualizers = Vec::new();
+    for node in elements(root) {
+        if node.tag_name().name() == "Type" {
+            visualizers.push(parse_type(node)?);
+        }
+    }
+    Ok(visualizers)
+}
+
+fn parse_type(node: Node) -> Result<TypeVisualizer, Error> {
+    let mut names = vec![required_attr(node, "Name")?.to_owned()];
+    let mut display_strings = Vec::new();
+    let mut expand = None;
+    for child in elements(node) {
+        match child.tag_name().name() {
+            "AlternativeType" => names.push(required_attr(child, "Name")?.to_owned()),
+            "DisplayString" => {
+                let parts = parse_display_string(&text(child)).map_err(|err| node_error(child, &err.to_string()))?;
+                display_strings.push(DisplayString {
+                    condition: condition(child),
+                    optional: optional(child),
+                    parts,
+                })
+            }
+            "Expand" => expand = Some(parse_expand(child)?),
+            _ => {}
+        }
+    }
+    Ok(TypeVisualizer {
+        names,
+        display_strings,
+        expand,
+    })
+}
+
+fn parse_expand(node: Node) -> Result<Expand, Error> {
+    let mut items = Vec::new();
+    for child in elements(node) {
+        let kind = match child.tag_name().name() {
+            "Item" => ExpandItemKind::Item {
+                name: required_attr(child, "Name")?.to_owned(),
+                expr: required_text(child)?,
+            },
+            "ArrayItems" => ExpandItemKind::ArrayItems {
+                size: required_cond_exprs(child, "Size")?,
+                value_pointer: required_cond_exprs(child, "ValuePointer")?,
+            },
+            "IndexListItems" => ExpandItemKind::IndexListItems {
+                size: required_cond_exprs(child, "Size")?,
+                value_node: required_cond_exprs(child, "ValueNode")?,
+            },
+            "LinkedListItems" => ExpandItemKind::LinkedListItems {
+                size: child_text(child, "Size"),
+                head_pointer: required_child_text(child, "HeadPointer")?,
+                next_pointer: required_child_text(child, "NextPointer")?,
+                value_node: required_cond_exprs(child, "ValueNode")?.remove(0),
+            },
+            "TreeItems" => ExpandItemKind::TreeItems {
+                size: child_text(child, "Size"),
+                head_pointer: required_child_text(child, "HeadPointer")?,
+                left_pointer: required_child_text(child, "LeftPointer")?,
+                right_pointer: required_child_text(child, "RightPointer")?,
+                value_node: required_cond_exprs(child, "ValueNode")?.remove(0),
+            },
+            "ExpandedItem" => ExpandItemKind::ExpandedItem {
+                expr: required_text(child)?,
+            },
+            _ => continue,
+        };
+        items.push(ExpandItem {
+            condition: condition(child),
+            optional: optional(child),
+            kind,
+        });
+    }
+    Ok(Expand {
+        hide_raw_view: node.attribute("HideRawView") == Some("true"),
+        items,
+    })
+}
+
+// Child elements, excluding ones that are restricted to a specific view via IncludeView.
+fn elements<'a, 'input>(node: Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(|n| n.is_element() && !n.has_attribute("IncludeView"))
+}
+
+fn text(node: Node) -> String {
+    node.children().filter_map(|n| n.text()).collect::<String>().trim().to_owned()
+}
+
+fn condition(node: Node) -> Option<String> {
+    node.attribute("Condition").map(|s| s.trim().to_owned())
+}
+
+fn optional(node: Node) -> bool {
+    node.attribute("Optional") == Some("true")
+}
+
+fn required_attr<'a>(node: Node<'a, '_>, name: &str) -> Result<&'a str, Error> {
+    node.attribute(name)
+        .ok_or_else(|| node_error(node, &format!("Missing attribute \"{}\"", name)))
+}
+
+fn required_text(node: Node) -> Result<String, Error> {
+    let text = text(node);
+    if text.is_empty() {
+        bail!(node_error(node, "Expected an expression"));
+    }
+    Ok(text)
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    elements(node).find(|n| n.tag_name().name() == name).map(text)
+}
+
+fn required_child_text(node: Node, name: &str) -> Result<String, Error> {
+    match elements(node).find(|n| n.tag_name().name() == name) {
+        Some(child) => required_text(child),
+        None => bail!(node_error(node, &format!("Missing <{}>", name))),
+    }
+}
+
+// Collect all child elements with the given name, each of which may have a condition.
+fn required_cond_exprs(node: Node, name: &str) -> Result<Vec<CondExpr>, Error> {
+    let mut result = Vec::new();
+    for child in elements(node).filter(|n| n.tag_name().name() == name) {
+        result.push(CondExpr {
+            condition: condition(child),
+            expr: required_text(child)?,
+        });
+    }
+    if result.is_empty() {
+        bail!(node_error(node, &format!("Missing <{}>", name)));
+    }
+    Ok(result)
+}
+
+fn node_error(node: Node, message: &str) -> Error {
+    let pos = node.document().text_pos_at(node.range().start);
+    format!("{} at {}:{}", message, pos.row, pos.col).into()
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_parse_natvis() {
+    let text = r#"<?xml version="1.0" encoding="utf-8"?>
+<AutoVisualizer xmlns="http://schemas.microsoft.com/vstudio/debugger/natvis/2010">
+  <Type Name="Vec&lt;*&gt;">
+    <AlternativeType Name="SmallVec&lt;*&gt;" />
+    <DisplayString Condition="len == 0">empty</DisplayString>
+    <DisplayString>{{ len={len} }}</DisplayString>
+    <Expand HideRawView="true">
+      <Item Name="[len]" ExcludeView="simple">len</Item>
+      <Item Name="[view]" IncludeView="other">len</Item>
+      <ArrayItems>
+        <Size>len</Size>
+        <ValuePointer>($T1*)data</ValuePointer>
+      </ArrayItems>
+    </Expand>
+  </Type>
+  <Type Name="List">
+    <Expand>
+      <LinkedListItems>
+        <HeadPointer>head</HeadPointer>
+        <NextPointer>next</NextPointer>
+        <ValueNode>value</ValueNode>
+      </LinkedListItems>
+    </Expand>
+  </Type>
+</AutoVisualizer>"#;
+    let visualizers = parse_natvis(text).unwrap();
+    assert_eq!(visualizers.len(), 2);
+    assert_eq!(visualizers[0].names, vec!["Vec<*>", "SmallVec<*>"]);
+    assert_eq!(visualizers[0].display_strings.len(), 2);
+    assert_eq!(visualizers[0].display_strings[0].condition.as_deref(), Some("len == 0"));
+    let expand = visualizers[0].expand.as_ref().unwrap();
+    assert!(expand.hide_raw_view);
+    assert_eq!(expand.items.len(), 2);
+    assert!(
+        matches!(&expand.items[1].kind, ExpandItemKind::ArrayItems { value_pointer, .. }
+        if value_pointer[0].expr == "($T1*)data")
+    );
+    assert!(matches!(
+        &visualizers[1].expand.as_ref().unwrap().items[0].kind,
+        ExpandItemKind::LinkedListItems { size: None, .. }
+    ));
+
+    let err = parse_natvis(r#"<AutoVisualizer><Type><DisplayString>x</DisplayString></Type></AutoVisualizer>"#);
+    assert!(err.err().unwrap().to_string().contains("Missing attribute \"Name\" at 1:17"));
+    assert!(parse_natvis("<AutoVisualizer><Type>").is_err());
+}
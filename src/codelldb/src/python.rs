@@ -49,6 +49,7 @@ pub enum PythonEvent {
         output: String,
         category: String,
     },
+    FrameFilterRegistered,
 }
 
 // Interface through which the rest of CodeLLDB interacts with Python, via C ABI.
@@ -84,6 +85,11 @@ struct PythonCalls {
         exec_context: SBExecutionContext,
         eval_context: c_int,
     ) -> bool,
+    filter_frame_name: unsafe extern "C" fn(
+        exec_context: SBExecutionContext,
+        name_callback: unsafe extern "C" fn(context: *mut c_void, name: *const c_char, name_len: usize),
+        context: *mut c_void,
+    ) -> bool,
 }
 
 // Initialize Python interface.
@@ -132,7 +138,7 @@ pub fn initialize(debugger: &SBDebugger, adapter_dir: &Path) -> Result<Arc<Pytho
         pointers: *const *const c_void,
         pointers_len: usize,
     ) {
-        if pointers_len != 8 {
+        if pointers_len != 9 {
             error!("Invalid number of pointers passed to init_callback: {}", pointers_len);
             return;
         }
@@ -147,6 +153,7 @@ pub fn initialize(debugger: &SBDebugger, adapter_dir: &Path) -> Result<Arc<Pytho
             compile_code: mem::transmute(pointers[5]),
             evaluate_as_sbvalue: mem::transmute(pointers[6]),
             evaluate_as_bool: mem::transmute(pointers[7]),
+            filter_frame_name: mem::transmute(pointers[8]),
         };
         (*interface_ptr).py = Initialized(py_calls);
     }
@@ -321,6 +328,26 @@ impl PythonSession {
         Ok(())
     }
 
+    // Apply frame filters registered by scripts to the name of the frame.
+    // Returns None if the name was not changed.
+    pub fn filter_frame_name(&self, frame: &SBFrame) -> Result<Option<String>, Error> {
+        unsafe extern "C" fn name_callback(context: *mut c_void, name: *const c_char, name_len: usize) {
+            let name = std::slice::from_raw_parts(name as *const u8, name_len);
+            *(context as *mut Option<String>) = Some(String::from_utf8_lossy(name).into_owned());
+        }
+
+        let context = SBExecutionContext::from_frame(frame);
+        let mut name: Option<String> = None;
+        let succeeded = unsafe {
+            (self.interface.py.filter_frame_name)(context, name_callback, &mut name as *mut _ as *mut c_void)
+        };
+        if succeeded {
+            Ok(name)
+        } else {
+            Err("Frame filter failed".into())
+        }
+    }
+
     // Load the language support module
     pub fn init_lang_support(&self) -> Result<(), Error> {
         let lang_support = self.interface.adapter_dir.parent().unwrap().join("lang_support");
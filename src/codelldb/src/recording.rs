@@ -0,0 +1,428 @@
+use crate::prelude::*;
+
+use crate::dap_session::in_memory_channel;
+use adapter_protocol::*;
+use futures::channel::mpsc;
+use futures::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// How long to wait for the adapter to produce a message seen in the recording.
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Direction {
+    // Client to adapter.
+    In,
+    // Adapter to client.
+    Out,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    // Seconds since the start of recording.
+    time: f64,
+    session: u32,
+    direction: Direction,
+    message: &'a RawValue,
+}
+
+#[derive(Deserialize)]
+struct RecordedMessage {
+    session: u32,
+    direction: Direction,
+    message: Value,
+}
+
+/// Writes DAP messages of all sessions into a JSONL file.
+pub struct Recorder {
+    file: Mutex<LineWriter<File>>,
+    start: Instant,
+    next_session: AtomicU32,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Arc<Recorder>, Error> {
+        let file = File::create(path)?;
+        Ok(Arc::new(Recorder {
+            file: Mutex::new(LineWriter::new(file)),
+            start: Instant::now(),
+            next_session: AtomicU32::new(1),
+        }))
+    }
+
+    pub fn new_session(self: &Arc<Self>) -> SessionRecorder {
+        SessionRecorder {
+            recorder: self.clone(),
+            session: self.next_session.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct SessionRecorder {
+    recorder: Arc<Recorder>,
+    session: u32,
+}
+
+impl SessionRecorder {
+    pub fn record_inbound(&self, message_bytes: &[u8]) {
+        self.record(Direction::In, message_bytes);
+    }
+
+    pub fn record_outbound(&self, message_bytes: &[u8]) {
+        self.record(Direction::Out, message_bytes);
+    }
+
+    fn record(&self, direction: Direction, message_bytes: &[u8]) {
+        let message = match serde_json::from_slice::<&RawValue>(message_bytes) {
+            Ok(message) => message,
+            Err(err) => {
+                error!("Not recording malformed message: {}", err);
+                return;
+            }
+        };
+        let record = Record {
+            time: self.recorder.start.elapsed().as_secs_f64(),
+            session: self.session,
+            direction,
+            message,
+        };
+        let mut file = self.recorder.file.lock().unwrap();
+        log_errors!(serde_json::to_writer(&mut *file, &record));
+        log_errors!(file.write_all(b"\n"));
+    }
+}
+
+/// Feeds client messages from a recording to fresh debug sessions, and reports where the adapter's messages
+/// diverge from the recorded ones.  Returns the number of divergences.
+pub async fn replay(
+    path: &Path,
+    adapter_settings: &AdapterSettings,
+    python_interface: &Option<Arc<crate::python::PythonInterface>>,
+) -> Result<usize, Error> {
+    let text = std::fs::read_to_string(path)?;
+    let mut sessions: BTreeMap<u32, Vec<RecordedMessage>> = BTreeMap::new();
+    for (line_no, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: RecordedMessage = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(err) => bail!(format!("{}:{}: {}", path.display(), line_no + 1, err)),
+        };
+        sessions.entry(record.session).or_default().push(record);
+    }
+
+    let mut divergences = 0;
+    for (session, records) in sessions {
+        info!("Replaying session {} ({} messages)", session, records.len());
+        let (channel, client_sender, client_receiver) = in_memory_channel();
+        let session_fut = crate::run_debug_session(channel, adapter_settings, python_interface);
+        let client_fut = replay_client(records, client_sender, client_receiver);
+        let (_, session_divergences) = future::join(session_fut, client_fut).await;
+        divergences += session_divergences;
+    }
+    info!("Replay finished with {} divergence(s)", divergences);
+    Ok(divergences)
+}
+
+// Plays the part of the DAP client.
+async fn replay_client(
+    records: Vec<RecordedMessage>,
+    sender: mpsc::UnboundedSender<ProtocolMessage>,
+    mut receiver: mpsc::UnboundedReceiver<ProtocolMessage>,
+) -> usize {
+    let mut divergences = 0;
+    // Messages received from the adapter, but not yet matched to the recording.
+    let mut received: Vec<Value> = Vec::new();
+    let mut ids = IdMap::default();
+    for (index, record) in records.into_iter().enumerate() {
+        match record.direction {
+            Direction::In => match serde_json::from_value::<ProtocolMessage>(ids.translate(record.message)) {
+                Ok(message) => {
+                    if sender.unbounded_send(message).is_err() {
+                        warn!("#{}: the adapter has closed the connection", index + 1);
+                        divergences += 1;
+                        break;
+                    }
+                }
+                Err(err) => error!("#{}: could not deserialize message: {}", index + 1, err),
+            },
+            Direction::Out => {
+                // Messages sent by the adapter concurrently may arrive in a different order,
+                // so look for a message of the same kind, rather than comparing them in sequence.
+                let kind = message_kind(&record.message);
+                let actual = loop {
+                    if let Some(pos) = received.iter().position(|m| message_kind(m) == kind) {
+                        break Some(received.remove(pos));
+                    }
+                    match tokio::time::timeout(REPLAY_TIMEOUT, receiver.next()).await {
+                        Ok(Some(message)) => received.push(serde_json::to_value(&message).unwrap()),
+                        Ok(None) | Err(_) => break None,
+                    }
+                };
+                match actual {
+                    Some(actual) => {
+                        ids.learn(&record.message, &actual);
+                        let expected = ids.translate(record.message);
+                        if let Some(difference) = first_difference(&expected, &actual) {
+                            warn!("#{}: {}: {}", index + 1, kind, difference);
+                            divergences += 1;
+                        }
+                    }
+                    None => {
+                        warn!("#{}: {}: not received", index + 1, kind);
+                        divergences += 1;
+                    }
+                }
+            }
+        }
+    }
+    drop(sender); // Ends the session.
+    while let Ok(Some(message)) = tokio::time::timeout(REPLAY_TIMEOUT, receiver.next()).await {
+        received.push(serde_json::to_value(&message).unwrap());
+    }
+    for message in received {
+        warn!("{}: not in the recording", message_kind(&message));
+        divergences += 1;
+    }
+    divergences
+}
+
+// Thread ids, variable references, addresses, etc. are not stable across sessions, so the ones found in the
+// recorded messages are mapped to their counterparts in the messages produced by the adapter during replay.
+#[derive(Default)]
+struct IdMap {
+    ids: HashMap<(&'static str, Value), Value>,
+}
+
+impl IdMap {
+    // Associates ids in the recorded message with the ids in the same positions of the actual message.
+    fn learn(&mut self, expected: &Value, actual: &Value) {
+        self.learn_impl(expected, actual, None);
+    }
+
+    fn learn_impl(&mut self, expected: &Value, actual: &Value, parent: Option<&str>) {
+        match (expected, actual) {
+            (Value::Object(expected), Value::Object(actual)) => {
+                for (key, expected_value) in expected {
+                    if let Some(actual_value) = actual.get(key) {
+                        match id_kind(parent, key) {
+                            Some(kind) if !expected_value.is_object() && !expected_value.is_array() => {
+                                self.ids.entry((kind, expected_value.clone())).or_insert_with(|| actual_value.clone());
+                            }
+                            _ => self.learn_impl(expected_value, actual_value, Some(key)),
+                        }
+                    }
+                }
+            }
+            (Value::Array(expected), Value::Array(actual)) => {
+                for (expected_item, actual_item) in expected.iter().zip(actual.iter()) {
+                    self.learn_impl(expected_item, actual_item, parent);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Replaces recorded ids in the message with the corresponding ids seen during replay.
+    fn translate(&self, mut message: Value) -> Value {
+        self.translate_impl(&mut message, None);
+        message
+    }
+
+    fn translate_impl(&self, value: &mut Value, parent: Option<&str>) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object.iter_mut() {
+                    match id_kind(parent, key) {
+                        Some(kind) if !value.is_object() && !value.is_array() => {
+                            if let Some(actual) = self.ids.get(&(kind, value.clone())) {
+                                *value = actual.clone();
+                            }
+                        }
+                        _ => self.translate_impl(value, Some(key)),
+                    }
+                }
+            }
+            Value::Array(array) => {
+                for item in array {
+                    self.translate_impl(item, parent);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Determines what kind of id, if any, is stored in the field with the given name.
+// `parent` is the name of the field containing the object.
+fn id_kind(parent: Option<&str>, key: &str) -> Option<&'static str> {
+    match (parent, key) {
+        (_, "threadId") | (Some("threads" | "thread"), "id") => Some("thread"),
+        (_, "frameId") | (Some("stackFrames"), "id") => Some("frame"),
+        (Some("breakpoints" | "breakpoint"), "id") => Some("breakpoint"),
+        (_, "variablesReference") => Some("variables"),
+        (_, "sourceReference") => Some("source"),
+        (_, "memoryReference" | "instructionPointerReference" | "instructionReference") => Some("address"),
+        _ => None,
+    }
+}
+
+// Describes the message in a way that allows matching the adapter's messages to the recorded ones.
+fn message_kind(message: &Value) -> String {
+    let field = |name| message.get(name).and_then(|v| v.as_str()).unwrap_or("?");
+    match field("type") {
+        "response" => format!(
+            "response to '{}' (request_seq={})",
+            field("command"),
+            message.get("request_seq").unwrap_or(&Value::Null)
+        ),
+        "event" => format!("event '{}'", field("event")),
+        "request" => format!("request '{}'", field("command")),
+        other => format!("message of type '{}'", other),
+    }
+}
+
+// Compares two messages, ignoring their sequence numbers, and describes the first difference.
+fn first_difference(expected: &Value, actual: &Value) -> Option<String> {
+    fn compare(expected: &Value, actual: &Value, path: &mut String) -> Option<String> {
+        match (expected, actual) {
+            (Value::Object(expected), Value::Object(actual)) => {
+                for (key, expected_value) in expected {
+                    if path.is_empty() && key == "seq" {
+                        continue;
+                    }
+                    let len = path.len();
+                    path.push('/');
+                    path.push_str(key);
+                    let result = match actual.get(key) {
+                        Some(actual_value) => compare(expected_value, actual_value, path),
+                        None => Some(format!("{}: missing", path)),
+                    };
+                    path.truncate(len);
+                    if result.is_some() {
+                        return result;
+                    }
+                }
+                for key in actual.keys() {
+                    if !expected.contains_key(key) {
+                        return Some(format!("{}/{}: unexpected", path, key));
+                    }
+                }
+                None
+            }
+            (Value::Array(expected), Value::Array(actual)) => {
+                for (index, (expected_item, actual_item)) in expected.iter().zip(actual.iter()).enumerate() {
+                    let len = path.len();
+                    path.push_str(&format!("/{}", index));
+                    let result = compare(expected_item, actual_item, path);
+                    path.truncate(len);
+                    if result.is_some() {
+                        return result;
+                    }
+                }
+                if expected.len() != actual.len() {
+                    return Some(format!(
+                        "{}: expected {} elements, got {}",
+                        path,
+                        expected.len(),
+                        actual.len()
+                    ));
+                }
+                None
+            }
+            _ if expected == actual => None,
+            _ => Some(format!("{}: expected {}, got {}", path, expected, actual)),
+        }
+    }
+    compare(expected, actual, &mut String::new())
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_first_difference() {
+    use serde_json::json;
+
+    let expected = json!({"seq": 5, "type": "response", "body": {"threads": [{"id": 1}, {"id": 2}]}});
+    assert_eq!(first_difference(&expected, &expected), None);
+
+    let actual = json!({"seq": 7, "type": "response", "body": {"threads": [{"id": 1}, {"id": 2}]}});
+    assert_eq!(first_difference(&expected, &actual), None);
+
+    let actual = json!({"seq": 5, "type": "response", "body": {"threads": [{"id": 1}, {"id": 3}]}});
+    assert_eq!(
+        first_difference(&expected, &actual).unwrap(),
+        "/body/threads/1/id: expected 2, got 3"
+    );
+
+    let actual = json!({"seq": 5, "type": "response", "body": {"threads": [{"id": 1}]}});
+    assert_eq!(
+        first_difference(&expected, &actual).unwrap(),
+        "/body/threads: expected 2 elements, got 1"
+    );
+
+    let actual = json!({"seq": 5, "type": "response", "body": {}});
+    assert_eq!(first_difference(&expected, &actual).unwrap(), "/body/threads: missing");
+
+    let actual = json!({"seq": 5, "type": "response", "body": {"threads": [{"id": 1}, {"id": 2}]}, "x": 0});
+    assert_eq!(first_difference(&expected, &actual).unwrap(), "/x: unexpected");
+}
+
+#[test]
+fn test_message_kind() {
+    use serde_json::json;
+
+    let response = json!({"seq": 5, "type": "response", "request_seq": 3, "command": "threads"});
+    assert_eq!(message_kind(&response), "response to 'threads' (request_seq=3)");
+    let event = json!({"seq": 6, "type": "event", "event": "stopped"});
+    assert_eq!(message_kind(&event), "event 'stopped'");
+}
+
+#[test]
+fn test_id_map() {
+    use serde_json::json;
+
+    let mut ids = IdMap::default();
+    let expected = json!({"body": {"threads": [{"id": 1, "name": "main"}, {"id": 2, "name": "worker"}]}});
+    let actual = json!({"body": {"threads": [{"id": 101, "name": "main"}, {"id": 102, "name": "worker"}]}});
+    ids.learn(&expected, &actual);
+    assert_eq!(ids.translate(expected), actual);
+
+    let expected =
+        json!({"body": {"variables": [{"name": "x", "variablesReference": 1001, "memoryReference": "0x1000"}]}});
+    let actual =
+        json!({"body": {"variables": [{"name": "x", "variablesReference": 2001, "memoryReference": "0x5000"}]}});
+    ids.learn(&expected, &actual);
+
+    // Ids of different kinds do not interfere with one another.
+    let request = json!({"command": "stackTrace", "arguments": {"threadId": 2, "startFrame": 1}});
+    assert_eq!(
+        ids.translate(request),
+        json!({"command": "stackTrace", "arguments": {"threadId": 102, "startFrame": 1}})
+    );
+    let request = json!({"command": "variables", "arguments": {"variablesReference": 1001}});
+    assert_eq!(
+        ids.translate(request),
+        json!({"command": "variables", "arguments": {"variablesReference": 2001}})
+    );
+    let request = json!({"command": "readMemory", "arguments": {"memoryReference": "0x1000", "count": 2}});
+    assert_eq!(
+        ids.translate(request),
+        json!({"command": "readMemory", "arguments": {"memoryReference": "0x5000", "count": 2}})
+    );
+
+    // Unknown ids are passed through unchanged.
+    let request = json!({"command": "variables", "arguments": {"variablesReference": 7}});
+    assert_eq!(ids.translate(request.clone()), request);
+}
@@ -1,12 +1,14 @@
 use crate::prelude::*;
 
+use crate::auth::{one_time_secret, tokens_equal};
 use crate::dap_session::DAPSession;
 use adapter_protocol::*;
+use futures::prelude::*;
+use futures::stream::FuturesUnordered;
 use std::collections::HashMap;
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
 use std::time::Duration;
-use tokio::io::AsyncReadExt;
-use tokio::io::BufReader;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
 
 pub struct Terminal {
@@ -26,8 +28,7 @@ impl Terminal {
         let terminal_fut = async move {
             let listener = TcpListener::bind("127.0.0.1:0").await?;
             let addr = listener.local_addr()?;
-
-            let accept_fut = listener.accept();
+            let auth_token = one_time_secret()?;
 
             let current_exe = std::env::current_exe()?;
             let mut launcher = current_exe.with_file_name("codelldb-launch");
@@ -51,6 +52,7 @@ impl Terminal {
                 args: vec![
                     launcher.to_string_lossy().to_string(),
                     format!("--connect={addr}"),
+                    format!("--auth-token={auth_token}"),
                     "--clear-screen".into(),
                 ],
                 cwd: String::new(),
@@ -62,17 +64,43 @@ impl Terminal {
             let run_in_term = dap_session.send_request(RequestArguments::runInTerminal(req_args));
             tokio::task::spawn_local(run_in_term);
 
-            let (stream, _remote_addr) = accept_fut.await?;
-            let mut reader = BufReader::new(stream);
-            let mut buf = Vec::new();
-            reader.read_to_end(&mut buf).await?;
-
-            let launch_env: LaunchEnvironment = serde_json::from_slice(&buf)?;
-
-            Ok(Terminal {
-                connection: Some(reader.into_inner().into_std()?),
-                terminal_id: launch_env.terminal_id,
-            })
+            // Any local process may connect to the listener, so wait for the one that knows the secret.
+            // Connections are read concurrently, so that one which never sends anything does not block the others.
+            let mut pending = FuturesUnordered::new();
+            loop {
+                tokio::select! {
+                    accepted = listener.accept() => {
+                        let (stream, remote_addr) = accepted?;
+                        pending.push(read_launch_env(stream, remote_addr));
+                    }
+                    Some((mut stream, remote_addr, result)) = pending.next(), if !pending.is_empty() => {
+                        let launch_env = match result {
+                            Ok(launch_env) => launch_env,
+                            Err(err) => {
+                                error!("Rejecting terminal agent connection from {}: {}", remote_addr, err);
+                                continue;
+                            }
+                        };
+                        if !tokens_equal(&auth_token, launch_env.auth_token.as_deref().unwrap_or_default()) {
+                            error!(
+                                "Rejecting terminal agent connection from {}: invalid auth token",
+                                remote_addr
+                            );
+                            let response = LaunchResponse {
+                                success: false,
+                                message: Some("Invalid auth token".into()),
+                            };
+                            log_errors!(stream.write_all(&serde_json::to_vec(&response)?).await);
+                            continue;
+                        }
+
+                        return Ok(Terminal {
+                            connection: Some(stream.into_std()?),
+                            terminal_id: launch_env.terminal_id,
+                        });
+                    }
+                }
+            }
         };
 
         match tokio::time::timeout(Duration::from_secs(10), terminal_fut).await {
@@ -134,6 +162,18 @@ impl Terminal {
     }
 }
 
+async fn read_launch_env(
+    mut stream: tokio::net::TcpStream,
+    remote_addr: SocketAddr,
+) -> (tokio::net::TcpStream, SocketAddr, Result<LaunchEnvironment, Error>) {
+    let mut buf = Vec::new();
+    let result = match stream.read_to_end(&mut buf).await {
+        Ok(_) => serde_json::from_slice(&buf).map_err(Into::into),
+        Err(err) => Err(err.into()),
+    };
+    (stream, remote_addr, result)
+}
+
 impl Drop for Terminal {
     fn drop(&mut self) {
         if let Some(ref connection) = self.connection {
@@ -98,6 +98,7 @@ cpp! {{
 
 mod sbaddress;
 mod sbattachinfo;
+mod sbblock;
 mod sbbreakpoint;
 mod sbbreakpointlocation;
 mod sbbroadcaster;
@@ -142,6 +143,7 @@ mod sbwatchpoint;
 
 pub use sbaddress::*;
 pub use sbattachinfo::*;
+pub use sbblock::*;
 pub use sbbreakpoint::*;
 pub use sbbreakpointlocation::*;
 pub use sbbroadcaster::*;
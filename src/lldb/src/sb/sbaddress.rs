@@ -49,6 +49,12 @@ impl SBAddress {
         })
         .check()
     }
+    pub fn block(&self) -> Option<SBBlock> {
+        cpp!(unsafe [self as "SBAddress*"] -> SBBlock as "SBBlock" {
+            return self->GetBlock();
+        })
+        .check()
+    }
     pub fn module(&self) -> Option<SBModule> {
         cpp!(unsafe [self as "SBAddress*"] -> SBModule as "SBModule" {
             return self->GetModule();
@@ -0,0 +1,59 @@
+use super::*;
+
+cpp_class!(pub unsafe struct SBBlock as "SBBlock");
+
+unsafe impl Send for SBBlock {}
+
+impl SBBlock {
+    pub fn is_inlined(&self) -> bool {
+        cpp!(unsafe [self as "SBBlock*"] -> bool as "bool" {
+            return self->IsInlined();
+        })
+    }
+    pub fn inlined_name(&self) -> &str {
+        let ptr = cpp!(unsafe [self as "SBBlock*"] -> *const c_char as "const char*" {
+            return self->GetInlinedName();
+        });
+        unsafe { get_str(ptr) }
+    }
+    pub fn inlined_call_site_file(&self) -> SBFileSpec {
+        cpp!(unsafe [self as "SBBlock*"] -> SBFileSpec as "SBFileSpec" {
+            return self->GetInlinedCallSiteFile();
+        })
+    }
+    pub fn inlined_call_site_line(&self) -> u32 {
+        cpp!(unsafe [self as "SBBlock*"] -> u32 as "uint32_t" {
+            return self->GetInlinedCallSiteLine();
+        })
+    }
+    pub fn parent(&self) -> Option<SBBlock> {
+        cpp!(unsafe [self as "SBBlock*"] -> SBBlock as "SBBlock" {
+            return self->GetParent();
+        })
+        .check()
+    }
+    pub fn containing_inlined_block(&self) -> Option<SBBlock> {
+        cpp!(unsafe [self as "SBBlock*"] -> SBBlock as "SBBlock" {
+            return self->GetContainingInlinedBlock();
+        })
+        .check()
+    }
+}
+
+impl IsValid for SBBlock {
+    fn is_valid(&self) -> bool {
+        cpp!(unsafe [self as "SBBlock*"] -> bool as "bool" {
+            return self->IsValid();
+        })
+    }
+}
+
+impl fmt::Debug for SBBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        debug_descr(f, |descr| {
+            cpp!(unsafe [self as "SBBlock*", descr as "SBStream*"] -> bool as "bool" {
+                return self->GetDescription(*descr);
+            })
+        })
+    }
+}
@@ -1,15 +1,18 @@
 use super::*;
 use std::collections::HashMap;
 use std::os::raw::c_void;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 cpp_class!(pub unsafe struct SBBreakpoint as "SBBreakpoint");
 
 unsafe impl Send for SBBreakpoint {}
 
+// Breakpoint IDs are only unique within a target, so callbacks are keyed by the ID of the owning debugger as well,
+// to keep concurrent debug sessions apart.
+// Each callback is individually locked, so that the map itself need not stay locked while a callback is running.
+type BreakpointCallback = Arc<Mutex<dyn FnMut(&SBProcess, &SBThread, &SBBreakpointLocation) -> bool + Send>>;
 lazy_static::lazy_static! {
-    static ref CALLBACKS: Mutex<HashMap<BreakpointID, Box<dyn FnMut(&SBProcess, &SBThread, &SBBreakpointLocation) -> bool + Send>>> =
-        Mutex::new(HashMap::new());
+    static ref CALLBACKS: Mutex<HashMap<(u64, BreakpointID), BreakpointCallback>> = Mutex::new(HashMap::new());
 }
 
 impl SBBreakpoint {
@@ -30,6 +33,11 @@ impl SBBreakpoint {
             return self->GetNumResolvedLocations();
         }) as u32
     }
+    pub fn target(&self) -> SBTarget {
+        cpp!(unsafe [self as "SBBreakpoint*"] -> SBTarget as "SBTarget" {
+            return self->GetTarget();
+        })
+    }
     pub fn location_at_index(&self, index: u32) -> SBBreakpointLocation {
         cpp!(unsafe [self as "SBBreakpoint*", index as "uint32_t"] -> SBBreakpointLocation as "SBBreakpointLocation" {
             return self->GetLocationAtIndex(index);
@@ -87,18 +95,16 @@ impl SBBreakpoint {
             thread: *const SBThread,
             location: *const SBBreakpointLocation,
         ) -> bool {
-            let bp_id = (*location).breakpoint().id();
-            let mut callbacks = CALLBACKS.lock().unwrap();
-            if let Some(callback) = callbacks.get_mut(&bp_id) {
-                callback(&*process, &*thread, &*location)
-            } else {
-                false
+            let key = (*location).breakpoint().callback_key();
+            let callback = CALLBACKS.lock().unwrap().get(&key).cloned();
+            match callback {
+                Some(callback) => (callback.lock().unwrap())(&*process, &*thread, &*location),
+                None => false,
             }
         }
 
-        let bp_id = self.id();
         let mut callbacks = CALLBACKS.lock().unwrap();
-        callbacks.insert(bp_id, Box::new(callback));
+        callbacks.insert(self.callback_key(), Arc::new(Mutex::new(callback)));
 
         let cb = callback_thunk as *const c_void;
         cpp!(unsafe [self as "SBBreakpoint*", cb as "SBBreakpointHitCallback"] {
@@ -110,11 +116,16 @@ impl SBBreakpoint {
             self->SetCallback(nullptr, nullptr);
         });
         let mut callbacks = CALLBACKS.lock().unwrap();
-        callbacks.remove(&self.id());
+        callbacks.remove(&self.callback_key());
     }
-    pub fn clear_all_callbacks() {
+    /// Clears callbacks of all breakpoints belonging to the specified debugger.
+    pub fn clear_all_callbacks(debugger: &SBDebugger) {
+        let debugger_id = debugger.id();
         let mut callbacks = CALLBACKS.lock().unwrap();
-        callbacks.clear();
+        callbacks.retain(|(id, _), _| *id != debugger_id);
+    }
+    fn callback_key(&self) -> (u64, BreakpointID) {
+        (self.target().debugger().id(), self.id())
     }
 }
 
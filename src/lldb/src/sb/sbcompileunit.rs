@@ -23,6 +23,19 @@ impl SBCompileUnit {
     pub fn line_entries<'a>(&'a self) -> impl Iterator<Item = SBLineEntry> + 'a {
         SBIterator::new(self.num_line_entries(), move |index| self.line_entry_at_index(index))
     }
+    pub fn num_support_files(&self) -> u32 {
+        cpp!(unsafe [self as "SBCompileUnit*"] -> u32 as "uint32_t" {
+            return self->GetNumSupportFiles();
+        })
+    }
+    pub fn support_file_at_index(&self, index: u32) -> SBFileSpec {
+        cpp!(unsafe [self as "SBCompileUnit*", index as "uint32_t"] -> SBFileSpec as "SBFileSpec" {
+            return self->GetSupportFileAtIndex(index);
+        })
+    }
+    pub fn support_files<'a>(&'a self) -> impl Iterator<Item = SBFileSpec> + 'a {
+        SBIterator::new(self.num_support_files(), move |index| self.support_file_at_index(index))
+    }
     pub fn language(&self) -> LanguageType {
         cpp!(unsafe [self as "SBCompileUnit*"] -> u32 as "uint32_t" {
             return self->GetLanguage();
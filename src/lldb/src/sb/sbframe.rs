@@ -109,6 +109,14 @@ impl SBFrame {
             return self->GetRegisters();
         })
     }
+    pub fn find_register(&self, name: &str) -> Option<SBValue> {
+        with_cstr(name, |name| {
+            cpp!(unsafe [self as "SBFrame*", name as "const char*"] -> SBValue as "SBValue" {
+                return self->FindRegister(name);
+            })
+        })
+        .check()
+    }
     pub fn pc(&self) -> Address {
         cpp!(unsafe [self as "SBFrame*"] -> Address as "addr_t" {
             return self->GetPC();
@@ -129,6 +137,12 @@ impl SBFrame {
             return self->GetFP();
         })
     }
+    pub fn guess_language(&self) -> LanguageType {
+        cpp!(unsafe [self as "SBFrame*"] -> u32 as "uint32_t" {
+            return self->GuessLanguage();
+        })
+        .into()
+    }
 }
 
 impl PartialEq for SBFrame {
@@ -56,6 +56,16 @@ impl SBProcess {
             return self->GetExitStatus();
         })
     }
+    pub fn exit_description(&self) -> Option<&str> {
+        let ptr = cpp!(unsafe [self as "SBProcess*"] -> *const c_char as "const char*" {
+            return self->GetExitDescription();
+        });
+        if ptr.is_null() {
+            None
+        } else {
+            unsafe { Some(get_str(ptr)) }
+        }
+    }
     pub fn selected_thread(&self) -> SBThread {
         cpp!(unsafe [self as "SBProcess*"] -> SBThread as "SBThread" {
             return self->GetSelectedThread();
@@ -44,10 +44,12 @@ impl SBSymbol {
             return self->GetSize();
         })
     }
-    pub fn instructions(&self, target: &SBTarget) -> SBInstructionList {
+    pub fn instructions(&self, target: &SBTarget, flavor: Option<&str>) -> SBInstructionList {
         let target = target.clone();
-        cpp!(unsafe [self as "SBSymbol*", target as "SBTarget"] -> SBInstructionList as "SBInstructionList" {
-            return self->GetInstructions(target);
+        with_opt_cstr(flavor, |flavor| {
+            cpp!(unsafe [self as "SBSymbol*", target as "SBTarget", flavor as "const char*"] -> SBInstructionList as "SBInstructionList" {
+                return self->GetInstructions(target, flavor);
+            })
         })
     }
     pub fn get_description(&self, description: &mut SBStream) -> bool {
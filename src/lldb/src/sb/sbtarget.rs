@@ -187,6 +187,14 @@ impl SBTarget {
             return self->GetBasicType(basic_type);
         })
     }
+    pub fn find_first_type(&self, name: &str) -> Option<SBType> {
+        with_cstr(name, |name| {
+            cpp!(unsafe [self as "SBTarget*", name as "const char*"] -> SBType as "SBType" {
+                return self->FindFirstType(name);
+            })
+        })
+        .check()
+    }
     pub fn create_value_from_data(&self, name: &str, data: &SBData, ty: &SBType) -> SBValue {
         with_cstr(name, |name| {
             cpp!(unsafe [self as "SBTarget*", name as "const char*", data as "SBData*", ty as "SBType*"] -> SBValue as "SBValue" {
@@ -294,6 +302,19 @@ impl SBTarget {
     pub fn modules<'a>(&'a self) -> impl Iterator<Item = SBModule> + 'a {
         SBIterator::new(self.num_modules(), move |index| self.module_at_index(index))
     }
+    pub fn set_module_load_address(&self, module: &SBModule, slide: i64) -> Result<(), SBError> {
+        let error = cpp!(unsafe [self as "SBTarget*", module as "SBModule*", slide as "int64_t"]
+                               -> SBError as "SBError" {
+            return self->SetModuleLoadAddress(*module, slide);
+        });
+        error.into_result()
+    }
+    pub fn clear_module_load_address(&self, module: &SBModule) -> Result<(), SBError> {
+        let error = cpp!(unsafe [self as "SBTarget*", module as "SBModule*"] -> SBError as "SBError" {
+            return self->ClearModuleLoadAddress(*module);
+        });
+        error.into_result()
+    }
     pub fn broadcaster(&self) -> SBBroadcaster {
         cpp!(unsafe [self as "SBTarget*"] -> SBBroadcaster as "SBBroadcaster" {
             return self->GetBroadcaster();
@@ -31,6 +31,16 @@ impl SBThread {
             unsafe { Some(get_str(ptr)) }
         }
     }
+    pub fn queue_name(&self) -> Option<&str> {
+        let ptr = cpp!(unsafe [self as "SBThread*"] -> *const c_char as "const char*" {
+            return self->GetQueueName();
+        });
+        if ptr.is_null() {
+            None
+        } else {
+            unsafe { Some(get_str(ptr)) }
+        }
+    }
     pub fn process(&self) -> SBProcess {
         cpp!(unsafe [self as "SBThread*"] -> SBProcess as "SBProcess" {
             return self->GetProcess();
@@ -57,6 +67,12 @@ impl SBThread {
         })
         .check()
     }
+    pub fn current_exception(&self) -> Option<SBValue> {
+        cpp!(unsafe [self as "SBThread*"] -> SBValue as "SBValue" {
+            return self->GetCurrentException();
+        })
+        .check()
+    }
     pub fn stop_reason_data_count(&self) -> usize {
         cpp!(unsafe [self as "SBThread*"] -> usize as "size_t" {
             return self->GetStopReasonDataCount();
@@ -101,6 +117,18 @@ impl SBThread {
     pub fn frames<'a>(&'a self) -> impl Iterator<Item = SBFrame> + 'a {
         SBIterator::new(self.num_frames(), move |index| self.frame_at_index(index))
     }
+    pub fn suspend(&self) -> Result<(), SBError> {
+        let mut error = SBError::new();
+        cpp!(unsafe [self as "SBThread*", mut error as "SBError"]  {
+            self->Suspend(error);
+        });
+        error.into_result()
+    }
+    pub fn is_suspended(&self) -> bool {
+        cpp!(unsafe [self as "SBThread*"] -> bool as "bool" {
+            return self->IsSuspended();
+        })
+    }
     pub fn resume(&self) -> Result<(), SBError> {
         let mut error = SBError::new();
         cpp!(unsafe [self as "SBThread*", mut error as "SBError"]  {
@@ -152,6 +180,13 @@ impl SBThread {
         });
         error.into_result()
     }
+    pub fn step_out_of_frame(&self, frame: &SBFrame) -> Result<(), SBError> {
+        let mut error = SBError::new();
+        cpp!(unsafe [self as "SBThread*", frame as "SBFrame*", mut error as "SBError"] {
+            self->StepOutOfFrame(*frame, error);
+        });
+        error.into_result()
+    }
     pub fn step_instruction(&self, step_over: bool) -> Result<(), SBError> {
         let mut error = SBError::new();
         cpp!(unsafe [self as "SBThread*", step_over as "bool", mut error as "SBError"] {
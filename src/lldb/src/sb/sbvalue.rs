@@ -182,11 +182,24 @@ impl SBValue {
             return self->Dereference();
         })
     }
+    pub fn address_of(&self) -> SBValue {
+        cpp!(unsafe [self as "SBValue*"] -> SBValue as "SBValue" {
+            return self->AddressOf();
+        })
+    }
     pub fn cast(&self, ty: &SBType) -> SBValue {
         cpp!(unsafe [self as "SBValue*", ty as "SBType*"] -> SBValue as "SBValue" {
             return self->Cast(*ty);
         })
     }
+    // Evaluates expression in the context of this value, so that its members may be referenced directly.
+    pub fn evaluate_expression(&self, expr: &str) -> SBValue {
+        with_cstr(expr, |expr| {
+            cpp!(unsafe [self as "SBValue*", expr as "const char*"] -> SBValue as "SBValue" {
+                return self->EvaluateExpression(expr);
+            })
+        })
+    }
     pub fn create_child_at_offset(&self, name: &str, offset: u32, ty: &SBType) -> SBValue {
         with_cstr(name, |name| {
             cpp!(unsafe [self as "SBValue*", name as "const char*",
@@ -228,6 +241,14 @@ impl SBValue {
     pub fn children<'a>(&'a self) -> impl Iterator<Item = SBValue> + 'a {
         SBIterator::new(self.num_children(), move |index| self.child_at_index(index))
     }
+    // Resolves a path relative to this value, such as `.member` or `[5]` (the latter works with synthetic children too).
+    pub fn value_for_expression_path(&self, path: &str) -> SBValue {
+        with_cstr(path, |path| {
+            cpp!(unsafe [self as "SBValue*", path as "const char*"] -> SBValue as "SBValue" {
+                return self->GetValueForExpressionPath(path);
+            })
+        })
+    }
     pub fn get_expression_path(&self, path: &mut SBStream) -> bool {
         cpp!(unsafe [self as "SBValue*", path as "SBStream*"] -> bool as "bool" {
             return self->GetExpressionPath(*path);
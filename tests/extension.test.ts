@@ -46,32 +46,52 @@ suite('Extension Tests', () => {
     test('RPC launch', async () => {
         let rpcServer = new RpcLaunchServer({ token: 'secret' });
         let addrinfo = await rpcServer.listen({ host: '127.0.0.1', port: 0 }) as AddressInfo;
-
-        let ext = vscode.extensions.getExtension('vadimcn.vscode-lldb');
-        let launcher = path.join(ext.extensionPath, 'bin', 'codelldb-launch');
-        let proc = cp.spawn(launcher, [
+        let code = await runLauncher([
             `--connect=${addrinfo.address}:${addrinfo.port}`,
             '--config={ token: secret }',
             'debuggee/debuggee',
-        ], { cwd: ext.extensionPath, stdio: 'pipe', env: { ...process.env, RUST_LOG: 'debug' }});
-        proc.stdout.on('data', buf => console.log(buf.toString()));
-        proc.stderr.on('data', buf => console.error(buf.toString()));
+        ]);
+        assert.equal(code, 0);
+        rpcServer.close();
+    });
 
-        await new Promise<void>((resolve, reject) => {
-            proc.on('error', err => reject(err));
-            proc.on('exit', (code, signal) => {
-                if (code == 0) {
-                    resolve();
-                } else {
-                    reject(Error(`Launcher exited with code: ${code}, signal: ${signal}`));
-                }
-            });
-        });
+    test('RPC launch and wait, with waitEndOfSession disabled', async () => {
+        let rpcServer = new RpcLaunchServer({ token: 'secret' });
+        let addrinfo = await rpcServer.listen({ host: '127.0.0.1', port: 0 }) as AddressInfo;
+        let code = await runLauncher([
+            `--connect=${addrinfo.address}:${addrinfo.port}`,
+            '--config={ token: secret, waitEndOfSession: false }',
+            '--wait',
+            'debuggee/debuggee',
+        ]);
+        // Without a test case, the debuggee exits with -1.
+        assert.equal(code, 255);
         rpcServer.close();
     });
 });
 
 
+// Runs codelldb-launch and returns its exit code.
+async function runLauncher(args: string[]): Promise<number> {
+    let ext = vscode.extensions.getExtension('vadimcn.vscode-lldb');
+    let launcher = path.join(ext.extensionPath, 'bin', 'codelldb-launch');
+    let proc = cp.spawn(launcher, args,
+        { cwd: ext.extensionPath, stdio: 'pipe', env: { ...process.env, RUST_LOG: 'debug' } });
+    proc.stdout.on('data', buf => console.log(buf.toString()));
+    proc.stderr.on('data', buf => console.error(buf.toString()));
+
+    return new Promise<number>((resolve, reject) => {
+        proc.on('error', err => reject(err));
+        proc.on('exit', (code, signal) => {
+            if (code != null) {
+                resolve(code);
+            } else {
+                reject(Error(`Launcher exited with signal: ${signal}`));
+            }
+        });
+    });
+}
+
 class Logger implements vscode.DebugAdapterTrackerFactory, vscode.DebugAdapterTracker {
     lines: string[] = [];
     inspectOptions = { compact: true, breakLength: Infinity, colors: true };